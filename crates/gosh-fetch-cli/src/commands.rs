@@ -0,0 +1,1076 @@
+//! Subcommand implementations
+//!
+//! Every command is handed the shared `gosh-fetch.db`. Commands that only
+//! look at downloads read it; those that change them open a `DownloadService`
+//! backed by the same `engine.db` session as the desktop app, and shut its
+//! engine down before returning. When `gosh-fetchd` owns that session they
+//! send their commands to it instead, and when the desktop app runs its own
+//! engine they refuse rather than start a second one.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use gosh_fetch_core::backup::{self, Bundle, ImportMode};
+use gosh_fetch_core::checksum;
+use gosh_fetch_core::duplicate::{self, Verdict};
+use gosh_fetch_core::input_file;
+use gosh_fetch_core::metalink;
+use gosh_fetch_core::{
+    calculate_progress, finish_download, format_bytes, format_eta, format_speed, record_ui_message,
+    remove_download, DaemonClient, Database, Download, DownloadOptions, DownloadService,
+    DownloadState, DownloadsDb, EngineAdapter, EngineCommand, Error, Result, Settings, SettingsDb,
+    UiMessage, WatchFolder, WatchFoldersDb,
+};
+use gosh_fetch_core::{dbus, ipc};
+
+/// Interval between progress refreshes while waiting on downloads
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Database and engine handles shared by the engine-backed commands
+struct Session {
+    db: Database,
+    adapter: EngineAdapter,
+}
+
+impl Session {
+    async fn open(db: &Database) -> Result<Self> {
+        let settings = SettingsDb::load(db).unwrap_or_else(|e| {
+            log::warn!("Failed to load settings, using defaults: {}", e);
+            Settings::default()
+        });
+        let service = DownloadService::new_async(&settings, db.clone()).await?;
+        Ok(Self {
+            db: db.clone(),
+            adapter: service.adapter(),
+        })
+    }
+
+    /// Stop the engine so it saves its session and leaves no transfers
+    /// running when the process exits
+    async fn close(self) {
+        if let Err(e) = self.adapter.shutdown().await {
+            log::warn!("Failed to shut the engine down: {}", e);
+        }
+    }
+
+    /// Look up a freshly added download and record it in the app database
+    fn record_added(&self, gid: &str, options: Option<&DownloadOptions>, json: bool) {
+        if let Some(options) = options {
+            if let Err(e) = DownloadsDb::save_options(&self.db, gid, options) {
+                log::error!("Failed to save download options: {}", e);
+            }
+        }
+
+        match self.adapter.get_status(gid) {
+            Some(download) => {
+                record_ui_message(&self.db, &UiMessage::DownloadAdded(download.clone()));
+                if json {
+                    print_json(&download);
+                } else if let Some(outcome) = download.conflict {
+                    println!("Added {} ({}, {})", download.gid, download.name, outcome);
+                } else {
+                    println!("Added {} ({})", download.gid, download.name);
+                }
+            }
+            None => println!("Added {}", gid),
+        }
+    }
+
+    /// Record a download's progress, as the service's owner would
+    fn record_update(&self, download: &Download) {
+        let msg = UiMessage::DownloadUpdated(download.gid.clone(), download.clone());
+        record_ui_message(&self.db, &msg);
+    }
+
+    /// Record the state of every download the engine has, after a change
+    /// that touched all of them
+    fn record_all(&self) {
+        for download in self.adapter.get_all() {
+            self.record_update(&download);
+        }
+    }
+
+    /// Run a completed download through the same finish path as the
+    /// service, recording and printing what it reports
+    ///
+    /// False on a checksum mismatch, which also skips its completion
+    /// actions. A failed action is reported without failing the download.
+    async fn finish(&self, download: &Download, json: bool) -> bool {
+        let (sender, receiver) = async_channel::unbounded();
+        let download = finish_download(self.db.clone(), sender, download.clone()).await;
+        while let Ok(msg) = receiver.try_recv() {
+            record_ui_message(&self.db, &msg);
+            match msg {
+                UiMessage::Error(message) => eprintln!("{}", message),
+                UiMessage::ExtractionFailed(_, message) => {
+                    eprintln!("Could not extract {}: {}", download.name, message)
+                }
+                UiMessage::ExtractionFinished(_, message) if !json => {
+                    println!("  extract: {}", message)
+                }
+                _ => {}
+            }
+        }
+        download
+            .checksum
+            .is_none_or(|checksum| checksum.matched != Some(false))
+    }
+
+    /// Apply an add command's `on_duplicate` action through the same check
+    /// as the service; an error means it must not be added
+    async fn may_add(&self, cmd: &EngineCommand) -> Result<()> {
+        match duplicate::check(&self.adapter, &self.db, cmd).await? {
+            Verdict::Add => Ok(()),
+            Verdict::Report(existing) => Err(Error::InvalidInput(format!(
+                "already added as {} ({})",
+                existing.gid, existing.state
+            ))),
+            Verdict::Restart(existing) => {
+                record_ui_message(&self.db, &UiMessage::DownloadRemoved(existing.gid));
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Connection to a running `gosh-fetchd`, which adds, records and finishes
+/// downloads on its own engine
+struct Daemon {
+    commands: async_channel::Sender<EngineCommand>,
+    messages: async_channel::Receiver<UiMessage>,
+}
+
+impl Daemon {
+    async fn connect(socket: &Path) -> Result<Self> {
+        let (ui_sender, messages) = async_channel::unbounded();
+        let (commands, cmd_receiver) = async_channel::unbounded();
+        DaemonClient::connect(socket)?.spawn(ui_sender, cmd_receiver);
+        let daemon = Self { commands, messages };
+
+        // The daemon greets each client before it reads its commands
+        match daemon.recv().await? {
+            UiMessage::Error(message) => Err(Error::Channel(message)),
+            _ => Ok(daemon),
+        }
+    }
+
+    async fn send(&self, cmd: EngineCommand) -> Result<()> {
+        self.commands
+            .send(cmd)
+            .await
+            .map_err(|e| Error::Channel(e.to_string()))
+    }
+
+    async fn recv(&self) -> Result<UiMessage> {
+        self.messages
+            .recv()
+            .await
+            .map_err(|_| Error::Channel("Lost connection to gosh-fetchd".to_string()))
+    }
+
+    /// Hang up once every command sent was written to the daemon
+    async fn close(self) {
+        drop(self.commands);
+        while self.messages.recv().await.is_ok() {}
+    }
+}
+
+/// Socket of the `gosh-fetchd` that owns the engine session, if one is
+/// running
+///
+/// The desktop app running its own engine cannot take commands from here,
+/// so that is an error rather than a reason to start a second engine.
+async fn daemon_socket() -> Result<Option<PathBuf>> {
+    let socket = ipc::socket_path();
+    if ipc::daemon_available(&socket) {
+        return Ok(Some(socket));
+    }
+    if dbus::service_running().await {
+        return Err(Error::Engine(format!(
+            "Gosh-Fetch is running its own engine and no gosh-fetchd is listening on {}; \
+             close the app, or start gosh-fetchd so both can share it",
+            socket.display()
+        )));
+    }
+    Ok(None)
+}
+
+/// Send `commands` to the daemon listening at `socket`, in order
+async fn send_to_daemon(socket: &Path, commands: Vec<EngineCommand>) -> Result<()> {
+    let daemon = Daemon::connect(socket).await?;
+    for cmd in commands {
+        daemon.send(cmd).await?;
+    }
+    daemon.close().await;
+    Ok(())
+}
+
+/// Run `f` with an engine session, shutting the engine down afterwards
+/// whether or not it succeeded
+///
+/// When `gosh-fetchd` is running, `commands` are sent to it instead and it
+/// records their outcome; `f` does not run.
+async fn with_session(
+    db: &Database,
+    commands: Vec<EngineCommand>,
+    f: impl AsyncFnOnce(&Session) -> Result<()>,
+) -> Result<()> {
+    if let Some(socket) = daemon_socket().await? {
+        send_to_daemon(&socket, commands).await?;
+        eprintln!("Sent to gosh-fetchd");
+        return Ok(());
+    }
+
+    let session = Session::open(db).await?;
+    let result = f(&session).await;
+    session.close().await;
+    result
+}
+
+pub async fn add(
+    db: &Database,
+    urls: Vec<String>,
+    options: Option<DownloadOptions>,
+    wait: bool,
+    json: bool,
+) -> Result<()> {
+    let commands = vec![EngineCommand::AddDownloads {
+        urls: urls.clone(),
+        options: options.clone(),
+    }];
+    with_session(db, commands, async |session| {
+        let mut batch = Vec::new();
+        let mut failures = 0;
+
+        for url in urls {
+            let cmd = EngineCommand::AddDownload {
+                url: url.clone(),
+                options: options.clone(),
+            };
+            match session.may_add(&cmd).await {
+                Ok(()) => batch.push(url),
+                Err(e) => {
+                    eprintln!("Failed to add {}: {}", url, e);
+                    failures += 1;
+                }
+            }
+        }
+
+        // The URLs are resolved in parallel and added in order
+        let results = session
+            .adapter
+            .add_urls(batch.clone(), options.clone())
+            .await;
+        let mut gids = Vec::new();
+        for (url, result) in batch.iter().zip(results) {
+            match result {
+                Ok(gid) => {
+                    session.record_added(&gid, options.as_ref(), json);
+                    gids.push(gid);
+                }
+                Err(e) => {
+                    eprintln!("Failed to add {}: {}", url, Error::from(e));
+                    failures += 1;
+                }
+            }
+        }
+
+        if wait {
+            wait_for(session, gids, json).await?;
+        }
+
+        if failures > 0 {
+            return Err(Error::InvalidInput(format!(
+                "{} URL(s) could not be added",
+                failures
+            )));
+        }
+        Ok(())
+    })
+    .await
+}
+
+pub async fn add_magnet(
+    db: &Database,
+    uri: String,
+    options: Option<DownloadOptions>,
+    wait: bool,
+    json: bool,
+) -> Result<()> {
+    if !uri.starts_with("magnet:") {
+        return Err(Error::InvalidInput("Invalid magnet link".to_string()));
+    }
+
+    let cmd = EngineCommand::AddMagnet {
+        uri: uri.clone(),
+        options: options.clone(),
+    };
+    with_session(db, vec![cmd.clone()], async |session| {
+        session.may_add(&cmd).await?;
+        let gid = session.adapter.add_magnet(&uri, options.clone()).await?;
+        session.record_added(&gid, options.as_ref(), json);
+
+        if wait {
+            wait_for(session, vec![gid], json).await?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+pub async fn add_torrent(
+    db: &Database,
+    path: String,
+    options: Option<DownloadOptions>,
+    wait: bool,
+    json: bool,
+) -> Result<()> {
+    let data = std::fs::read(&path)?;
+
+    let cmd = EngineCommand::AddTorrent {
+        data: data.clone(),
+        options: options.clone(),
+    };
+    with_session(db, vec![cmd.clone()], async |session| {
+        session.may_add(&cmd).await?;
+        let gid = session.adapter.add_torrent(&data, options.clone()).await?;
+        DownloadsDb::save_torrent(&session.db, &gid, &data)?;
+        session.record_added(&gid, options.as_ref(), json);
+
+        if wait {
+            wait_for(session, vec![gid], json).await?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+pub async fn add_metalink(
+    db: &Database,
+    source: String,
+    options: Option<DownloadOptions>,
+    wait: bool,
+    json: bool,
+) -> Result<()> {
+    let data = if source.starts_with("http://") || source.starts_with("https://") {
+        metalink::fetch(&source).await?
+    } else {
+        std::fs::read(&source)?
+    };
+
+    let commands = vec![EngineCommand::AddMetalink {
+        data: data.clone(),
+        options: options.clone(),
+    }];
+    with_session(db, commands, async |session| {
+        let added = session.adapter.add_metalink(&data, options).await?;
+        let mut gids = Vec::new();
+        for (gid, options) in added {
+            session.record_added(&gid, Some(&options), json);
+            gids.push(gid);
+        }
+
+        if wait {
+            wait_for(session, gids, json).await?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+pub async fn add_list(db: &Database, path: String, wait: bool, json: bool) -> Result<()> {
+    let entries = input_file::parse(&std::fs::read_to_string(&path)?)?;
+
+    let commands = entries.iter().cloned().map(|e| e.into_command()).collect();
+    with_session(db, commands, async |session| {
+        let mut gids = Vec::new();
+        let mut failures = 0;
+
+        for entry in entries {
+            // `into_command` only builds adds of URLs and magnet links
+            let cmd = entry.into_command();
+            let checked = session.may_add(&cmd).await;
+            let (uri, result, options) = match cmd {
+                EngineCommand::AddMagnet { uri, options } => {
+                    let result = match checked {
+                        Ok(()) => session
+                            .adapter
+                            .add_magnet(&uri, options.clone())
+                            .await
+                            .map_err(Error::from),
+                        Err(e) => Err(e),
+                    };
+                    (uri, result, options)
+                }
+                EngineCommand::AddDownload { url, options } => {
+                    let result = match checked {
+                        Ok(()) => session
+                            .adapter
+                            .add_download(url.clone(), options.clone())
+                            .await
+                            .map_err(Error::from),
+                        Err(e) => Err(e),
+                    };
+                    (url, result, options)
+                }
+                _ => continue,
+            };
+
+            match result {
+                Ok(gid) => {
+                    session.record_added(&gid, options.as_ref(), json);
+                    gids.push(gid);
+                }
+                Err(e) => {
+                    eprintln!("Failed to add {}: {}", uri, e);
+                    failures += 1;
+                }
+            }
+        }
+
+        if wait {
+            wait_for(session, gids, json).await?;
+        }
+
+        if failures > 0 {
+            return Err(Error::InvalidInput(format!(
+                "{} download(s) could not be added",
+                failures
+            )));
+        }
+        Ok(())
+    })
+    .await
+}
+
+pub fn export_list(db: &Database, output: Option<String>) -> Result<()> {
+    let entries = input_file::queue(db)?;
+    let text = input_file::write(&entries);
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, text)?;
+            println!("Wrote {} download(s) to {}", entries.len(), path);
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+pub fn list(db: &Database, category: Option<String>, json: bool) -> Result<()> {
+    let mut downloads = DownloadsDb::get_all(db)?;
+    if let Some(category) = category {
+        downloads.retain(|d| {
+            d.category
+                .as_deref()
+                .is_some_and(|c| c.eq_ignore_ascii_case(&category))
+        });
+    }
+
+    if json {
+        print_json(&downloads);
+        return Ok(());
+    }
+
+    if downloads.is_empty() {
+        println!("No downloads");
+        return Ok(());
+    }
+
+    println!(
+        "{:<36}  {:<8}  {:>6}  {:>12}  NAME",
+        "GID", "STATUS", "DONE", "SPEED"
+    );
+    for download in &downloads {
+        println!(
+            "{:<36}  {:<8}  {:>5.1}%  {:>12}  {}",
+            download.gid,
+            download.status.to_string(),
+            calculate_progress(download.completed_size, download.total_size) * 100.0,
+            format_speed(download.download_speed),
+            download.name
+        );
+    }
+    Ok(())
+}
+
+pub fn status(db: &Database, gid: String, json: bool) -> Result<()> {
+    let download = DownloadsDb::get_by_gid(db, &gid)?
+        .ok_or_else(|| Error::NotFound(format!("download {}", gid)))?;
+
+    if json {
+        print_json(&download);
+        return Ok(());
+    }
+
+    println!("GID:         {}", download.gid);
+    println!("Name:        {}", download.name);
+    println!("Type:        {}", download.download_type);
+    println!("Status:      {}", download.status);
+    if let Some(url) = &download.url {
+        println!("URL:         {}", url);
+    }
+    if let Some(magnet) = &download.magnet_uri {
+        println!("Magnet:      {}", magnet);
+    }
+    if let Some(hash) = &download.info_hash {
+        println!("Info hash:   {}", hash);
+    }
+    println!("Save path:   {}", download.save_path);
+    println!(
+        "Progress:    {} / {} ({:.1}%)",
+        format_bytes(download.completed_size),
+        format_bytes(download.total_size),
+        calculate_progress(download.completed_size, download.total_size) * 100.0
+    );
+    println!(
+        "Speed:       {} down, {} up",
+        format_speed(download.download_speed),
+        format_speed(download.upload_speed)
+    );
+    println!(
+        "ETA:         {}",
+        format_eta(
+            download.total_size.saturating_sub(download.completed_size),
+            download.download_speed
+        )
+    );
+    println!("Connections: {}", download.connections);
+    println!("Created:     {}", download.created_at);
+    if let Some(completed_at) = &download.completed_at {
+        println!("Completed:   {}", completed_at);
+    }
+    if let Some(error) = &download.error_message {
+        println!("Error:       {}", error);
+    }
+    Ok(())
+}
+
+pub async fn pause(db: &Database, gid: Option<String>, all: bool) -> Result<()> {
+    let cmd = if all {
+        EngineCommand::PauseAll
+    } else {
+        EngineCommand::Pause(gid.clone().unwrap_or_default())
+    };
+    with_session(db, vec![cmd], async |session| {
+        if all {
+            session.adapter.pause_all().await?;
+            session.record_all();
+            return Ok(());
+        }
+
+        let gid = gid.unwrap_or_default();
+        session.adapter.pause(&gid).await?;
+        DownloadsDb::update_status(&session.db, &gid, DownloadState::Paused)?;
+        Ok(())
+    })
+    .await
+}
+
+pub async fn resume(db: &Database, gid: Option<String>, all: bool) -> Result<()> {
+    let cmd = if all {
+        EngineCommand::ResumeAll
+    } else {
+        EngineCommand::Resume(gid.clone().unwrap_or_default())
+    };
+    with_session(db, vec![cmd], async |session| {
+        if all {
+            session.adapter.resume_all().await?;
+            session.record_all();
+            return Ok(());
+        }
+
+        let gid = gid.unwrap_or_default();
+        session.adapter.resume(&gid).await?;
+        DownloadsDb::update_status(&session.db, &gid, DownloadState::Waiting)?;
+        Ok(())
+    })
+    .await
+}
+
+pub async fn remove(db: &Database, gid: String, delete_files: bool) -> Result<()> {
+    let cmd = EngineCommand::Remove {
+        gid: gid.clone(),
+        delete_files,
+    };
+    with_session(db, vec![cmd], async |session| {
+        let known = DownloadsDb::get_by_gid(&session.db, &gid)?.is_some();
+
+        // Scheduled downloads are dropped before the engine sees them
+        match remove_download(&session.adapter, &session.db, &gid, delete_files).await {
+            Ok(()) => {}
+            // Entries that only exist in the history can still be removed
+            Err(Error::NotFound(_)) if known => {}
+            Err(e) => return Err(e),
+        }
+
+        DownloadsDb::delete(&session.db, &gid)?;
+        Ok(())
+    })
+    .await
+}
+
+pub fn history(db: &Database, limit: i64, clear: bool, json: bool) -> Result<()> {
+    if clear {
+        DownloadsDb::clear_history(db)?;
+        println!("History cleared");
+        return Ok(());
+    }
+
+    let downloads = DownloadsDb::get_completed(db, limit)?;
+    if json {
+        print_json(&downloads);
+        return Ok(());
+    }
+
+    if downloads.is_empty() {
+        println!("No completed downloads");
+        return Ok(());
+    }
+
+    for download in &downloads {
+        println!(
+            "{:<25}  {:>10}  {}  ({})",
+            download.completed_at.as_deref().unwrap_or("-"),
+            format_bytes(download.total_size),
+            download.name,
+            download.save_path
+        );
+        for entry in DownloadsDb::get_action_log(db, &download.gid)? {
+            let status = if entry.success { "ok" } else { "failed" };
+            println!("    {} ({}): {}", entry.action, status, entry.message);
+        }
+    }
+    Ok(())
+}
+
+pub async fn verify(db: &Database, gid: String, json: bool) -> Result<()> {
+    let download = DownloadsDb::get_by_gid(db, &gid)?
+        .ok_or_else(|| Error::NotFound(format!("Download not found: {}", gid)))?;
+    if download.status != DownloadState::Complete {
+        return Err(Error::InvalidInput(format!(
+            "{} has not finished downloading",
+            download.name
+        )));
+    }
+
+    let options = DownloadsDb::get_options(db, &gid)?;
+    let result = checksum::verify(&download, options.as_ref()).await?;
+    DownloadsDb::set_checksum_result(db, &gid, &result)?;
+
+    if json {
+        print_json(&result);
+    } else {
+        let outcome = match result.matched {
+            Some(true) => "OK",
+            Some(false) => "MISMATCH",
+            None => "no expected checksum",
+        };
+        println!(
+            "{}: {} {} ({})",
+            download.name, result.algorithm, result.digest, outcome
+        );
+    }
+
+    if result.matched == Some(false) {
+        return Err(Error::InvalidInput(format!(
+            "Checksum mismatch for {}",
+            download.name
+        )));
+    }
+    Ok(())
+}
+
+pub fn export(db: &Database, output: Option<String>, redact: bool) -> Result<()> {
+    let json = backup::export(db, redact)?.to_json()?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, json)?;
+            println!("Exported to {}", path);
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+pub fn import(db: &Database, path: String, replace: bool, json: bool) -> Result<()> {
+    let bundle = Bundle::from_json(&std::fs::read_to_string(&path)?)?;
+    let mode = if replace {
+        ImportMode::Replace
+    } else {
+        ImportMode::Merge
+    };
+    let summary = backup::import(db, &bundle, mode)?;
+
+    if json {
+        print_json(&summary);
+    } else {
+        println!(
+            "Imported {} settings, {} downloads, {} trackers, {} categories and {} feeds",
+            summary.settings,
+            summary.downloads,
+            summary.trackers,
+            summary.categories,
+            summary.feeds
+        );
+    }
+    Ok(())
+}
+
+pub fn watch_folders(db: &Database, json: bool) -> Result<()> {
+    let folders = WatchFoldersDb::list(db)?;
+    if json {
+        print_json(&folders);
+        return Ok(());
+    }
+
+    if folders.is_empty() {
+        println!("No watch folders");
+        return Ok(());
+    }
+
+    for folder in &folders {
+        let mut details = Vec::new();
+        if let Some(dir) = &folder.dir {
+            details.push(format!("saves to {}", dir));
+        }
+        if let Some(category) = &folder.category {
+            details.push(format!("category {}", category));
+        }
+        if folder.move_aside {
+            details.push("moves files aside".to_string());
+        }
+        if !folder.enabled {
+            details.push("disabled".to_string());
+        }
+        println!("{:<4}  {}  {}", folder.id, folder.path, details.join(", "));
+    }
+    Ok(())
+}
+
+pub fn add_watch_folder(
+    db: &Database,
+    path: String,
+    dir: Option<String>,
+    category: Option<String>,
+    move_aside: bool,
+) -> Result<()> {
+    // The daemon may run from another working directory
+    let path = std::env::current_dir()?
+        .join(path)
+        .to_string_lossy()
+        .to_string();
+    let existing = WatchFoldersDb::list(db)?
+        .into_iter()
+        .find(|folder| folder.path == path);
+
+    let folder = WatchFolder {
+        id: existing.map(|folder| folder.id).unwrap_or(0),
+        path,
+        enabled: true,
+        dir,
+        category,
+        move_aside,
+    };
+    WatchFoldersDb::save(db, &folder)?;
+    println!("Watching {}", folder.path);
+    Ok(())
+}
+
+pub fn remove_watch_folder(db: &Database, folder: String) -> Result<()> {
+    let path = std::env::current_dir()?.join(&folder);
+    let found = WatchFoldersDb::list(db)?
+        .into_iter()
+        .find(|f| f.id.to_string() == folder || Path::new(&f.path) == path)
+        .ok_or_else(|| Error::NotFound(format!("Watch folder not found: {}", folder)))?;
+
+    WatchFoldersDb::delete(db, found.id)?;
+    println!("Stopped watching {}", found.path);
+    Ok(())
+}
+
+pub async fn watch(db: &Database, forever: bool, json: bool) -> Result<()> {
+    if let Some(socket) = daemon_socket().await? {
+        return watch_daemon(&socket, forever, json).await;
+    }
+
+    with_session(db, Vec::new(), async |session| {
+        loop {
+            let downloads = session.adapter.get_all();
+            let mut pending = 0;
+
+            for download in &downloads {
+                // Completed downloads are only recorded once; their completion
+                // actions may have moved them since
+                let newly_completed = download.status == DownloadState::Complete
+                    && !is_recorded_complete(&session.db, &download.gid);
+                if download.status == DownloadState::Complete && !newly_completed {
+                    continue;
+                }
+
+                if newly_completed {
+                    session.finish(download, json).await;
+                } else {
+                    session.record_update(download);
+                }
+                if is_pending(download) {
+                    pending += 1;
+                    print_progress(download, json);
+                }
+            }
+
+            if pending == 0 && !forever {
+                return Ok(());
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = tokio::signal::ctrl_c() => return Ok(()),
+            }
+        }
+    })
+    .await
+}
+
+/// Follow the downloads of a running `gosh-fetchd`, which records and
+/// finishes them itself
+async fn watch_daemon(socket: &Path, forever: bool, json: bool) -> Result<()> {
+    let daemon = Daemon::connect(socket).await?;
+    loop {
+        daemon.send(EngineCommand::RefreshDownloads).await?;
+        let downloads = loop {
+            if let UiMessage::DownloadsList(downloads) = daemon.recv().await? {
+                break downloads;
+            }
+        };
+
+        let mut pending = 0;
+        for download in downloads.iter().filter(|d| is_pending(d)) {
+            pending += 1;
+            print_progress(download, json);
+        }
+        if pending == 0 && !forever {
+            break;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    daemon.close().await;
+    Ok(())
+}
+
+/// Poll the given downloads until each one has completed or failed
+async fn wait_for(session: &Session, gids: Vec<String>, json: bool) -> Result<()> {
+    let mut remaining: HashSet<String> = gids.into_iter().collect();
+    let mut failed = 0;
+
+    while !remaining.is_empty() {
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("Interrupted; downloads continue on the next `watch`");
+                return Ok(());
+            }
+        }
+
+        for gid in remaining.clone() {
+            let Some(download) = session.adapter.get_status(&gid) else {
+                remaining.remove(&gid);
+                continue;
+            };
+
+            if download.status != DownloadState::Complete {
+                session.record_update(&download);
+            }
+
+            match download.status {
+                DownloadState::Complete => {
+                    remaining.remove(&gid);
+                    if !json {
+                        println!("Completed: {} ({})", download.name, download.save_path);
+                    }
+                    if !session.finish(&download, json).await {
+                        failed += 1;
+                    }
+                }
+                DownloadState::Error => {
+                    remaining.remove(&gid);
+                    failed += 1;
+                    if !json {
+                        eprintln!(
+                            "Failed: {}: {}",
+                            download.name,
+                            download.error_message.as_deref().unwrap_or("unknown error")
+                        );
+                    }
+                }
+                _ => {}
+            }
+            print_progress(&download, json);
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::Engine(format!("{} download(s) failed", failed)));
+    }
+    Ok(())
+}
+
+fn is_recorded_complete(db: &Database, gid: &str) -> bool {
+    DownloadsDb::get_by_gid(db, gid)
+        .ok()
+        .flatten()
+        .is_some_and(|d| d.status == DownloadState::Complete)
+}
+
+fn is_pending(download: &Download) -> bool {
+    matches!(
+        download.status,
+        DownloadState::Active | DownloadState::Waiting
+    )
+}
+
+fn print_progress(download: &Download, json: bool) {
+    if json {
+        print_json(download);
+        return;
+    }
+    if !is_pending(download) {
+        return;
+    }
+
+    println!(
+        "[{:>5.1}%] {} / {}  {}  ETA {}  {}",
+        calculate_progress(download.completed_size, download.total_size) * 100.0,
+        format_bytes(download.completed_size),
+        format_bytes(download.total_size),
+        format_speed(download.download_speed),
+        format_eta(
+            download.total_size.saturating_sub(download.completed_size),
+            download.download_speed
+        ),
+        download.name
+    );
+}
+
+fn print_json<T: serde::Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => log::error!("Failed to serialize output: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gosh_fetch_core::open_database;
+    use std::path::PathBuf;
+
+    /// A fresh database in its own folder under the temp dir
+    fn temp_db(name: &str) -> (Database, PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("gosh-fetch-cli-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        (open_database(&dir.join("gosh-fetch.db")).unwrap(), dir)
+    }
+
+    #[test]
+    fn test_read_only_commands_use_the_database() {
+        let (db, dir) = temp_db("read");
+        let download = Download {
+            gid: "abc".to_string(),
+            name: "distro.iso".to_string(),
+            status: DownloadState::Paused,
+            category: Some("ISOs".to_string()),
+            ..Default::default()
+        };
+        DownloadsDb::save(&db, &download).unwrap();
+
+        assert!(list(&db, Some("isos".to_string()), true).is_ok());
+        assert!(status(&db, "abc".to_string(), true).is_ok());
+        assert!(matches!(
+            status(&db, "missing".to_string(), true),
+            Err(Error::NotFound(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_history_is_cleared() {
+        let (db, dir) = temp_db("history");
+        let download = Download {
+            gid: "done".to_string(),
+            name: "distro.iso".to_string(),
+            status: DownloadState::Complete,
+            completed_at: Some("2026-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        DownloadsDb::save(&db, &download).unwrap();
+
+        history(&db, 10, false, true).unwrap();
+        assert_eq!(DownloadsDb::count_completed(&db).unwrap(), 1);
+        history(&db, 10, true, true).unwrap();
+        assert_eq!(DownloadsDb::count_completed(&db).unwrap(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_watch_folders_are_added_once_and_removed() {
+        let (db, dir) = temp_db("watch");
+        let folder = dir.join("drop").to_string_lossy().to_string();
+
+        add_watch_folder(&db, folder.clone(), None, None, false).unwrap();
+        add_watch_folder(&db, folder.clone(), None, Some("ISOs".to_string()), true).unwrap();
+        let folders = WatchFoldersDb::list(&db).unwrap();
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].category.as_deref(), Some("ISOs"));
+        assert!(folders[0].move_aside);
+
+        assert!(matches!(
+            remove_watch_folder(&db, "elsewhere".to_string()),
+            Err(Error::NotFound(_))
+        ));
+        remove_watch_folder(&db, folder).unwrap();
+        assert!(WatchFoldersDb::list(&db).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_commands_go_to_a_running_daemon() {
+        let dir =
+            std::env::temp_dir().join(format!("gosh-fetch-cli-daemon-{}", std::process::id()));
+        let socket = dir.join("gosh-fetchd.sock");
+        let listener = ipc::bind(&socket).unwrap();
+        let (cmd_sender, cmd_receiver) = async_channel::bounded(8);
+        let (events, _) = tokio::sync::broadcast::channel(8);
+        tokio::spawn(ipc::serve(listener, cmd_sender, events));
+
+        let commands = vec![
+            EngineCommand::Pause("abc".to_string()),
+            EngineCommand::Remove {
+                gid: "def".to_string(),
+                delete_files: true,
+            },
+        ];
+        send_to_daemon(&socket, commands).await.unwrap();
+
+        let timeout = Duration::from_secs(5);
+        let first = tokio::time::timeout(timeout, cmd_receiver.recv())
+            .await
+            .unwrap();
+        assert!(matches!(first, Ok(EngineCommand::Pause(gid)) if gid == "abc"));
+        let second = tokio::time::timeout(timeout, cmd_receiver.recv())
+            .await
+            .unwrap();
+        assert!(matches!(
+            second,
+            Ok(EngineCommand::Remove { gid, delete_files: true }) if gid == "def"
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
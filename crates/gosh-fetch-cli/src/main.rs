@@ -0,0 +1,489 @@
+mod commands;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use gosh_fetch_core::{
+    init_database, CompletionAction, ConflictPolicy, DownloadOptions, DuplicateAction,
+    ExtractTarget, Result,
+};
+
+/// Headless Gosh-Fetch client sharing the desktop app's database and engine session
+#[derive(Debug, Parser)]
+#[command(name = "gosh-fetch-cli", version, about)]
+struct Cli {
+    /// Print machine-readable JSON instead of tables
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Add one or more HTTP/HTTPS downloads
+    Add {
+        /// URLs to download
+        #[arg(required = true)]
+        urls: Vec<String>,
+        #[command(flatten)]
+        options: OptionArgs,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+    /// Add a magnet link
+    AddMagnet {
+        /// Magnet URI (magnet:?xt=urn:btih:...)
+        uri: String,
+        #[command(flatten)]
+        options: OptionArgs,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+    /// Add a .torrent file
+    AddTorrent {
+        /// Path to the .torrent file
+        path: String,
+        #[command(flatten)]
+        options: OptionArgs,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+    /// Add the files of a Metalink (.metalink or .meta4)
+    AddMetalink {
+        /// Path or URL of the metalink
+        source: String,
+        #[command(flatten)]
+        options: OptionArgs,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+    /// Add the downloads listed in an aria2 input file (`aria2c -i` format)
+    AddList {
+        /// Path to the input file
+        path: String,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+    /// Write unfinished downloads as an aria2 input file
+    ExportList {
+        /// File to write; the list is printed when omitted
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List recorded downloads
+    List {
+        /// Only show downloads filed under this category
+        #[arg(long)]
+        category: Option<String>,
+    },
+    /// Show the status of a single download
+    Status {
+        /// Download GID
+        gid: String,
+    },
+    /// Pause a download
+    Pause {
+        /// Download GID
+        #[arg(required_unless_present = "all")]
+        gid: Option<String>,
+        /// Pause all downloads
+        #[arg(long, conflicts_with = "gid")]
+        all: bool,
+    },
+    /// Resume a paused download
+    Resume {
+        /// Download GID
+        #[arg(required_unless_present = "all")]
+        gid: Option<String>,
+        /// Resume all downloads
+        #[arg(long, conflicts_with = "gid")]
+        all: bool,
+    },
+    /// Remove a download
+    Remove {
+        /// Download GID
+        gid: String,
+        /// Also delete downloaded files
+        #[arg(long)]
+        delete_files: bool,
+    },
+    /// Show completed download history
+    History {
+        /// Maximum number of entries
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+        /// Clear the history instead of printing it
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Hash a completed download again and record the result in the history
+    Verify {
+        /// Download GID
+        gid: String,
+    },
+    /// Write settings, history, trackers, categories and feeds to a JSON bundle
+    Export {
+        /// File to write; the bundle is printed when omitted
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Leave out proxy credentials, cookies, credential headers and URL passwords
+        #[arg(long)]
+        redact: bool,
+    },
+    /// Load a bundle written by `export`
+    Import {
+        /// Path to the bundle
+        path: String,
+        /// Replace settings, trackers, categories, feeds and history instead of merging
+        #[arg(long)]
+        replace: bool,
+    },
+    /// List the folders watched for dropped .torrent, .metalink and URL list files
+    WatchFolders,
+    /// Watch a folder for dropped .torrent, .metalink, .magnet and .txt URL
+    /// list files; the daemon or desktop app picks it up within a minute
+    AddWatchFolder {
+        /// Folder to watch
+        path: String,
+        /// Directory to save its downloads in
+        #[arg(short, long)]
+        dir: Option<String>,
+        /// File its downloads under this category
+        #[arg(long)]
+        category: Option<String>,
+        /// Move processed files into an `added` subfolder instead of renaming
+        /// them to `.added`
+        #[arg(long)]
+        move_aside: bool,
+    },
+    /// Stop watching a folder
+    RemoveWatchFolder {
+        /// Folder path or ID, as listed by `watch-folders`
+        folder: String,
+    },
+    /// Run the engine and show progress until all downloads finish
+    Watch {
+        /// Keep running after all downloads have finished
+        #[arg(long)]
+        forever: bool,
+    },
+}
+
+/// Per-download options, named after their `DownloadOptions` fields
+#[derive(Debug, Args)]
+struct OptionArgs {
+    /// Directory to save the file
+    #[arg(short, long)]
+    dir: Option<String>,
+    /// Output filename
+    #[arg(short, long)]
+    out: Option<String>,
+    /// Number of connections per server
+    #[arg(short = 'x', long)]
+    max_connection_per_server: Option<String>,
+    /// Custom user agent
+    #[arg(long)]
+    user_agent: Option<String>,
+    /// Referer URL
+    #[arg(long)]
+    referer: Option<String>,
+    /// Custom header ("Key: Value"), may be repeated
+    #[arg(long = "header", short = 'H')]
+    headers: Vec<String>,
+    /// Cookies to send ("a=1; b=2")
+    #[arg(long)]
+    cookies: Option<String>,
+    /// Proxy for checking the URL before adding it, instead of the proxy
+    /// setting (scheme://[user:pass@]host:port, or "" for none); the
+    /// download itself uses the proxy setting
+    #[arg(long)]
+    proxy: Option<String>,
+    /// Checksum as TYPE=VALUE (md5, sha1, sha256, sha512 or blake3), e.g. sha256=abc...
+    #[arg(long)]
+    checksum: Option<String>,
+    /// URL or path of a checksum file (e.g. SHA256SUMS) to look the checksum up in
+    #[arg(long, conflicts_with = "checksum")]
+    checksum_file: Option<String>,
+    /// Mirror URL for fallback, may be repeated
+    #[arg(long = "mirror")]
+    mirrors: Vec<String>,
+    /// Download priority (critical, high, normal, low)
+    #[arg(long)]
+    priority: Option<String>,
+    /// Max download speed, e.g. 500K or 2M
+    #[arg(long)]
+    max_download_limit: Option<String>,
+    /// Max upload speed, e.g. 500K or 2M
+    #[arg(long)]
+    max_upload_limit: Option<String>,
+    /// Torrent file indices to download, e.g. 0,2,5
+    #[arg(long)]
+    select_file: Option<String>,
+    /// Seed ratio for torrents
+    #[arg(long)]
+    seed_ratio: Option<String>,
+    /// Download torrent pieces sequentially
+    #[arg(long)]
+    sequential: bool,
+    /// File under this category instead of matching one by rules
+    #[arg(long)]
+    category: Option<String>,
+    /// Move the file into this folder once it completes
+    #[arg(long)]
+    move_to: Option<String>,
+    /// Copy the file into this folder once it completes
+    #[arg(long)]
+    copy_to: Option<String>,
+    /// Shell command to run once the file completes, may be repeated; sees
+    /// GOSH_GID, GOSH_PATH, GOSH_NAME and GOSH_URL
+    #[arg(long = "on-complete")]
+    on_complete: Vec<String>,
+    /// Open the file once it completes
+    #[arg(long)]
+    open: bool,
+    /// Unpack the file once it completes if it is a zip, tar, tar.gz or
+    /// tar.zst archive, next to it or into its category's folder
+    #[arg(long, num_args = 0..=1, default_missing_value = "sibling", value_enum)]
+    extract: Option<ExtractArg>,
+    /// Delete the archive after unpacking it
+    #[arg(long, requires = "extract")]
+    delete_archive: bool,
+    /// What to do when the URL, torrent or target file is already a
+    /// download: report it and skip (default), add it anyway, or remove the
+    /// old download and add it again
+    #[arg(long, value_enum)]
+    on_duplicate: Option<DuplicateArg>,
+    /// What to do when the file already exists, instead of the
+    /// conflict_policy setting
+    #[arg(long, value_enum)]
+    on_conflict: Option<ConflictArg>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ExtractArg {
+    Sibling,
+    Category,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DuplicateArg {
+    Report,
+    Add,
+    Restart,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ConflictArg {
+    Rename,
+    Overwrite,
+    Skip,
+    Resume,
+}
+
+#[derive(Debug, Args)]
+struct WaitArgs {
+    /// Queue the download and exit instead of waiting for it to finish
+    #[arg(long)]
+    no_wait: bool,
+}
+
+impl OptionArgs {
+    fn into_options(self) -> Option<DownloadOptions> {
+        let (checksum_type, checksum_value) = match self.checksum {
+            Some(c) => match c.split_once('=') {
+                Some((t, v)) => (Some(t.to_string()), Some(v.to_string())),
+                None => (None, None),
+            },
+            None => (None, None),
+        };
+
+        // Copy before moving and unpack after, so the later actions see the
+        // final location
+        let mut actions = Vec::new();
+        actions.extend(self.copy_to.map(|dir| CompletionAction::Copy { dir }));
+        actions.extend(self.move_to.map(|dir| CompletionAction::Move { dir }));
+        actions.extend(self.extract.map(|target| CompletionAction::Extract {
+            target: match target {
+                ExtractArg::Sibling => ExtractTarget::Sibling,
+                ExtractArg::Category => ExtractTarget::Category,
+            },
+            delete_archive: self.delete_archive,
+        }));
+        actions.extend(
+            self.on_complete
+                .into_iter()
+                .map(|command| CompletionAction::Command { command }),
+        );
+        if self.open {
+            actions.push(CompletionAction::Open);
+        }
+
+        let options = DownloadOptions {
+            dir: self.dir,
+            out: self.out,
+            max_connection_per_server: self.max_connection_per_server,
+            user_agent: self.user_agent,
+            referer: self.referer,
+            header: Some(self.headers).filter(|h| !h.is_empty()),
+            select_file: self.select_file,
+            seed_ratio: self.seed_ratio,
+            max_download_limit: self.max_download_limit,
+            max_upload_limit: self.max_upload_limit,
+            checksum_type,
+            checksum_value,
+            checksum_file: self.checksum_file,
+            mirror_urls: Some(self.mirrors).filter(|m| !m.is_empty()),
+            priority: self.priority,
+            cookies: self.cookies,
+            sequential: self.sequential.then_some(true),
+            category: self.category,
+            completion_actions: Some(actions).filter(|a| !a.is_empty()),
+            on_duplicate: self.on_duplicate.map(|action| match action {
+                DuplicateArg::Report => DuplicateAction::Report,
+                DuplicateArg::Add => DuplicateAction::Add,
+                DuplicateArg::Restart => DuplicateAction::Restart,
+            }),
+            conflict_policy: self.on_conflict.map(|policy| match policy {
+                ConflictArg::Rename => ConflictPolicy::Rename,
+                ConflictArg::Overwrite => ConflictPolicy::Overwrite,
+                ConflictArg::Skip => ConflictPolicy::Skip,
+                ConflictArg::Resume => ConflictPolicy::Resume,
+            }),
+            proxy: self.proxy,
+            ..Default::default()
+        };
+
+        Some(options)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    if let Err(e) = run(Cli::parse()).await {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let json = cli.json;
+    let db = &init_database()?;
+
+    match cli.command {
+        Command::Add {
+            urls,
+            options,
+            wait,
+        } => commands::add(db, urls, options.into_options(), !wait.no_wait, json).await,
+        Command::AddMagnet { uri, options, wait } => {
+            commands::add_magnet(db, uri, options.into_options(), !wait.no_wait, json).await
+        }
+        Command::AddTorrent {
+            path,
+            options,
+            wait,
+        } => commands::add_torrent(db, path, options.into_options(), !wait.no_wait, json).await,
+        Command::AddMetalink {
+            source,
+            options,
+            wait,
+        } => commands::add_metalink(db, source, options.into_options(), !wait.no_wait, json).await,
+        Command::AddList { path, wait } => commands::add_list(db, path, !wait.no_wait, json).await,
+        Command::ExportList { output } => commands::export_list(db, output),
+        Command::List { category } => commands::list(db, category, json),
+        Command::Status { gid } => commands::status(db, gid, json),
+        Command::Pause { gid, all } => commands::pause(db, gid, all).await,
+        Command::Resume { gid, all } => commands::resume(db, gid, all).await,
+        Command::Remove { gid, delete_files } => commands::remove(db, gid, delete_files).await,
+        Command::History { limit, clear } => commands::history(db, limit, clear, json),
+        Command::Verify { gid } => commands::verify(db, gid, json).await,
+        Command::Export { output, redact } => commands::export(db, output, redact),
+        Command::Import { path, replace } => commands::import(db, path, replace, json),
+        Command::WatchFolders => commands::watch_folders(db, json),
+        Command::AddWatchFolder {
+            path,
+            dir,
+            category,
+            move_aside,
+        } => commands::add_watch_folder(db, path, dir, category, move_aside),
+        Command::RemoveWatchFolder { folder } => commands::remove_watch_folder(db, folder),
+        Command::Watch { forever } => commands::watch(db, forever, json).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> std::result::Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("gosh-fetch-cli").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_pause_takes_a_gid_or_all() {
+        assert!(matches!(
+            parse(&["pause", "--all"]).unwrap().command,
+            Command::Pause {
+                gid: None,
+                all: true
+            }
+        ));
+        assert!(matches!(
+            parse(&["resume", "abc"]).unwrap().command,
+            Command::Resume {
+                gid: Some(_),
+                all: false
+            }
+        ));
+        assert!(parse(&["pause"]).is_err());
+        assert!(parse(&["pause", "abc", "--all"]).is_err());
+    }
+
+    #[test]
+    fn test_options_are_built_from_flags() {
+        let cli = parse(&[
+            "--json",
+            "add",
+            "https://example.com/a.tar.gz",
+            "--checksum",
+            "sha256=abc",
+            "--copy-to",
+            "/backup",
+            "--extract",
+            "--on-conflict",
+            "skip",
+            "-H",
+            "Accept: */*",
+        ])
+        .unwrap();
+        assert!(cli.json);
+        let Command::Add { urls, options, .. } = cli.command else {
+            panic!("expected add");
+        };
+        assert_eq!(urls, ["https://example.com/a.tar.gz"]);
+
+        let options = options.into_options().unwrap();
+        assert_eq!(options.checksum_type.as_deref(), Some("sha256"));
+        assert_eq!(options.checksum_value.as_deref(), Some("abc"));
+        assert_eq!(options.header, Some(vec!["Accept: */*".to_string()]));
+        assert_eq!(options.conflict_policy, Some(ConflictPolicy::Skip));
+        // Copied before unpacking, and unpacked next to the file by default
+        assert_eq!(
+            options.completion_actions,
+            Some(vec![
+                CompletionAction::Copy {
+                    dir: "/backup".to_string()
+                },
+                CompletionAction::Extract {
+                    target: ExtractTarget::Sibling,
+                    delete_archive: false
+                },
+            ])
+        );
+
+        assert!(parse(&["add", "https://example.com/a", "--delete-archive"]).is_err());
+        assert!(parse(&["add"]).is_err());
+    }
+}
@@ -0,0 +1,385 @@
+//! Time-based bandwidth profiles
+//!
+//! The weekly schedule in the settings maps each hour of the week to a
+//! named profile. The service checks it on a timer and applies the speed
+//! limits of whichever profile is in effect. A profile picked by hand stays
+//! in effect until the schedule next changes profile.
+//!
+//! A profile that pauses downloads also pauses the ones added or started
+//! while it is in effect, and resumes only those it paused when it ends.
+
+use crate::engine_adapter::EngineAdapter;
+use crate::error::{Error, Result};
+use crate::types::{BandwidthProfile, BandwidthSchedule, Download, DownloadState, Settings};
+use chrono::{Datelike, Duration as TimeDelta, NaiveDateTime, Timelike};
+use std::time::Duration;
+
+/// How often the service checks the schedule
+pub(crate) const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Number of hourly slots in the weekly schedule
+pub const HOURS_PER_WEEK: usize = 7 * 24;
+
+/// Slot of the weekly schedule a local time falls in
+pub fn slot_of(time: NaiveDateTime) -> usize {
+    time.weekday().num_days_from_monday() as usize * 24 + time.hour() as usize
+}
+
+/// Profile the schedule puts in effect for a slot
+pub fn scheduled_profile(schedule: &BandwidthSchedule, slot: usize) -> Option<&BandwidthProfile> {
+    if !schedule.enabled {
+        return None;
+    }
+    let name = schedule.slots.get(slot % HOURS_PER_WEEK)?.as_deref()?;
+    schedule.profiles.iter().find(|p| p.name == name)
+}
+
+/// Start of the next hour in which the schedule changes profile
+///
+/// Returns `None` when the schedule is off or the same all week.
+pub fn next_boundary(schedule: &BandwidthSchedule, time: NaiveDateTime) -> Option<NaiveDateTime> {
+    let slot = slot_of(time);
+    let current = scheduled_profile(schedule, slot).map(|p| &p.name);
+    let hour_start = time.date().and_hms_opt(time.hour(), 0, 0)?;
+
+    (1..HOURS_PER_WEEK)
+        .find(|offset| scheduled_profile(schedule, slot + offset).map(|p| &p.name) != current)
+        .map(|offset| hour_start + TimeDelta::hours(offset as i64))
+}
+
+/// Profile chosen by hand
+struct Manual {
+    profile: BandwidthProfile,
+    until: Option<NaiveDateTime>,
+}
+
+/// Keeps the engine's speed limits in line with the bandwidth schedule
+pub(crate) struct Bandwidth {
+    schedule: BandwidthSchedule,
+    /// Global limits from the settings, used when no profile is in effect
+    default_limits: (u64, u64),
+    manual: Option<Manual>,
+    /// Profile last applied; `None` until limits have been applied
+    applied: Option<Option<BandwidthProfile>>,
+    /// Whether a pausing profile is in effect
+    pausing: bool,
+    /// Downloads paused by a profile, resumed when it ends
+    paused: Vec<String>,
+}
+
+impl Bandwidth {
+    pub(crate) fn new(settings: &Settings) -> Self {
+        Self {
+            schedule: settings.bandwidth_schedule.clone(),
+            default_limits: (settings.download_speed_limit, settings.upload_speed_limit),
+            manual: None,
+            applied: None,
+            pausing: false,
+            paused: Vec::new(),
+        }
+    }
+
+    /// Pick up edited settings; limits are re-applied on the next `apply`
+    pub(crate) fn update_settings(&mut self, settings: &Settings) {
+        self.schedule = settings.bandwidth_schedule.clone();
+        self.default_limits = (settings.download_speed_limit, settings.upload_speed_limit);
+        self.invalidate();
+    }
+
+    /// Forget the applied limits, e.g. after the engine config was replaced
+    pub(crate) fn invalidate(&mut self) {
+        self.applied = None;
+    }
+
+    /// Name of the profile in effect
+    pub(crate) fn active_profile(&self) -> Option<String> {
+        self.applied.clone().flatten().map(|p| p.name)
+    }
+
+    /// Use a profile until the schedule next changes, or go back to the
+    /// schedule with `None`
+    pub(crate) fn set_manual(&mut self, name: Option<&str>) -> Result<()> {
+        self.set_manual_at(name, now())
+    }
+
+    fn set_manual_at(&mut self, name: Option<&str>, now: NaiveDateTime) -> Result<()> {
+        self.manual = match name {
+            Some(name) => {
+                let profile = self
+                    .schedule
+                    .profiles
+                    .iter()
+                    .find(|p| p.name == name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::InvalidInput(format!("Unknown bandwidth profile: {}", name))
+                    })?;
+                let until = next_boundary(&self.schedule, now);
+                Some(Manual { profile, until })
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    fn wanted(&mut self, now: NaiveDateTime) -> Option<BandwidthProfile> {
+        if let Some(manual) = &self.manual {
+            if manual.until.is_none_or(|until| now < until) {
+                return Some(manual.profile.clone());
+            }
+            self.manual = None;
+        }
+        scheduled_profile(&self.schedule, slot_of(now)).cloned()
+    }
+
+    /// Download and upload limits for a profile, `None` meaning unlimited
+    fn limits(&self, profile: Option<&BandwidthProfile>) -> (Option<u64>, Option<u64>) {
+        let (download, upload) =
+            profile.map_or(self.default_limits, |p| (p.download_limit, p.upload_limit));
+        let limit = |bytes: u64| (bytes > 0).then_some(bytes);
+        (limit(download), limit(upload))
+    }
+
+    /// Downloads to pause and to resume so that they follow `pause`
+    ///
+    /// While pausing, every running download is paused, including ones
+    /// added or started since. Once it ends, only the downloads it paused
+    /// that are still paused are resumed.
+    fn pause_plan(&self, pause: bool, downloads: &[Download]) -> (Vec<String>, Vec<String>) {
+        if pause {
+            let running = downloads
+                .iter()
+                .filter(|d| matches!(d.status, DownloadState::Active | DownloadState::Waiting))
+                .map(|d| d.gid.clone())
+                .collect();
+            return (running, Vec::new());
+        }
+        let held = downloads
+            .iter()
+            .filter(|d| d.status == DownloadState::Paused && self.paused.contains(&d.gid))
+            .map(|d| d.gid.clone())
+            .collect();
+        (Vec::new(), held)
+    }
+
+    /// Pause or resume downloads to follow `pause`
+    async fn follow_pause(&mut self, adapter: &EngineAdapter, pause: bool) {
+        let (to_pause, to_resume) = self.pause_plan(pause, &adapter.get_all());
+        for gid in to_pause {
+            if adapter.pause(&gid).await.is_ok() && !self.paused.contains(&gid) {
+                self.paused.push(gid);
+            }
+        }
+        for gid in to_resume {
+            let _ = adapter.resume(&gid).await;
+        }
+        if !pause {
+            self.paused.clear();
+        }
+        self.pausing = pause;
+    }
+
+    /// Pause downloads added or started while a pausing profile is in effect
+    pub(crate) async fn hold(&mut self, adapter: &EngineAdapter) {
+        if self.pausing {
+            self.follow_pause(adapter, true).await;
+        }
+    }
+
+    /// Apply the limits of the profile in effect, if they changed
+    pub(crate) async fn apply(&mut self, adapter: &EngineAdapter) {
+        let wanted = self.wanted(now());
+        if self.applied.as_ref() == Some(&wanted) {
+            self.hold(adapter).await;
+            return;
+        }
+
+        let (download, upload) = self.limits(wanted.as_ref());
+        if let Err(e) = adapter.set_speed_limit(download, upload) {
+            log::error!("Failed to apply bandwidth limits: {}", e);
+            return;
+        }
+
+        let pause = wanted.as_ref().is_some_and(|p| p.paused);
+        if pause || self.pausing {
+            self.follow_pause(adapter, pause).await;
+        }
+
+        match &wanted {
+            Some(profile) => log::info!("Bandwidth profile: {}", profile.name),
+            None => log::info!("Bandwidth profile: global limits"),
+        }
+        self.applied = Some(wanted);
+    }
+}
+
+fn now() -> NaiveDateTime {
+    chrono::Local::now().naive_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str) -> BandwidthProfile {
+        BandwidthProfile {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn download(gid: &str, status: DownloadState) -> Download {
+        Download {
+            gid: gid.to_string(),
+            status,
+            ..Default::default()
+        }
+    }
+
+    /// Weekday office hours under `office`, and nights under a pausing `night`
+    fn bandwidth() -> Bandwidth {
+        let mut slots = vec![None; HOURS_PER_WEEK];
+        for day in 0..7 {
+            for hour in 9..17 {
+                slots[day * 24 + hour] = Some("office".to_string());
+            }
+            for hour in 0..6 {
+                slots[day * 24 + hour] = Some("night".to_string());
+            }
+        }
+        let settings = Settings {
+            download_speed_limit: 0,
+            upload_speed_limit: 1000,
+            bandwidth_schedule: BandwidthSchedule {
+                enabled: true,
+                profiles: vec![
+                    BandwidthProfile {
+                        name: "office".to_string(),
+                        download_limit: 500,
+                        upload_limit: 0,
+                        ..Default::default()
+                    },
+                    BandwidthProfile {
+                        paused: true,
+                        ..profile("night")
+                    },
+                ],
+                slots,
+            },
+            ..Default::default()
+        };
+        Bandwidth::new(&settings)
+    }
+
+    #[test]
+    fn test_limits_follow_the_profile_in_effect() {
+        let mut bandwidth = bandwidth();
+        // 2024-01-01 is a Monday
+        let office = bandwidth.wanted(at("2024-01-01 10:00"));
+        assert_eq!(bandwidth.limits(office.as_ref()), (Some(500), None));
+        let evening = bandwidth.wanted(at("2024-01-01 20:00"));
+        assert_eq!(evening, None);
+        assert_eq!(bandwidth.limits(evening.as_ref()), (None, Some(1000)));
+    }
+
+    #[test]
+    fn test_only_downloads_paused_by_the_profile_are_resumed() {
+        let mut bandwidth = bandwidth();
+        let mut downloads = vec![
+            download("running", DownloadState::Active),
+            download("queued", DownloadState::Waiting),
+            download("held", DownloadState::Paused),
+            download("done", DownloadState::Complete),
+        ];
+
+        let (pause, resume) = bandwidth.pause_plan(true, &downloads);
+        assert_eq!(pause, ["running", "queued"]);
+        assert!(resume.is_empty());
+        bandwidth.paused = pause;
+        for d in &mut downloads[..2] {
+            d.status = DownloadState::Paused;
+        }
+
+        // A download added while the profile is in effect is held too
+        downloads.push(download("late", DownloadState::Waiting));
+        let (pause, _) = bandwidth.pause_plan(true, &downloads);
+        assert_eq!(pause, ["late"]);
+        bandwidth.paused.extend(pause);
+        downloads[4].status = DownloadState::Paused;
+
+        // The one paused by hand stays paused, and a removed one is skipped
+        downloads.remove(1);
+        let (pause, resume) = bandwidth.pause_plan(false, &downloads);
+        assert!(pause.is_empty());
+        assert_eq!(resume, ["running", "late"]);
+    }
+
+    #[test]
+    fn test_manual_profile_lasts_until_the_schedule_changes() {
+        let mut bandwidth = bandwidth();
+        bandwidth
+            .set_manual_at(Some("night"), at("2024-01-01 10:00"))
+            .unwrap();
+        assert_eq!(
+            bandwidth.wanted(at("2024-01-01 16:59")).map(|p| p.name),
+            Some("night".to_string())
+        );
+        assert_eq!(bandwidth.wanted(at("2024-01-01 17:00")), None);
+        // Expired, so the schedule is back in charge
+        assert!(bandwidth.manual.is_none());
+        assert_eq!(
+            bandwidth.wanted(at("2024-01-02 10:00")).map(|p| p.name),
+            Some("office".to_string())
+        );
+
+        assert!(bandwidth
+            .set_manual_at(Some("lunch"), at("2024-01-01 10:00"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_next_boundary() {
+        // Office hours on weekdays, nothing on weekends
+        let mut slots = vec![None; HOURS_PER_WEEK];
+        for day in 0..5 {
+            for hour in 9..17 {
+                slots[day * 24 + hour] = Some("office".to_string());
+            }
+        }
+        let schedule = BandwidthSchedule {
+            enabled: true,
+            profiles: vec![profile("office")],
+            slots,
+        };
+
+        // 2024-01-01 is a Monday
+        let monday_noon = at("2024-01-01 12:30");
+        assert_eq!(slot_of(monday_noon), 12);
+        assert_eq!(
+            scheduled_profile(&schedule, slot_of(monday_noon)).map(|p| p.name.as_str()),
+            Some("office")
+        );
+        assert_eq!(
+            next_boundary(&schedule, monday_noon),
+            Some(at("2024-01-01 17:00"))
+        );
+
+        // Friday evening runs through the weekend to Monday morning
+        let friday_evening = at("2024-01-05 20:15");
+        assert_eq!(scheduled_profile(&schedule, slot_of(friday_evening)), None);
+        assert_eq!(
+            next_boundary(&schedule, friday_evening),
+            Some(at("2024-01-08 09:00"))
+        );
+
+        let off = BandwidthSchedule {
+            enabled: false,
+            ..schedule
+        };
+        assert_eq!(next_boundary(&off, monday_noon), None);
+    }
+}
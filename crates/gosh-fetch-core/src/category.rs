@@ -0,0 +1,290 @@
+//! Download categories
+//!
+//! Picks the category a new download falls into and fills in the save
+//! directory and default options that category brings.
+
+use crate::types::{Category, CategoryRules, DownloadOptions, TorrentInfo};
+use reqwest::Url;
+
+/// What is known about a download when it is added
+#[derive(Debug, Clone, Default)]
+pub struct CategoryTarget {
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    /// Hosts the download is fetched from (before and after redirects)
+    pub hosts: Vec<String>,
+    /// Tracker hostnames
+    pub trackers: Vec<String>,
+}
+
+impl CategoryTarget {
+    /// Target for an HTTP download
+    pub fn for_url(urls: &[&str], out: Option<&str>, mime_type: Option<&str>) -> Self {
+        let parsed: Vec<Url> = urls.iter().filter_map(|u| Url::parse(u).ok()).collect();
+        let file_name = out.map(str::to_string).or_else(|| {
+            parsed.last().and_then(|url| {
+                url.path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+            })
+        });
+
+        let mut hosts = Vec::new();
+        for host in parsed.iter().filter_map(Url::host_str) {
+            let host = host.to_lowercase();
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+
+        Self {
+            file_name,
+            mime_type: mime_type.map(str::to_string),
+            hosts,
+            trackers: Vec::new(),
+        }
+    }
+
+    /// Target for a magnet link, from its display name and trackers
+    pub fn for_magnet(uri: &str) -> Self {
+        let mut target = Self::default();
+        if let Ok(url) = Url::parse(uri) {
+            for (key, value) in url.query_pairs() {
+                match key.as_ref() {
+                    "dn" => target.file_name = Some(value.into_owned()),
+                    "tr" => target.trackers.extend(tracker_host(&value)),
+                    _ => {}
+                }
+            }
+        }
+        target
+    }
+
+    /// Target for a .torrent, going by its largest file
+    pub fn for_torrent(info: &TorrentInfo) -> Self {
+        let file_name = info
+            .files
+            .iter()
+            .max_by_key(|f| f.length)
+            .map(|f| f.path.clone())
+            .or_else(|| Some(info.name.clone()));
+
+        Self {
+            file_name,
+            trackers: info
+                .announce_list
+                .iter()
+                .filter_map(|t| tracker_host(t))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+fn tracker_host(tracker: &str) -> Option<String> {
+    Url::parse(tracker)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+}
+
+/// Find the category for a download
+///
+/// A category named in the options wins; otherwise the first category whose
+/// rules match is used.
+pub fn find<'a>(
+    categories: &'a [Category],
+    options: &DownloadOptions,
+    target: &CategoryTarget,
+) -> Option<&'a Category> {
+    if let Some(name) = options.category.as_deref().filter(|n| !n.is_empty()) {
+        let category = categories
+            .iter()
+            .find(|c| c.name.eq_ignore_ascii_case(name));
+        if category.is_none() {
+            log::warn!("Unknown category: {}", name);
+        }
+        return category;
+    }
+
+    categories.iter().find(|c| matches(&c.rules, target))
+}
+
+/// Whether any of the rules match the target
+pub fn matches(rules: &CategoryRules, target: &CategoryTarget) -> bool {
+    let extension = target
+        .file_name
+        .as_deref()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_lowercase());
+    if let Some(extension) = extension {
+        if rules
+            .extensions
+            .iter()
+            .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&extension))
+        {
+            return true;
+        }
+    }
+
+    if let Some(mime_type) = target.mime_type.as_deref() {
+        // Drop parameters such as "; charset=utf-8"
+        let mime_type = mime_type.split(';').next().unwrap_or("").trim();
+        if rules.mime_types.iter().any(|m| mime_matches(m, mime_type)) {
+            return true;
+        }
+    }
+
+    let host_rule = |rules: &[String], hosts: &[String]| {
+        rules
+            .iter()
+            .any(|rule| hosts.iter().any(|host| host_matches(rule, host)))
+    };
+    host_rule(&rules.hosts, &target.hosts) || host_rule(&rules.trackers, &target.trackers)
+}
+
+fn mime_matches(rule: &str, mime_type: &str) -> bool {
+    match rule.strip_suffix("/*") {
+        Some(prefix) => mime_type
+            .split_once('/')
+            .is_some_and(|(kind, _)| kind.eq_ignore_ascii_case(prefix)),
+        None => rule.eq_ignore_ascii_case(mime_type),
+    }
+}
+
+fn host_matches(rule: &str, host: &str) -> bool {
+    let rule = rule.trim().trim_start_matches("*.").to_lowercase();
+    !rule.is_empty() && (host == rule || host.ends_with(&format!(".{}", rule)))
+}
+
+/// Fill in the category's save directory and defaults where the download
+/// options leave them unset
+pub fn apply(mut options: DownloadOptions, category: &Category) -> DownloadOptions {
+    let defaults = &category.options;
+
+    if options.dir.is_none() && !category.save_dir.is_empty() {
+        options.dir = Some(category.save_dir.clone());
+    }
+    options.max_connection_per_server = options
+        .max_connection_per_server
+        .or_else(|| defaults.max_connection_per_server.clone());
+    options.user_agent = options.user_agent.or_else(|| defaults.user_agent.clone());
+    options.header = options.header.or_else(|| defaults.header.clone());
+    options.seed_ratio = options.seed_ratio.or_else(|| defaults.seed_ratio.clone());
+    options.max_download_limit = options
+        .max_download_limit
+        .or_else(|| defaults.max_download_limit.clone());
+    options.max_upload_limit = options
+        .max_upload_limit
+        .or_else(|| defaults.max_upload_limit.clone());
+    options.priority = options.priority.or_else(|| defaults.priority.clone());
+    options.sequential = options.sequential.or(defaults.sequential);
+    options.preallocation = options
+        .preallocation
+        .or_else(|| defaults.preallocation.clone());
+    options.completion_actions = options
+        .completion_actions
+        .or_else(|| defaults.completion_actions.clone());
+    options.category = Some(category.name.clone());
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(name: &str, rules: CategoryRules) -> Category {
+        Category {
+            name: name.to_string(),
+            save_dir: format!("/downloads/{}", name),
+            rules,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_category() {
+        let categories = vec![
+            category(
+                "video",
+                CategoryRules {
+                    extensions: vec!["mkv".to_string()],
+                    mime_types: vec!["video/*".to_string()],
+                    ..Default::default()
+                },
+            ),
+            category(
+                "linux",
+                CategoryRules {
+                    hosts: vec!["kernel.org".to_string()],
+                    trackers: vec!["torrent.ubuntu.com".to_string()],
+                    ..Default::default()
+                },
+            ),
+        ];
+        let none = DownloadOptions::default();
+        let name =
+            |target: &CategoryTarget| find(&categories, &none, target).map(|c| c.name.as_str());
+
+        let target = CategoryTarget::for_url(&["https://example.com/a/Movie.MKV"], None, None);
+        assert_eq!(name(&target), Some("video"));
+
+        let target = CategoryTarget::for_url(
+            &["https://example.com/watch?v=1"],
+            None,
+            Some("video/mp4; codecs=avc1"),
+        );
+        assert_eq!(name(&target), Some("video"));
+
+        let target =
+            CategoryTarget::for_url(&["https://mirrors.kernel.org/pub/linux.tar.xz"], None, None);
+        assert_eq!(name(&target), Some("linux"));
+
+        let target = CategoryTarget::for_magnet(
+            "magnet:?xt=urn:btih:abc&dn=ubuntu.iso&tr=https%3A%2F%2Ftorrent.ubuntu.com%2Fannounce",
+        );
+        assert_eq!(name(&target), Some("linux"));
+
+        let target = CategoryTarget::for_url(&["https://notkernel.org/a.zip"], None, None);
+        assert_eq!(name(&target), None);
+
+        // An explicit category beats the rules
+        let explicit = DownloadOptions {
+            category: Some("Linux".to_string()),
+            ..Default::default()
+        };
+        let target = CategoryTarget::for_url(&["https://example.com/a.mkv"], None, None);
+        assert_eq!(
+            find(&categories, &explicit, &target).map(|c| c.name.as_str()),
+            Some("linux")
+        );
+    }
+
+    #[test]
+    fn test_apply_keeps_explicit_options() {
+        let mut video = category("video", CategoryRules::default());
+        video.options.max_download_limit = Some("2M".to_string());
+        video.options.max_connection_per_server = Some("4".to_string());
+
+        let options = apply(
+            DownloadOptions {
+                max_connection_per_server: Some("16".to_string()),
+                ..Default::default()
+            },
+            &video,
+        );
+        assert_eq!(options.dir.as_deref(), Some("/downloads/video"));
+        assert_eq!(options.max_download_limit.as_deref(), Some("2M"));
+        assert_eq!(options.max_connection_per_server.as_deref(), Some("16"));
+        assert_eq!(options.category.as_deref(), Some("video"));
+
+        let options = apply(
+            DownloadOptions {
+                dir: Some("/elsewhere".to_string()),
+                ..Default::default()
+            },
+            &video,
+        );
+        assert_eq!(options.dir.as_deref(), Some("/elsewhere"));
+    }
+}
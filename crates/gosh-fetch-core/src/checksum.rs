@@ -0,0 +1,274 @@
+//! File checksums
+//!
+//! The engine checks MD5 and SHA-256 while downloading; SHA-1, SHA-512 and
+//! BLAKE3 are checked by hashing the finished file. The expected value can
+//! also come from a checksum file (`SHA256SUMS`, `distro.iso.sha256` or
+//! BSD-style `SHA256 (distro.iso) = ...` lines), searched for the line that
+//! names the download.
+
+use crate::error::{Error, Result};
+use crate::feeds;
+use crate::types::{ChecksumResult, Download, DownloadOptions};
+use sha2::Digest;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Supported hash algorithms, as written in `checksum_type`
+pub const ALGORITHMS: &[&str] = &["md5", "sha1", "sha256", "sha512", "blake3"];
+
+/// Algorithm name for "SHA-256", "sha256", "B3" and the like
+pub fn normalize(kind: &str) -> Option<&'static str> {
+    match kind.trim().to_lowercase().replace(['-', '_'], "").as_str() {
+        "md5" => Some("md5"),
+        "sha1" => Some("sha1"),
+        "sha256" => Some("sha256"),
+        "sha512" => Some("sha512"),
+        "blake3" | "b3" => Some("blake3"),
+        _ => None,
+    }
+}
+
+/// Whether the engine checks this algorithm while downloading
+pub fn engine_verifies(algorithm: &str) -> bool {
+    matches!(normalize(algorithm), Some("md5" | "sha256"))
+}
+
+/// Whether the options ask for a check the engine does not do itself
+pub fn needs_check(options: &DownloadOptions) -> bool {
+    options.checksum_file.is_some()
+        || options
+            .checksum_type
+            .as_deref()
+            .is_some_and(|kind| !engine_verifies(kind))
+}
+
+/// The algorithm and digest a file is expected to have
+///
+/// An explicit `checksum_type`/`checksum_value` wins; otherwise the
+/// `checksum_file` URL or path is read and searched for `file_name`.
+pub async fn expected(
+    options: &DownloadOptions,
+    file_name: &str,
+) -> Result<Option<(&'static str, String)>> {
+    if let (Some(kind), Some(value)) = (&options.checksum_type, &options.checksum_value) {
+        let algorithm = normalize(kind)
+            .ok_or_else(|| Error::InvalidInput(format!("Unsupported checksum type: {}", kind)))?;
+        return Ok(Some((algorithm, value.trim().to_lowercase())));
+    }
+
+    let Some(source) = &options.checksum_file else {
+        return Ok(None);
+    };
+    let data = if source.starts_with("http://") || source.starts_with("https://") {
+        let client = feeds::http_client()?;
+        feeds::fetch(&client, source).await?
+    } else {
+        std::fs::read(source)?
+    };
+
+    find_in_sums(&String::from_utf8_lossy(&data), file_name, source)
+        .map(Some)
+        .ok_or_else(|| Error::NotFound(format!("No checksum for {} in {}", file_name, source)))
+}
+
+/// Find the checksum of `file_name` in the text of a checksum file
+///
+/// The algorithm comes from a BSD-style tag, then from the checksum file's
+/// own name (`SHA512SUMS`, `.b3`, ...), then from the digest's length. A
+/// file holding a single digest without a name applies to any file.
+pub fn find_in_sums(text: &str, file_name: &str, source: &str) -> Option<(&'static str, String)> {
+    let hinted = algorithm_from_name(source);
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect();
+
+    for line in &lines {
+        // BSD style: "SHA256 (name) = digest"
+        if let Some((tag, rest)) = line.split_once(" (") {
+            if let Some((name, digest)) = rest.rsplit_once(") = ") {
+                if same_file(name, file_name) {
+                    let algorithm = normalize(tag).or(hinted).or_else(|| by_length(digest))?;
+                    return Some((algorithm, digest.trim().to_lowercase()));
+                }
+                continue;
+            }
+        }
+
+        // GNU style: "digest  name", with " *name" for binary mode
+        let Some((digest, name)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let name = name.trim_start().trim_start_matches('*');
+        if is_hex(digest) && same_file(name, file_name) {
+            let algorithm = hinted.or_else(|| by_length(digest))?;
+            return Some((algorithm, digest.to_lowercase()));
+        }
+    }
+
+    match lines.as_slice() {
+        [digest] if is_hex(digest) => {
+            let algorithm = hinted.or_else(|| by_length(digest))?;
+            Some((algorithm, digest.to_lowercase()))
+        }
+        _ => None,
+    }
+}
+
+fn same_file(listed: &str, file_name: &str) -> bool {
+    let listed = listed.trim();
+    let base = listed.rsplit(['/', '\\']).next().unwrap_or(listed);
+    base == file_name
+}
+
+fn is_hex(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Algorithm named by a checksum file's URL or path
+fn algorithm_from_name(source: &str) -> Option<&'static str> {
+    let name = source
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(source)
+        .rsplit('/')
+        .next()
+        .unwrap_or(source)
+        .to_lowercase();
+    ["sha512", "sha256", "sha1", "md5", "blake3", "b3"]
+        .into_iter()
+        .find(|kind| name.contains(kind))
+        .and_then(normalize)
+}
+
+/// Algorithm by digest length; 64 hex digits are taken as SHA-256
+fn by_length(digest: &str) -> Option<&'static str> {
+    match digest.trim().len() {
+        32 => Some("md5"),
+        40 => Some("sha1"),
+        64 => Some("sha256"),
+        128 => Some("sha512"),
+        _ => None,
+    }
+}
+
+/// Hex digest of a file
+pub fn hash_file(path: &Path, algorithm: &str) -> Result<String> {
+    let mut file = BufReader::new(File::open(path)?);
+    match normalize(algorithm) {
+        Some("md5") => digest_reader(&mut file, md5::Md5::new()),
+        Some("sha1") => digest_reader(&mut file, sha1::Sha1::new()),
+        Some("sha256") => digest_reader(&mut file, sha2::Sha256::new()),
+        Some("sha512") => digest_reader(&mut file, sha2::Sha512::new()),
+        Some("blake3") => {
+            let mut hasher = blake3::Hasher::new();
+            std::io::copy(&mut file, &mut hasher)?;
+            Ok(hasher.finalize().to_hex().to_string())
+        }
+        _ => Err(Error::InvalidInput(format!(
+            "Unsupported checksum type: {}",
+            algorithm
+        ))),
+    }
+}
+
+fn digest_reader<D: Digest + Write>(file: &mut BufReader<File>, mut hasher: D) -> Result<String> {
+    std::io::copy(file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// The file a finished download wrote
+///
+/// Torrents with several files have no single file to hash.
+pub fn download_file(download: &Download) -> Result<PathBuf> {
+    let path = Path::new(&download.save_path).join(&download.name);
+    if !path.is_file() {
+        return Err(Error::InvalidInput(format!(
+            "{} is not a single downloaded file",
+            path.display()
+        )));
+    }
+    Ok(path)
+}
+
+/// Hash a finished download and compare it with its expected checksum
+///
+/// Without an expected checksum the file's SHA-256 is recorded. Hashing runs
+/// on the blocking thread pool, since large files take a while.
+pub async fn verify(
+    download: &Download,
+    options: Option<&DownloadOptions>,
+) -> Result<ChecksumResult> {
+    let path = download_file(download)?;
+    let expected = match options {
+        Some(options) => expected(options, &download.name).await?,
+        None => None,
+    };
+
+    let (algorithm, expected_digest) = match expected {
+        Some((algorithm, digest)) => (algorithm, Some(digest)),
+        None => ("sha256", None),
+    };
+    let digest = tokio::task::spawn_blocking(move || hash_file(&path, algorithm))
+        .await
+        .map_err(|e| Error::Engine(format!("Checksum task failed: {}", e)))??;
+
+    Ok(ChecksumResult {
+        algorithm: algorithm.to_string(),
+        matched: expected_digest.map(|expected| expected == digest),
+        digest,
+        checked_at: chrono::Utc::now().timestamp(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum_files_and_hashes() {
+        let sums = "# release 1.0\n\
+            d41d8cd98f00b204e9800998ecf8427e  distro.iso.sig\n\
+            E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855 *isos/distro.iso\n";
+        assert_eq!(
+            find_in_sums(sums, "distro.iso", "https://example.com/SHA256SUMS"),
+            Some((
+                "sha256",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string()
+            ))
+        );
+        assert_eq!(
+            find_in_sums(sums, "distro.iso.sig", "sums.txt").map(|(a, _)| a),
+            Some("md5")
+        );
+        assert_eq!(find_in_sums(sums, "other.iso", "SHA256SUMS"), None);
+
+        let bsd = "SHA512 (distro.iso) = abcdef\n";
+        assert_eq!(
+            find_in_sums(bsd, "distro.iso", "CHECKSUM"),
+            Some(("sha512", "abcdef".to_string()))
+        );
+        let single = "0123456789abcdef0123456789abcdef01234567\n";
+        assert_eq!(
+            find_in_sums(single, "distro.iso", "distro.iso.b3").map(|(a, _)| a),
+            Some("blake3")
+        );
+
+        assert_eq!(normalize("SHA-512"), Some("sha512"));
+        assert_eq!(normalize("crc32"), None);
+
+        let path = std::env::temp_dir().join(format!("gosh-fetch-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"abc").unwrap();
+        assert_eq!(
+            hash_file(&path, "sha1").unwrap(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hash_file(&path, "blake3").unwrap(),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
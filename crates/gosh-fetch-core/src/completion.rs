@@ -0,0 +1,336 @@
+//! Actions run once a download completes
+//!
+//! A download's own `completion_actions` win, then those of its category,
+//! then the global setting. Actions run in order and each outcome is stored
+//! in the download's action log; a failed action is reported but leaves the
+//! download complete. Extraction only applies to archives and is skipped
+//! for other files.
+
+use crate::db::{CategoriesDb, Database, DownloadsDb, SettingsDb};
+use crate::error::{Error, Result};
+use crate::extract;
+use crate::types::{
+    ActionLogEntry, CompletionAction, Download, DownloadOptions, DownloadType, ExtractTarget,
+};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+
+/// Called with the bytes of an archive read so far and its size
+pub type ExtractProgress = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// Characters of a failed command's stderr kept in its log entry
+const STDERR_TAIL: usize = 400;
+
+/// The actions to run for a finished download
+pub fn actions_for(
+    db: &Database,
+    download: &Download,
+    options: Option<&DownloadOptions>,
+) -> Vec<CompletionAction> {
+    if let Some(actions) = options.and_then(|o| o.completion_actions.clone()) {
+        return actions;
+    }
+
+    let category = download
+        .category
+        .as_deref()
+        .and_then(|name| CategoriesDb::get_by_name(db, name).ok().flatten());
+    if let Some(actions) = category.and_then(|c| c.options.completion_actions) {
+        return actions;
+    }
+
+    SettingsDb::load(db)
+        .map(|settings| settings.completion_actions)
+        .unwrap_or_else(|e| {
+            log::error!("Failed to load settings: {}", e);
+            Vec::new()
+        })
+}
+
+/// Short description of an action, as stored in the action log
+pub fn describe(action: &CompletionAction) -> String {
+    match action {
+        CompletionAction::Move { dir } => format!("move to {}", dir),
+        CompletionAction::Copy { dir } => format!("copy to {}", dir),
+        CompletionAction::Command { command } => format!("run {}", command),
+        CompletionAction::Open => "open".to_string(),
+        CompletionAction::Extract {
+            target,
+            delete_archive,
+        } => {
+            let mut text = match target {
+                ExtractTarget::Sibling => "extract".to_string(),
+                ExtractTarget::Category => "extract into the category folder".to_string(),
+            };
+            if *delete_archive {
+                text.push_str(" and delete the archive");
+            }
+            text
+        }
+    }
+}
+
+/// Run `actions` on a finished download and log each outcome
+///
+/// Returns each action that ran with its log entry. A move updates
+/// `download.save_path`.
+pub async fn run<'a>(
+    db: &Database,
+    download: &mut Download,
+    actions: &'a [CompletionAction],
+    progress: ExtractProgress,
+) -> Vec<(&'a CompletionAction, ActionLogEntry)> {
+    let mut entries = Vec::new();
+    for action in actions {
+        let outcome = match action {
+            CompletionAction::Move { dir } => move_to(download, Path::new(dir)),
+            CompletionAction::Copy { dir } => copy_to(download, Path::new(dir)),
+            CompletionAction::Command { command } => run_command(download, command).await,
+            CompletionAction::Open => open_file(download),
+            CompletionAction::Extract { .. } if !extract::is_archive(&download.name) => continue,
+            CompletionAction::Extract {
+                target,
+                delete_archive,
+            } => extract_archive(db, download, *target, *delete_archive, progress.clone()).await,
+        };
+
+        let entry = ActionLogEntry {
+            action: describe(action),
+            success: outcome.is_ok(),
+            message: outcome.unwrap_or_else(|e| e.to_string()),
+            ran_at: chrono::Utc::now().timestamp(),
+        };
+        if !entry.success {
+            log::warn!(
+                "{} failed for {}: {}",
+                entry.action,
+                download.name,
+                entry.message
+            );
+        }
+        if let Err(e) = DownloadsDb::log_action(db, &download.gid, &entry) {
+            log::error!("Failed to record completion action: {}", e);
+        }
+        entries.push((action, entry));
+    }
+    entries
+}
+
+/// The file or folder a finished download wrote
+fn downloaded_path(download: &Download) -> Result<PathBuf> {
+    let path = Path::new(&download.save_path).join(&download.name);
+    if !path.exists() {
+        return Err(Error::NotFound(path.display().to_string()));
+    }
+    Ok(path)
+}
+
+/// Where a download goes in `dir`; never an existing file
+fn target_in(download: &Download, dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let target = dir.join(&download.name);
+    if target.exists() {
+        return Err(Error::InvalidInput(format!(
+            "{} already exists",
+            target.display()
+        )));
+    }
+    Ok(target)
+}
+
+fn move_to(download: &mut Download, dir: &Path) -> Result<String> {
+    // The engine keeps seeding from where the torrent was downloaded
+    if matches!(
+        download.download_type,
+        DownloadType::Torrent | DownloadType::Magnet
+    ) {
+        return Err(Error::InvalidInput(
+            "torrents are seeded in place; copy them instead".to_string(),
+        ));
+    }
+
+    let source = downloaded_path(download)?;
+    let target = target_in(download, dir)?;
+    // Renaming fails across filesystems
+    if std::fs::rename(&source, &target).is_err() {
+        copy_path(&source, &target)?;
+        if source.is_dir() {
+            std::fs::remove_dir_all(&source)?;
+        } else {
+            std::fs::remove_file(&source)?;
+        }
+    }
+
+    download.save_path = dir.to_string_lossy().to_string();
+    Ok(format!("moved to {}", target.display()))
+}
+
+fn copy_to(download: &Download, dir: &Path) -> Result<String> {
+    let source = downloaded_path(download)?;
+    let target = target_in(download, dir)?;
+    copy_path(&source, &target)?;
+    Ok(format!("copied to {}", target.display()))
+}
+
+fn copy_path(source: &Path, target: &Path) -> Result<()> {
+    if !source.is_dir() {
+        std::fs::copy(source, target)?;
+        return Ok(());
+    }
+    std::fs::create_dir_all(target)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        copy_path(&entry.path(), &target.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Run a command through `sh -c` in the download's folder
+async fn run_command(download: &Download, command: &str) -> Result<String> {
+    let path = Path::new(&download.save_path).join(&download.name);
+    let url = download
+        .url
+        .as_deref()
+        .or(download.magnet_uri.as_deref())
+        .unwrap_or_default();
+
+    let output = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(&download.save_path)
+        .env("GOSH_GID", &download.gid)
+        .env("GOSH_PATH", &path)
+        .env("GOSH_NAME", &download.name)
+        .env("GOSH_URL", url)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+
+    if output.status.success() {
+        return Ok(output.status.to_string());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.trim();
+    let skip = stderr.chars().count().saturating_sub(STDERR_TAIL);
+    let tail: String = stderr.chars().skip(skip).collect();
+    Err(Error::Engine(format!(
+        "command failed with {}: {}",
+        output.status, tail
+    )))
+}
+
+/// Unpack an archive next to itself or into its category's folder
+///
+/// Downloads without a category fall back to the folder next to the
+/// archive. Torrents keep their archive, since they are still seeding it.
+async fn extract_archive(
+    db: &Database,
+    download: &Download,
+    target: ExtractTarget,
+    delete_archive: bool,
+    progress: ExtractProgress,
+) -> Result<String> {
+    let archive = downloaded_path(download)?;
+    let category_dir = match target {
+        ExtractTarget::Category => download
+            .category
+            .as_deref()
+            .and_then(|name| CategoriesDb::get_by_name(db, name).ok().flatten())
+            .map(|category| category.save_dir)
+            .filter(|dir| !dir.is_empty()),
+        ExtractTarget::Sibling => None,
+    };
+    let (dest, merge) = match category_dir {
+        Some(dir) => (PathBuf::from(dir), true),
+        None => (extract::sibling_dir(&archive)?, false),
+    };
+
+    let (source, target_dir) = (archive.clone(), dest.clone());
+    tokio::task::spawn_blocking(move || extract::extract(&source, &target_dir, merge, &*progress))
+        .await
+        .map_err(|e| Error::Engine(format!("Extraction task failed: {}", e)))??;
+
+    if !delete_archive {
+        return Ok(format!("extracted to {}", dest.display()));
+    }
+    if matches!(
+        download.download_type,
+        DownloadType::Torrent | DownloadType::Magnet
+    ) {
+        return Ok(format!(
+            "extracted to {}; archive kept for seeding",
+            dest.display()
+        ));
+    }
+    std::fs::remove_file(&archive)?;
+    Ok(format!("extracted to {}; archive deleted", dest.display()))
+}
+
+fn open_file(download: &Download) -> Result<String> {
+    let path = downloaded_path(download)?;
+    open::that_detached(&path)?;
+    Ok(format!("opened {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_actions_run_in_order_and_are_logged() {
+        let db = crate::db::in_memory();
+        let root = std::env::temp_dir().join(format!("gosh-fetch-{}", uuid::Uuid::new_v4()));
+        let incoming = root.join("incoming");
+        std::fs::create_dir_all(&incoming).unwrap();
+        std::fs::write(incoming.join("a.txt"), b"abc").unwrap();
+
+        let mut download = Download {
+            gid: "gid-1".to_string(),
+            name: "a.txt".to_string(),
+            url: Some("https://example.com/a.txt".to_string()),
+            save_path: incoming.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let done = root.join("done");
+        let actions = [
+            CompletionAction::Copy {
+                dir: root.join("backup").to_string_lossy().to_string(),
+            },
+            CompletionAction::Move {
+                dir: done.to_string_lossy().to_string(),
+            },
+            CompletionAction::Command {
+                command: "test \"$GOSH_PATH\" = \"$PWD/a.txt\" && test -n \"$GOSH_URL\""
+                    .to_string(),
+            },
+            CompletionAction::Command {
+                command: "echo nope >&2; exit 3".to_string(),
+            },
+            // Not an archive, so skipped without a log entry
+            CompletionAction::Extract {
+                target: ExtractTarget::Sibling,
+                delete_archive: true,
+            },
+            CompletionAction::Copy {
+                dir: root.join("backup").to_string_lossy().to_string(),
+            },
+        ];
+
+        let entries = run(&db, &mut download, &actions, Arc::new(|_, _| {})).await;
+        let outcomes: Vec<bool> = entries.iter().map(|(_, e)| e.success).collect();
+        assert_eq!(outcomes, [true, true, true, false, false]);
+        assert!(entries[3].1.message.ends_with("nope"));
+        assert!(entries[4].1.message.contains("already exists"));
+
+        assert_eq!(download.save_path, done.to_string_lossy());
+        assert!(done.join("a.txt").is_file());
+        assert!(root.join("backup/a.txt").is_file());
+        assert!(!incoming.join("a.txt").exists());
+
+        let logged = DownloadsDb::get_action_log(&db, "gid-1").unwrap();
+        assert_eq!(logged.len(), 5);
+        assert_eq!(logged[1].action, format!("move to {}", done.display()));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! Filename conflicts
+//!
+//! Before an HTTP download is handed to the engine, the file it resolves to
+//! is checked against its `ConflictPolicy`. Torrents are not checked: files
+//! already in their save directory are what they resume from. Neither are
+//! downloads added again into their own file after a restart, which is what
+//! the `Resume` policy does with a file that was already there.
+
+use crate::types::{ConflictOutcome, ConflictPolicy};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// What to do with a download whose file may already exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Nothing is in the way
+    Free,
+    /// Save under this name instead
+    Rename(String),
+    /// Replace the existing file; it is set aside until the download is
+    /// added and put back if adding fails
+    Overwrite,
+    /// Do not download; the existing file stays
+    Skip,
+    /// Download into the existing file, as `EngineAdapter::readd_download`
+    /// does when a download is restored
+    Resume,
+}
+
+impl Resolution {
+    /// The outcome recorded with the download
+    pub fn outcome(&self) -> Option<ConflictOutcome> {
+        match self {
+            Resolution::Free => None,
+            Resolution::Rename(_) => Some(ConflictOutcome::Renamed),
+            Resolution::Overwrite => Some(ConflictOutcome::Overwritten),
+            Resolution::Skip => Some(ConflictOutcome::Skipped),
+            Resolution::Resume => Some(ConflictOutcome::Resumed),
+        }
+    }
+}
+
+/// Apply `policy` to saving `name` in `dir`
+///
+/// Anything already at the path counts, including folders, which are only
+/// ever renamed around. So do the paths in `claimed`, taken by downloads
+/// added earlier in the same batch whose files may not exist yet; those are
+/// renamed around too, as there is nothing there to replace or keep.
+pub fn resolve(
+    dir: &Path,
+    name: &str,
+    policy: ConflictPolicy,
+    claimed: &HashSet<PathBuf>,
+) -> Resolution {
+    let path = dir.join(name);
+    if claimed.contains(&path) {
+        return Resolution::Rename(free_name(dir, name, claimed));
+    }
+    if !path.exists() {
+        return Resolution::Free;
+    }
+    match policy {
+        ConflictPolicy::Rename => Resolution::Rename(free_name(dir, name, claimed)),
+        _ if path.is_dir() => Resolution::Rename(free_name(dir, name, claimed)),
+        ConflictPolicy::Overwrite => Resolution::Overwrite,
+        ConflictPolicy::Skip => Resolution::Skip,
+        ConflictPolicy::Resume => Resolution::Resume,
+    }
+}
+
+/// The first of `name (1).ext`, `name (2).ext`, ... neither taken in `dir`
+/// nor in `claimed`
+///
+/// `.tar.gz` and other `.tar.*` extensions are kept together.
+pub fn free_name(dir: &Path, name: &str, claimed: &HashSet<PathBuf>) -> String {
+    let (stem, extension) = split_extension(name);
+    let mut n = 1;
+    loop {
+        let candidate = format!("{} ({}){}", stem, n, extension);
+        let path = dir.join(&candidate);
+        if !path.exists() && !claimed.contains(&path) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// A file moved out of the way of a download that replaces it
+#[derive(Debug)]
+pub struct SetAside {
+    original: PathBuf,
+    moved: PathBuf,
+}
+
+impl SetAside {
+    /// Move the file at `path` to a hidden name next to it
+    pub fn new(path: &Path) -> std::io::Result<Self> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let moved = path.with_file_name(format!(".{}.{}.old", name, uuid::Uuid::new_v4()));
+        std::fs::rename(path, &moved)?;
+        Ok(Self {
+            original: path.to_path_buf(),
+            moved,
+        })
+    }
+
+    /// Put the file back where it was
+    pub fn restore(self) -> std::io::Result<()> {
+        std::fs::rename(&self.moved, &self.original)
+    }
+
+    /// Delete the file now that it has been replaced
+    pub fn discard(self) -> std::io::Result<()> {
+        std::fs::remove_file(&self.moved)
+    }
+}
+
+fn split_extension(name: &str) -> (&str, &str) {
+    let Some(dot) = name.rfind('.').filter(|&i| i > 0) else {
+        return (name, "");
+    };
+    let stem = &name[..dot];
+    match stem.len().checked_sub(4) {
+        Some(i) if i > 0 && stem.is_char_boundary(i) && stem[i..].eq_ignore_ascii_case(".tar") => {
+            name.split_at(i)
+        }
+        _ => name.split_at(dot),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conflicts_are_resolved_by_policy() {
+        let dir = std::env::temp_dir().join(format!("gosh-fetch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("photos")).unwrap();
+        for name in ["distro.iso", "distro (1).iso", "src.tar.gz", "README"] {
+            std::fs::write(dir.join(name), b"x").unwrap();
+        }
+        let resolve = |name, policy| resolve(&dir, name, policy, &HashSet::new());
+
+        let rename = ConflictPolicy::Rename;
+        assert_eq!(resolve("new.iso", rename), Resolution::Free);
+        assert_eq!(
+            resolve("distro.iso", rename),
+            Resolution::Rename("distro (2).iso".to_string())
+        );
+        assert_eq!(
+            resolve("src.tar.gz", rename),
+            Resolution::Rename("src (1).tar.gz".to_string())
+        );
+        assert_eq!(
+            resolve("README", rename),
+            Resolution::Rename("README (1)".to_string())
+        );
+        assert_eq!(free_name(&dir, ".bashrc", &HashSet::new()), ".bashrc (1)");
+
+        assert_eq!(
+            resolve("distro.iso", ConflictPolicy::Overwrite),
+            Resolution::Overwrite
+        );
+        assert_eq!(
+            resolve("distro.iso", ConflictPolicy::Skip).outcome(),
+            Some(ConflictOutcome::Skipped)
+        );
+        let resumed = resolve("distro.iso", ConflictPolicy::Resume);
+        assert_eq!(resumed, Resolution::Resume);
+        assert_eq!(resumed.outcome(), Some(ConflictOutcome::Resumed));
+        assert_eq!("resume".parse(), Ok(ConflictPolicy::Resume));
+        assert_eq!(
+            resolve("photos", ConflictPolicy::Overwrite),
+            Resolution::Rename("photos (1)".to_string())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_names_claimed_in_a_batch_are_taken() {
+        let dir = std::env::temp_dir().join(format!("gosh-fetch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("distro (1).iso"), b"x").unwrap();
+
+        // Three URLs in one batch all named distro.iso, none on disk yet
+        let mut claimed = HashSet::new();
+        let mut names = Vec::new();
+        for _ in 0..3 {
+            let name = match resolve(&dir, "distro.iso", ConflictPolicy::Skip, &claimed) {
+                Resolution::Free => "distro.iso".to_string(),
+                Resolution::Rename(name) => name,
+                other => panic!("unexpected {:?}", other),
+            };
+            claimed.insert(dir.join(&name));
+            names.push(name);
+        }
+        assert_eq!(names, ["distro.iso", "distro (2).iso", "distro (3).iso"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replaced_file_survives_until_discarded() {
+        let dir = std::env::temp_dir().join(format!("gosh-fetch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("distro.iso");
+        std::fs::write(&path, b"old").unwrap();
+
+        let aside = SetAside::new(&path).unwrap();
+        assert!(!path.exists());
+        aside.restore().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+
+        let aside = SetAside::new(&path).unwrap();
+        std::fs::write(&path, b"new").unwrap();
+        aside.discard().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
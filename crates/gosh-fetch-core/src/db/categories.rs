@@ -0,0 +1,156 @@
+//! Categories database operations
+
+use crate::db::Database;
+use crate::error::{Error, Result};
+use crate::types::Category;
+use rusqlite::{params, OptionalExtension};
+
+/// Categories database operations
+pub struct CategoriesDb;
+
+impl CategoriesDb {
+    /// Get all categories in the order their rules are tried
+    pub fn list(db: &Database) -> Result<Vec<Category>> {
+        let rows: Vec<(i64, String, String, String, String)> = db.with_conn(|conn| {
+            let mut stmt = conn
+                .prepare("SELECT id, name, save_dir, options, rules FROM categories ORDER BY id")?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows)
+        })?;
+
+        rows.into_iter()
+            .map(|(id, name, save_dir, options, rules)| {
+                Ok(Category {
+                    id,
+                    name,
+                    save_dir,
+                    options: serde_json::from_str(&options)?,
+                    rules: serde_json::from_str(&rules)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get a category by name
+    pub fn get_by_name(db: &Database, name: &str) -> Result<Option<Category>> {
+        Ok(Self::list(db)?
+            .into_iter()
+            .find(|c| c.name.eq_ignore_ascii_case(name)))
+    }
+
+    /// Insert a category (id 0) or update an existing one
+    ///
+    /// Renaming a category relabels the downloads filed under it.
+    pub fn save(db: &Database, category: &Category) -> Result<i64> {
+        let name = category.name.trim();
+        if name.is_empty() {
+            return Err(Error::InvalidInput("Category name is required".to_string()));
+        }
+        let options = serde_json::to_string(&category.options)?;
+        let rules = serde_json::to_string(&category.rules)?;
+
+        db.with_conn_mut(|conn| {
+            let tx = conn.transaction()?;
+            let id = if category.id == 0 {
+                tx.execute(
+                    "INSERT INTO categories (name, save_dir, options, rules) VALUES (?1, ?2, ?3, ?4)",
+                    params![name, category.save_dir, options, rules],
+                )?;
+                tx.last_insert_rowid()
+            } else {
+                let old_name: Option<String> = tx
+                    .query_row(
+                        "SELECT name FROM categories WHERE id = ?1",
+                        params![category.id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                tx.execute(
+                    "UPDATE categories SET name = ?1, save_dir = ?2, options = ?3, rules = ?4
+                     WHERE id = ?5",
+                    params![name, category.save_dir, options, rules, category.id],
+                )?;
+                if let Some(old_name) = old_name.filter(|old| old != name) {
+                    tx.execute(
+                        "UPDATE downloads SET category = ?1 WHERE category = ?2",
+                        params![name, old_name],
+                    )?;
+                }
+                category.id
+            };
+            tx.commit()?;
+            Ok(id)
+        })
+    }
+
+    /// Delete a category; its downloads become uncategorized
+    pub fn delete(db: &Database, id: i64) -> Result<()> {
+        db.with_conn_mut(|conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "UPDATE downloads SET category = NULL
+                 WHERE category = (SELECT name FROM categories WHERE id = ?1)",
+                params![id],
+            )?;
+            tx.execute("DELETE FROM categories WHERE id = ?1", params![id])?;
+            tx.commit()?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DownloadsDb;
+    use crate::types::{CategoryRules, Download};
+
+    #[test]
+    fn test_rename_relabels_downloads() {
+        let db = crate::db::connection::in_memory();
+        let mut category = Category {
+            name: "Video".to_string(),
+            save_dir: "/downloads/video".to_string(),
+            rules: CategoryRules {
+                extensions: vec!["mkv".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        category.id = CategoriesDb::save(&db, &category).unwrap();
+
+        let download = Download {
+            gid: "abc".to_string(),
+            name: "movie.mkv".to_string(),
+            category: Some("Video".to_string()),
+            ..Default::default()
+        };
+        DownloadsDb::save(&db, &download).unwrap();
+
+        category.name = "Movies".to_string();
+        CategoriesDb::save(&db, &category).unwrap();
+
+        let list = CategoriesDb::list(&db).unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].rules.extensions, ["mkv"]);
+        assert_eq!(
+            DownloadsDb::get_by_category(&db, "Movies").unwrap().len(),
+            1
+        );
+
+        CategoriesDb::delete(&db, category.id).unwrap();
+        assert!(CategoriesDb::list(&db).unwrap().is_empty());
+        let download = DownloadsDb::get_by_gid(&db, "abc").unwrap().unwrap();
+        assert_eq!(download.category, None);
+    }
+}
@@ -1,12 +1,12 @@
 //! Database connection management
 
+use super::migrations;
 use crate::error::{Error, Result};
 use rusqlite::Connection;
-use std::path::PathBuf;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
-const SCHEMA: &str = include_str!("../../../../migrations/001_initial.sql");
-
 /// Get the database path
 pub fn get_db_path() -> PathBuf {
     let data_dir = dirs::data_dir()
@@ -21,17 +21,34 @@ pub fn get_db_path() -> PathBuf {
 pub fn init_database() -> Result<Database> {
     let path = get_db_path();
     log::info!("Initializing database at: {:?}", path);
+    open_database(&path)
+}
 
-    let conn = Connection::open(&path)?;
+/// Open and migrate the database at `path`
+pub fn open_database(path: &Path) -> Result<Database> {
+    let mut conn = Connection::open(path)?;
+
+    // Stored download options can include cookies and credentials
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
 
     // Run migrations
-    conn.execute_batch(SCHEMA)?;
+    migrations::run(&mut conn)?;
 
     Ok(Database {
         conn: Arc::new(Mutex::new(conn)),
     })
 }
 
+/// Open a migrated in-memory database for tests
+#[cfg(test)]
+pub(crate) fn in_memory() -> Database {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrations::run(&mut conn).unwrap();
+    Database {
+        conn: Arc::new(Mutex::new(conn)),
+    }
+}
+
 /// Database wrapper with thread-safe connection
 #[derive(Clone, Debug)]
 pub struct Database {
@@ -2,23 +2,55 @@
 
 use crate::db::Database;
 use crate::error::Result;
-use crate::types::{Download, DownloadState, DownloadType};
-use rusqlite::params;
+use crate::types::{
+    ActionLogEntry, ChecksumResult, ConflictOutcome, Download, DownloadOptions, DownloadState,
+    DownloadType,
+};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+/// Request headers whose values are credentials
+const SECRET_HEADERS: &[&str] = &["authorization", "cookie", "proxy-authorization"];
+
+/// Option values stored apart from the rest so they can be dropped early
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OptionSecrets {
+    cookies: Option<String>,
+    headers: Vec<String>,
+    proxy: Option<String>,
+}
 
 /// Downloads database operations
 pub struct DownloadsDb;
 
 impl DownloadsDb {
     /// Save a download to the database
+    ///
+    /// A download without a category, checksum result or conflict outcome
+    /// keeps the one already recorded, and a metalink download stays one,
+    /// since the engine knows none of them.
     pub fn save(db: &Database, download: &Download) -> Result<i64> {
+        let checksum = download
+            .checksum
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
         db.with_conn(|conn| {
             conn.execute(
                 r#"
                 INSERT OR REPLACE INTO downloads
                 (gid, name, url, magnet_uri, info_hash, download_type, status,
                  total_size, completed_size, download_speed, upload_speed,
-                 save_path, created_at, completed_at, error_message, selected_files)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+                 save_path, created_at, completed_at, error_message, selected_files, category,
+                 scheduled_start, checksum_result, conflict_outcome)
+                VALUES (?1, ?2, ?3, ?4, ?5,
+                        CASE WHEN ?6 = 'http' AND (SELECT download_type FROM downloads
+                                                   WHERE gid = ?1) = 'metalink'
+                             THEN 'metalink' ELSE ?6 END,
+                        ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                        COALESCE(?17, (SELECT category FROM downloads WHERE gid = ?1)), ?18,
+                        COALESCE(?19, (SELECT checksum_result FROM downloads WHERE gid = ?1)),
+                        COALESCE(?20, (SELECT conflict_outcome FROM downloads WHERE gid = ?1)))
                 "#,
                 params![
                     download.gid,
@@ -42,6 +74,10 @@ impl DownloadsDb {
                             .collect::<Vec<_>>()
                             .join(",")
                     }),
+                    download.category,
+                    download.scheduled_start,
+                    checksum,
+                    download.conflict.map(|c| c.to_string()),
                 ],
             )?;
             Ok(conn.last_insert_rowid())
@@ -55,14 +91,13 @@ impl DownloadsDb {
                 r#"
                 SELECT id, gid, name, url, magnet_uri, info_hash, download_type, status,
                        total_size, completed_size, download_speed, upload_speed,
-                       save_path, created_at, completed_at, error_message, selected_files
+                       save_path, created_at, completed_at, error_message, selected_files,
+                       category, scheduled_start, checksum_result, conflict_outcome
                 FROM downloads WHERE gid = ?1
                 "#,
             )?;
 
-            let result = stmt.query_row(params![gid], |row| {
-                Ok(row_to_download(row)?)
-            });
+            let result = stmt.query_row(params![gid], row_to_download);
 
             match result {
                 Ok(download) => Ok(Some(download)),
@@ -79,7 +114,8 @@ impl DownloadsDb {
                 r#"
                 SELECT id, gid, name, url, magnet_uri, info_hash, download_type, status,
                        total_size, completed_size, download_speed, upload_speed,
-                       save_path, created_at, completed_at, error_message, selected_files
+                       save_path, created_at, completed_at, error_message, selected_files,
+                       category, scheduled_start, checksum_result, conflict_outcome
                 FROM downloads
                 WHERE status = 'complete'
                 ORDER BY completed_at DESC
@@ -88,7 +124,29 @@ impl DownloadsDb {
             )?;
 
             let downloads = stmt
-                .query_map(params![limit], |row| row_to_download(row))?
+                .query_map(params![limit], row_to_download)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(downloads)
+        })
+    }
+
+    /// Get every recorded download, oldest first
+    pub fn get_all(db: &Database) -> Result<Vec<Download>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, gid, name, url, magnet_uri, info_hash, download_type, status,
+                       total_size, completed_size, download_speed, upload_speed,
+                       save_path, created_at, completed_at, error_message, selected_files,
+                       category, scheduled_start, checksum_result, conflict_outcome
+                FROM downloads
+                ORDER BY id
+                "#,
+            )?;
+
+            let downloads = stmt
+                .query_map([], row_to_download)?
                 .collect::<rusqlite::Result<Vec<_>>>()?;
 
             Ok(downloads)
@@ -102,21 +160,166 @@ impl DownloadsDb {
                 r#"
                 SELECT id, gid, name, url, magnet_uri, info_hash, download_type, status,
                        total_size, completed_size, download_speed, upload_speed,
-                       save_path, created_at, completed_at, error_message, selected_files
+                       save_path, created_at, completed_at, error_message, selected_files,
+                       category, scheduled_start, checksum_result, conflict_outcome
                 FROM downloads
-                WHERE status NOT IN ('complete', 'removed')
+                WHERE status NOT IN ('complete', 'removed', 'scheduled')
                 ORDER BY created_at DESC
                 "#,
             )?;
 
             let downloads = stmt
-                .query_map([], |row| row_to_download(row))?
+                .query_map([], row_to_download)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(downloads)
+        })
+    }
+
+    /// Get downloads filed under a category
+    pub fn get_by_category(db: &Database, category: &str) -> Result<Vec<Download>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, gid, name, url, magnet_uri, info_hash, download_type, status,
+                       total_size, completed_size, download_speed, upload_speed,
+                       save_path, created_at, completed_at, error_message, selected_files,
+                       category, scheduled_start, checksum_result, conflict_outcome
+                FROM downloads
+                WHERE category = ?1
+                ORDER BY created_at DESC
+                "#,
+            )?;
+
+            let downloads = stmt
+                .query_map(params![category], row_to_download)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(downloads)
+        })
+    }
+
+    /// Get downloads waiting for their scheduled start, earliest first
+    pub fn get_scheduled(db: &Database) -> Result<Vec<Download>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, gid, name, url, magnet_uri, info_hash, download_type, status,
+                       total_size, completed_size, download_speed, upload_speed,
+                       save_path, created_at, completed_at, error_message, selected_files,
+                       category, scheduled_start, checksum_result, conflict_outcome
+                FROM downloads
+                WHERE status = 'scheduled'
+                ORDER BY scheduled_start
+                "#,
+            )?;
+
+            let downloads = stmt
+                .query_map([], row_to_download)?
                 .collect::<rusqlite::Result<Vec<_>>>()?;
 
             Ok(downloads)
         })
     }
 
+    /// Move a scheduled download to a new start time
+    pub fn set_scheduled_start(db: &Database, gid: &str, start: i64) -> Result<()> {
+        db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE downloads SET scheduled_start = ?1 WHERE gid = ?2",
+                params![start, gid],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get the category of every categorized download, by GID
+    pub fn get_category_assignments(db: &Database) -> Result<Vec<(String, String)>> {
+        db.with_conn(|conn| {
+            let mut stmt =
+                conn.prepare("SELECT gid, category FROM downloads WHERE category IS NOT NULL")?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows)
+        })
+    }
+
+    /// What the conflict policy did for each download it applied to
+    pub fn get_conflict_outcomes(db: &Database) -> Result<Vec<(String, ConflictOutcome)>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT gid, conflict_outcome FROM downloads WHERE conflict_outcome IS NOT NULL",
+            )?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows
+                .into_iter()
+                .filter_map(|(gid, outcome)| Some((gid, outcome.parse().ok()?)))
+                .collect())
+        })
+    }
+
+    /// GIDs of the downloads of one type
+    pub fn get_gids_of_type(db: &Database, download_type: DownloadType) -> Result<Vec<String>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT gid FROM downloads WHERE download_type = ?1")?;
+            let gids = stmt
+                .query_map(params![download_type.to_string()], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(gids)
+        })
+    }
+
+    /// Record the outcome of a checksum verification
+    pub fn set_checksum_result(db: &Database, gid: &str, result: &ChecksumResult) -> Result<()> {
+        let json = serde_json::to_string(result)?;
+        db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE downloads SET checksum_result = ?1 WHERE gid = ?2",
+                params![json, gid],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Record the outcome of a completion action
+    pub fn log_action(db: &Database, gid: &str, entry: &ActionLogEntry) -> Result<()> {
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO action_log (gid, action, success, message, ran_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![gid, entry.action, entry.success, entry.message, entry.ran_at],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Completion actions run for a download, oldest first
+    pub fn get_action_log(db: &Database, gid: &str) -> Result<Vec<ActionLogEntry>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT action, success, message, ran_at FROM action_log
+                 WHERE gid = ?1 ORDER BY id",
+            )?;
+            let entries = stmt
+                .query_map(params![gid], |row| {
+                    Ok(ActionLogEntry {
+                        action: row.get(0)?,
+                        success: row.get(1)?,
+                        message: row.get(2)?,
+                        ran_at: row.get(3)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(entries)
+        })
+    }
+
     /// Update download status
     pub fn update_status(db: &Database, gid: &str, status: DownloadState) -> Result<()> {
         db.with_conn(|conn| {
@@ -139,10 +342,13 @@ impl DownloadsDb {
         })
     }
 
-    /// Delete a download record
+    /// Delete a download record with its stored options and torrent
     pub fn delete(db: &Database, gid: &str) -> Result<()> {
         db.with_conn(|conn| {
             conn.execute("DELETE FROM downloads WHERE gid = ?1", params![gid])?;
+            conn.execute("DELETE FROM download_options WHERE gid = ?1", params![gid])?;
+            conn.execute("DELETE FROM torrent_blobs WHERE gid = ?1", params![gid])?;
+            conn.execute("DELETE FROM action_log WHERE gid = ?1", params![gid])?;
             Ok(())
         })
     }
@@ -150,11 +356,128 @@ impl DownloadsDb {
     /// Clear all completed downloads
     pub fn clear_history(db: &Database) -> Result<()> {
         db.with_conn(|conn| {
+            conn.execute(
+                "DELETE FROM download_options WHERE gid IN
+                 (SELECT gid FROM downloads WHERE status = 'complete')",
+                [],
+            )?;
+            conn.execute(
+                "DELETE FROM torrent_blobs WHERE gid IN
+                 (SELECT gid FROM downloads WHERE status = 'complete')",
+                [],
+            )?;
+            conn.execute(
+                "DELETE FROM action_log WHERE gid IN
+                 (SELECT gid FROM downloads WHERE status = 'complete')",
+                [],
+            )?;
             conn.execute("DELETE FROM downloads WHERE status = 'complete'", [])?;
             Ok(())
         })
     }
 
+    /// Store the options a download was added with
+    ///
+    /// Cookies, credential headers and the proxy go to a separate column that
+    /// `clear_secrets` wipes once the download no longer needs them.
+    pub fn save_options(db: &Database, gid: &str, options: &DownloadOptions) -> Result<()> {
+        let mut options = options.clone();
+        let mut secrets = OptionSecrets {
+            cookies: options.cookies.take(),
+            headers: Vec::new(),
+            proxy: options.proxy.take(),
+        };
+        if let Some(headers) = options.header.take() {
+            let (secret, public): (Vec<String>, Vec<String>) =
+                headers.into_iter().partition(|h| is_secret_header(h));
+            secrets.headers = secret;
+            options.header = Some(public).filter(|h| !h.is_empty());
+        }
+
+        let options_json = serde_json::to_string(&options)?;
+        let secrets_json = if secrets.cookies.is_some()
+            || !secrets.headers.is_empty()
+            || secrets.proxy.is_some()
+        {
+            Some(serde_json::to_string(&secrets)?)
+        } else {
+            None
+        };
+
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO download_options (gid, options, secrets)
+                 VALUES (?1, ?2, ?3)",
+                params![gid, options_json, secrets_json],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get the stored options for a download, secrets included
+    pub fn get_options(db: &Database, gid: &str) -> Result<Option<DownloadOptions>> {
+        let row: Option<(String, Option<String>)> = db.with_conn(|conn| {
+            conn.query_row(
+                "SELECT options, secrets FROM download_options WHERE gid = ?1",
+                params![gid],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+        })?;
+
+        let Some((options_json, secrets_json)) = row else {
+            return Ok(None);
+        };
+
+        let mut options: DownloadOptions = serde_json::from_str(&options_json)?;
+        if let Some(secrets_json) = secrets_json {
+            let secrets: OptionSecrets = serde_json::from_str(&secrets_json)?;
+            options.cookies = secrets.cookies;
+            options.proxy = secrets.proxy;
+            if !secrets.headers.is_empty() {
+                options
+                    .header
+                    .get_or_insert_with(Vec::new)
+                    .extend(secrets.headers);
+            }
+        }
+        Ok(Some(options))
+    }
+
+    /// Keep the .torrent file a download was added from
+    pub fn save_torrent(db: &Database, gid: &str, data: &[u8]) -> Result<()> {
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO torrent_blobs (gid, data) VALUES (?1, ?2)",
+                params![gid, data],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get the .torrent file stored for a download
+    pub fn get_torrent(db: &Database, gid: &str) -> Result<Option<Vec<u8>>> {
+        db.with_conn(|conn| {
+            conn.query_row(
+                "SELECT data FROM torrent_blobs WHERE gid = ?1",
+                params![gid],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+    }
+
+    /// Drop the cookies, credential headers and proxy stored for a download
+    pub fn clear_secrets(db: &Database, gid: &str) -> Result<()> {
+        db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE download_options SET secrets = NULL WHERE gid = ?1",
+                params![gid],
+            )?;
+            Ok(())
+        })
+    }
+
     /// Get count of completed downloads
     pub fn count_completed(db: &Database) -> Result<i64> {
         db.with_conn(|conn| {
@@ -168,6 +491,14 @@ impl DownloadsDb {
     }
 }
 
+/// Whether a `Name: value` header carries credentials
+pub(crate) fn is_secret_header(header: &str) -> bool {
+    header
+        .split_once(':')
+        .map(|(name, _)| SECRET_HEADERS.contains(&name.trim().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
 fn row_to_download(row: &rusqlite::Row) -> rusqlite::Result<Download> {
     let download_type_str: String = row.get(6)?;
     let status_str: String = row.get(7)?;
@@ -197,5 +528,87 @@ fn row_to_download(row: &rusqlite::Row) -> rusqlite::Result<Download> {
                 .filter_map(|n| n.parse().ok())
                 .collect()
         }),
+        category: row.get(17)?,
+        scheduled_start: row.get(18)?,
+        checksum: row
+            .get::<_, Option<String>>(19)?
+            .and_then(|json| serde_json::from_str(&json).ok()),
+        conflict: row
+            .get::<_, Option<String>>(20)?
+            .and_then(|outcome| outcome.parse().ok()),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_secrets_are_stored_apart() {
+        let db = crate::db::connection::in_memory();
+        let options = DownloadOptions {
+            dir: Some("/tmp/isos".to_string()),
+            cookies: Some("session=abc".to_string()),
+            header: Some(vec![
+                "X-Trace: 1".to_string(),
+                "Authorization: Bearer token".to_string(),
+            ]),
+            ..Default::default()
+        };
+        DownloadsDb::save_options(&db, "abc", &options).unwrap();
+
+        let stored: String = db
+            .with_conn(|conn| {
+                conn.query_row(
+                    "SELECT options FROM download_options WHERE gid = 'abc'",
+                    [],
+                    |row| row.get(0),
+                )
+            })
+            .unwrap();
+        assert!(!stored.contains("session=abc"));
+        assert!(!stored.contains("Bearer"));
+
+        let restored = DownloadsDb::get_options(&db, "abc").unwrap().unwrap();
+        assert_eq!(restored.dir.as_deref(), Some("/tmp/isos"));
+        assert_eq!(restored.cookies.as_deref(), Some("session=abc"));
+        assert_eq!(restored.header.map(|h| h.len()), Some(2));
+
+        DownloadsDb::clear_secrets(&db, "abc").unwrap();
+        let cleared = DownloadsDb::get_options(&db, "abc").unwrap().unwrap();
+        assert_eq!(cleared.cookies, None);
+        assert_eq!(cleared.header, Some(vec!["X-Trace: 1".to_string()]));
+    }
+
+    #[test]
+    fn test_torrent_blob_follows_download() {
+        let db = crate::db::connection::in_memory();
+        let data = b"d4:infod4:name8:file.isoee".to_vec();
+        DownloadsDb::save_torrent(&db, "abc", &data).unwrap();
+        assert_eq!(DownloadsDb::get_torrent(&db, "abc").unwrap(), Some(data));
+
+        DownloadsDb::delete(&db, "abc").unwrap();
+        assert_eq!(DownloadsDb::get_torrent(&db, "abc").unwrap(), None);
+    }
+
+    #[test]
+    fn test_scheduled_downloads_are_not_restored() {
+        let db = crate::db::connection::in_memory();
+        let download = Download {
+            gid: "later".to_string(),
+            name: "file.iso".to_string(),
+            url: Some("https://example.com/file.iso".to_string()),
+            status: DownloadState::Scheduled,
+            scheduled_start: Some(2_000_000_000),
+            ..Default::default()
+        };
+        DownloadsDb::save(&db, &download).unwrap();
+        assert!(DownloadsDb::get_incomplete(&db).unwrap().is_empty());
+
+        DownloadsDb::set_scheduled_start(&db, "later", 42).unwrap();
+        let scheduled = DownloadsDb::get_scheduled(&db).unwrap();
+        assert_eq!(scheduled.len(), 1);
+        assert_eq!(scheduled[0].status, DownloadState::Scheduled);
+        assert_eq!(scheduled[0].scheduled_start, Some(42));
+    }
+}
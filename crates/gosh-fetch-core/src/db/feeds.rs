@@ -0,0 +1,191 @@
+//! Feed subscriptions database operations
+
+use crate::db::Database;
+use crate::error::{Error, Result};
+use crate::feeds::Filter;
+use crate::types::Feed;
+use rusqlite::{params, OptionalExtension, Row};
+
+const FEED_COLUMNS: &str = "id, url, title, enabled, include_pattern, exclude_pattern, category,
+                            poll_interval, last_checked, last_error";
+
+/// Feed subscriptions database operations
+pub struct FeedsDb;
+
+impl FeedsDb {
+    /// Get all feeds
+    pub fn list(db: &Database) -> Result<Vec<Feed>> {
+        db.with_conn(|conn| {
+            let mut stmt =
+                conn.prepare(&format!("SELECT {} FROM feeds ORDER BY id", FEED_COLUMNS))?;
+            let feeds = stmt
+                .query_map([], row_to_feed)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(feeds)
+        })
+    }
+
+    /// Get a feed by ID
+    pub fn get(db: &Database, id: i64) -> Result<Option<Feed>> {
+        db.with_conn(|conn| {
+            conn.query_row(
+                &format!("SELECT {} FROM feeds WHERE id = ?1", FEED_COLUMNS),
+                params![id],
+                row_to_feed,
+            )
+            .optional()
+        })
+    }
+
+    /// Enabled feeds whose poll interval has passed at `now`
+    pub fn get_due(db: &Database, now: i64) -> Result<Vec<Feed>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM feeds
+                 WHERE enabled = 1 AND (last_checked IS NULL OR last_checked + poll_interval <= ?1)
+                 ORDER BY id",
+                FEED_COLUMNS
+            ))?;
+            let feeds = stmt
+                .query_map(params![now], row_to_feed)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(feeds)
+        })
+    }
+
+    /// Insert a feed (id 0) or update an existing one
+    ///
+    /// The filter patterns are checked before anything is written.
+    pub fn save(db: &Database, feed: &Feed) -> Result<i64> {
+        let url = feed.url.trim();
+        if url.is_empty() {
+            return Err(Error::InvalidInput("Feed URL is required".to_string()));
+        }
+        Filter::new(feed)?;
+
+        db.with_conn(|conn| {
+            if feed.id == 0 {
+                conn.execute(
+                    "INSERT INTO feeds (url, title, enabled, include_pattern, exclude_pattern,
+                                        category, poll_interval)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        url,
+                        feed.title,
+                        feed.enabled,
+                        feed.include_pattern,
+                        feed.exclude_pattern,
+                        feed.category,
+                        feed.poll_interval as i64,
+                    ],
+                )?;
+                Ok(conn.last_insert_rowid())
+            } else {
+                conn.execute(
+                    "UPDATE feeds SET url = ?1, title = ?2, enabled = ?3, include_pattern = ?4,
+                                      exclude_pattern = ?5, category = ?6, poll_interval = ?7
+                     WHERE id = ?8",
+                    params![
+                        url,
+                        feed.title,
+                        feed.enabled,
+                        feed.include_pattern,
+                        feed.exclude_pattern,
+                        feed.category,
+                        feed.poll_interval as i64,
+                        feed.id,
+                    ],
+                )?;
+                Ok(feed.id)
+            }
+        })
+    }
+
+    /// Delete a feed and the record of its items
+    pub fn delete(db: &Database, id: i64) -> Result<()> {
+        db.with_conn_mut(|conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM feed_items WHERE feed_id = ?1", params![id])?;
+            tx.execute("DELETE FROM feeds WHERE id = ?1", params![id])?;
+            tx.commit()
+        })
+    }
+
+    /// Record the outcome of a check
+    pub fn set_checked(
+        db: &Database,
+        id: i64,
+        title: Option<&str>,
+        checked_at: i64,
+        error: Option<&str>,
+    ) -> Result<()> {
+        db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE feeds SET last_checked = ?1, last_error = ?2,
+                                  title = CASE WHEN title = '' THEN COALESCE(?3, '') ELSE title END
+                 WHERE id = ?4",
+                params![checked_at, error, title, id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Whether an item with this GUID or URL was already downloaded
+    pub fn is_seen(db: &Database, feed_id: i64, guid: &str, url: &str) -> Result<bool> {
+        db.with_conn(|conn| {
+            conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM feed_items
+                               WHERE feed_id = ?1 AND (guid = ?2 OR url = ?3))",
+                params![feed_id, guid, url],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    /// Items already downloaded from a feed, as `(guid, url, title)`
+    pub fn seen_items(db: &Database, feed_id: i64) -> Result<Vec<(String, String, String)>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT guid, url, title FROM feed_items WHERE feed_id = ?1 ORDER BY added_at",
+            )?;
+            let items = stmt
+                .query_map(params![feed_id], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(items)
+        })
+    }
+
+    /// Remember that an item was downloaded
+    pub fn mark_seen(
+        db: &Database,
+        feed_id: i64,
+        guid: &str,
+        url: &str,
+        title: &str,
+    ) -> Result<()> {
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO feed_items (feed_id, guid, url, title) VALUES (?1, ?2, ?3, ?4)",
+                params![feed_id, guid, url, title],
+            )?;
+            Ok(())
+        })
+    }
+}
+
+fn row_to_feed(row: &Row) -> rusqlite::Result<Feed> {
+    Ok(Feed {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        title: row.get(2)?,
+        enabled: row.get(3)?,
+        include_pattern: row.get(4)?,
+        exclude_pattern: row.get(5)?,
+        category: row.get(6)?,
+        poll_interval: row.get::<_, i64>(7)?.max(0) as u64,
+        last_checked: row.get(8)?,
+        last_error: row.get(9)?,
+    })
+}
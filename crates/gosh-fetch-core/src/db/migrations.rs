@@ -0,0 +1,214 @@
+//! Schema migrations
+//!
+//! Numbered SQL files from `migrations/` are applied in order, each inside a
+//! transaction that also bumps `PRAGMA user_version`. Databases created before
+//! versioning report version 0; `001_initial.sql` only uses `IF NOT EXISTS`
+//! and `INSERT OR IGNORE`, so it is safe to apply to them.
+
+use crate::error::{Error, Result};
+use rusqlite::Connection;
+
+/// A single forward migration
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../../../../migrations/001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "drop_unused_columns",
+        sql: include_str!("../../../../migrations/002_drop_unused_columns.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "download_options",
+        sql: include_str!("../../../../migrations/003_download_options.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "torrent_blobs",
+        sql: include_str!("../../../../migrations/004_torrent_blobs.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "categories",
+        sql: include_str!("../../../../migrations/005_categories.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "scheduled_start",
+        sql: include_str!("../../../../migrations/006_scheduled_start.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "feeds",
+        sql: include_str!("../../../../migrations/007_feeds.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "checksum_results",
+        sql: include_str!("../../../../migrations/008_checksum_results.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "action_log",
+        sql: include_str!("../../../../migrations/009_action_log.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "watch_folders",
+        sql: include_str!("../../../../migrations/010_watch_folders.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "conflict_outcome",
+        sql: include_str!("../../../../migrations/011_conflict_outcome.sql"),
+    },
+];
+
+/// Schema version this build writes
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Read the schema version stored in the database
+pub fn schema_version(conn: &Connection) -> Result<i64> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Bring the database up to the latest schema
+pub fn run(conn: &mut Connection) -> Result<()> {
+    apply(conn, MIGRATIONS)
+}
+
+fn apply(conn: &mut Connection, migrations: &[Migration]) -> Result<()> {
+    let current = schema_version(conn)?;
+    let latest = migrations.last().map(|m| m.version).unwrap_or(0);
+
+    // There are no down-migrations; never touch a schema from a newer build
+    if current > latest {
+        return Err(Error::Database(format!(
+            "database schema version {} is newer than this build supports ({})",
+            current, latest
+        )));
+    }
+
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        log::info!(
+            "Applying migration {:03}_{}",
+            migration.version,
+            migration.name
+        );
+
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql).map_err(|e| {
+            Error::Database(format!(
+                "migration {:03}_{} failed: {}",
+                migration.version, migration.name, e
+            ))
+        })?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column_names(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap();
+        stmt.query_map([], |row| row.get(1))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_migrations_are_sequential() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i64 + 1);
+        }
+    }
+
+    #[test]
+    fn test_upgrade_v1_database() {
+        // A database written by 2.1.0: schema applied, user_version never set
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.execute(
+            "INSERT INTO downloads (gid, name, save_path) VALUES ('abc', 'file.iso', '/tmp')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO trackers (url, last_checked, is_working) VALUES ('udp://t:1337', NULL, 0)",
+            [],
+        )
+        .unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+
+        run(&mut conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        let name: String = conn
+            .query_row("SELECT name FROM downloads WHERE gid = 'abc'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(name, "file.iso");
+        let themes: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM settings WHERE key = 'theme'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(themes, 0);
+        assert_eq!(column_names(&conn, "trackers"), ["id", "url", "enabled"]);
+
+        // Running again is a no-op
+        run(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+        assert!(run(&mut conn).is_err());
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration {
+                version: 1,
+                name: "good",
+                sql: "CREATE TABLE a (id INTEGER);",
+            },
+            Migration {
+                version: 2,
+                name: "bad",
+                sql: "CREATE TABLE b (id INTEGER); INSERT INTO missing VALUES (1);",
+            },
+        ];
+
+        assert!(apply(&mut conn, &migrations).is_err());
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        assert!(column_names(&conn, "b").is_empty());
+    }
+}
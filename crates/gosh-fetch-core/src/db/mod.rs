@@ -1,9 +1,19 @@
 //! Database module - SQLite persistence layer
 
+mod categories;
 mod connection;
 mod downloads;
+mod feeds;
+pub mod migrations;
 mod settings;
+mod watch_folders;
 
-pub use connection::{get_db_path, init_database, Database};
+pub use categories::CategoriesDb;
+pub use connection::{get_db_path, init_database, open_database, Database};
+#[cfg(test)]
+pub(crate) use connection::in_memory;
+pub(crate) use downloads::is_secret_header;
 pub use downloads::DownloadsDb;
+pub use feeds::FeedsDb;
 pub use settings::{SettingsDb, TrackersDb};
+pub use watch_folders::WatchFoldersDb;
@@ -9,6 +9,36 @@ use rusqlite::params;
 pub struct SettingsDb;
 
 impl SettingsDb {
+    /// Keys `load` understands
+    pub const KEYS: &'static [&'static str] = &[
+        "download_path",
+        "max_concurrent_downloads",
+        "max_connections_per_server",
+        "split_count",
+        "download_speed_limit",
+        "upload_speed_limit",
+        "user_agent",
+        "enable_notifications",
+        "close_to_tray",
+        "bt_enable_dht",
+        "bt_enable_pex",
+        "bt_enable_lpd",
+        "bt_max_peers",
+        "bt_seed_ratio",
+        "auto_update_trackers",
+        "delete_files_on_remove",
+        "proxy_enabled",
+        "proxy_type",
+        "proxy_url",
+        "proxy_user",
+        "proxy_pass",
+        "min_segment_size",
+        "bt_preallocation",
+        "bandwidth_schedule",
+        "completion_actions",
+        "conflict_policy",
+    ];
+
     /// Load all settings from database
     pub fn load(db: &Database) -> Result<Settings> {
         let mut settings = Settings::default();
@@ -92,6 +122,18 @@ impl SettingsDb {
                         settings.min_segment_size = value.parse().unwrap_or(1024);
                     }
                     "bt_preallocation" => settings.bt_preallocation = value,
+                    "bandwidth_schedule" => match serde_json::from_str(&value) {
+                        Ok(schedule) => settings.bandwidth_schedule = schedule,
+                        Err(e) => log::warn!("Ignoring invalid bandwidth schedule: {}", e),
+                    },
+                    "completion_actions" => match serde_json::from_str(&value) {
+                        Ok(actions) => settings.completion_actions = actions,
+                        Err(e) => log::warn!("Ignoring invalid completion actions: {}", e),
+                    },
+                    "conflict_policy" => match value.parse() {
+                        Ok(policy) => settings.conflict_policy = policy,
+                        Err(e) => log::warn!("Ignoring invalid conflict policy: {}", e),
+                    },
                     _ => {}
                 }
             }
@@ -142,9 +184,31 @@ impl SettingsDb {
         }
         Self::set(db, "min_segment_size", &settings.min_segment_size.to_string())?;
         Self::set(db, "bt_preallocation", &settings.bt_preallocation)?;
+        Self::set(db, "bandwidth_schedule", &serde_json::to_string(&settings.bandwidth_schedule)?)?;
+        Self::set(db, "completion_actions", &serde_json::to_string(&settings.completion_actions)?)?;
+        Self::set(db, "conflict_policy", &settings.conflict_policy.to_string())?;
         Ok(())
     }
 
+    /// Get every stored setting as it is saved, sorted by key
+    pub fn get_all(db: &Database) -> Result<Vec<(String, String)>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT key, value FROM settings ORDER BY key")?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows)
+        })
+    }
+
+    /// Remove every stored setting, so `load` falls back to the defaults
+    pub fn clear(db: &Database) -> Result<()> {
+        db.with_conn(|conn| {
+            conn.execute("DELETE FROM settings", [])?;
+            Ok(())
+        })
+    }
+
     /// Get a single setting value
     pub fn get(db: &Database, key: &str) -> Result<Option<String>> {
         db.with_conn(|conn| {
@@ -178,6 +242,29 @@ impl TrackersDb {
         })
     }
 
+    /// Get all trackers with whether they are enabled
+    pub fn get_all(db: &Database) -> Result<Vec<(String, bool)>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT url, enabled FROM trackers ORDER BY id")?;
+            let trackers = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(trackers)
+        })
+    }
+
+    /// Add a tracker, or enable or disable a known one
+    pub fn set(db: &Database, url: &str, enabled: bool) -> Result<()> {
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO trackers (url, enabled) VALUES (?1, ?2)
+                 ON CONFLICT(url) DO UPDATE SET enabled = excluded.enabled",
+                params![url, enabled],
+            )?;
+            Ok(())
+        })
+    }
+
     /// Replace all trackers
     pub fn replace_all(db: &Database, trackers: &[String]) -> Result<()> {
         db.with_conn_mut(|conn| {
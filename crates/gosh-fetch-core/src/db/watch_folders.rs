@@ -0,0 +1,94 @@
+//! Watch folders database operations
+
+use crate::db::Database;
+use crate::error::{Error, Result};
+use crate::types::WatchFolder;
+use rusqlite::{params, Row};
+
+const WATCH_FOLDER_COLUMNS: &str = "id, path, enabled, dir, category, move_aside";
+
+/// Watch folders database operations
+pub struct WatchFoldersDb;
+
+impl WatchFoldersDb {
+    /// Get all watch folders
+    pub fn list(db: &Database) -> Result<Vec<WatchFolder>> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM watch_folders ORDER BY id",
+                WATCH_FOLDER_COLUMNS
+            ))?;
+            let folders = stmt
+                .query_map([], row_to_watch_folder)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(folders)
+        })
+    }
+
+    /// Insert a watch folder (id 0) or update an existing one
+    ///
+    /// Empty `dir` and `category` values are stored as unset.
+    pub fn save(db: &Database, folder: &WatchFolder) -> Result<i64> {
+        let path = folder.path.trim();
+        if path.is_empty() {
+            return Err(Error::InvalidInput(
+                "Watch folder path is required".to_string(),
+            ));
+        }
+        let dir = folder
+            .dir
+            .as_deref()
+            .map(str::trim)
+            .filter(|d| !d.is_empty());
+        let category = folder
+            .category
+            .as_deref()
+            .map(str::trim)
+            .filter(|c| !c.is_empty());
+
+        db.with_conn(|conn| {
+            if folder.id == 0 {
+                conn.execute(
+                    "INSERT INTO watch_folders (path, enabled, dir, category, move_aside)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![path, folder.enabled, dir, category, folder.move_aside],
+                )?;
+                Ok(conn.last_insert_rowid())
+            } else {
+                conn.execute(
+                    "UPDATE watch_folders SET path = ?1, enabled = ?2, dir = ?3, category = ?4,
+                                              move_aside = ?5
+                     WHERE id = ?6",
+                    params![
+                        path,
+                        folder.enabled,
+                        dir,
+                        category,
+                        folder.move_aside,
+                        folder.id
+                    ],
+                )?;
+                Ok(folder.id)
+            }
+        })
+    }
+
+    /// Delete a watch folder
+    pub fn delete(db: &Database, id: i64) -> Result<()> {
+        db.with_conn(|conn| {
+            conn.execute("DELETE FROM watch_folders WHERE id = ?1", params![id])?;
+            Ok(())
+        })
+    }
+}
+
+fn row_to_watch_folder(row: &Row) -> rusqlite::Result<WatchFolder> {
+    Ok(WatchFolder {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        enabled: row.get(2)?,
+        dir: row.get(3)?,
+        category: row.get(4)?,
+        move_aside: row.get(5)?,
+    })
+}
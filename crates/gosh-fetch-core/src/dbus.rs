@@ -0,0 +1,458 @@
+//! D-Bus session service
+//!
+//! Whichever process owns the engine (the desktop app or `gosh-fetchd`)
+//! registers `io.github.gosh.Fetch` on the session bus so file managers,
+//! shell extensions and scripts can drive it:
+//!
+//! ```text
+//! gdbus call --session --dest io.github.gosh.Fetch \
+//!     --object-path /io/github/gosh/Fetch \
+//!     --method io.github.gosh.Fetch.AddUri https://example.com/file.iso ''
+//! ```
+//!
+//! Methods forward to the service as `EngineCommand`s. The add methods wait
+//! for it and return the new download's GID, or the GID of the download it
+//! duplicates with `duplicate` set; the others return once the command is
+//! queued. Options are the same `DownloadOptions` JSON the QML layer sends.
+
+use crate::error::{Error, Result};
+use crate::service::{AddOutcome, EngineCommand, UiMessage};
+use crate::types::{Download, DownloadOptions, DownloadState};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use zbus::object_server::SignalContext;
+
+/// Well-known bus name
+pub const BUS_NAME: &str = "io.github.gosh.Fetch";
+
+/// Path of the single exported object
+pub const OBJECT_PATH: &str = "/io/github/gosh/Fetch";
+
+/// `List` entry: gid, name, status, completed bytes, total bytes, download speed
+pub type DownloadEntry = (String, String, String, u64, u64, u64);
+
+struct FetchInterface {
+    cmd_sender: async_channel::Sender<EngineCommand>,
+    downloads: Arc<Mutex<HashMap<String, Download>>>,
+}
+
+impl FetchInterface {
+    async fn send(&self, cmd: EngineCommand) -> zbus::fdo::Result<()> {
+        self.cmd_sender
+            .send(cmd)
+            .await
+            .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+    }
+
+    /// Run an add command and return the GID it was added under, or that of
+    /// the download it duplicates with `true`
+    async fn add(&self, cmd: EngineCommand) -> zbus::fdo::Result<(String, bool)> {
+        let (reply, outcome) = async_channel::bounded(1);
+        self.send(EngineCommand::AddAndReply(Box::new(cmd), reply))
+            .await?;
+        match outcome.recv().await {
+            Ok(AddOutcome::Added(gid)) => Ok((gid, false)),
+            Ok(AddOutcome::Duplicate(existing)) => Ok((existing.gid, true)),
+            Ok(AddOutcome::Failed(message)) => Err(zbus::fdo::Error::Failed(message)),
+            Err(_) => Err(zbus::fdo::Error::Failed(
+                "The download service stopped".to_string(),
+            )),
+        }
+    }
+}
+
+/// Local path of a torrent given as a path or a `file://` URI, which file
+/// managers percent-encode
+fn torrent_path(path: &str) -> zbus::fdo::Result<PathBuf> {
+    if !path.starts_with("file:") {
+        return Ok(PathBuf::from(path));
+    }
+    Url::parse(path)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("Not a local file: {}", path)))
+}
+
+fn parse_options(options_json: &str) -> zbus::fdo::Result<Option<DownloadOptions>> {
+    let trimmed = options_json.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(trimmed)
+        .map(Some)
+        .map_err(|e| zbus::fdo::Error::InvalidArgs(format!("Invalid options: {}", e)))
+}
+
+#[zbus::interface(name = "io.github.gosh.Fetch")]
+impl FetchInterface {
+    /// Add an HTTP/HTTPS download
+    #[zbus(out_args("gid", "duplicate"))]
+    async fn add_uri(&self, uri: String, options: String) -> zbus::fdo::Result<(String, bool)> {
+        let options = parse_options(&options)?;
+        self.add(EngineCommand::AddDownload { url: uri, options })
+            .await
+    }
+
+    /// Add a magnet link
+    #[zbus(out_args("gid", "duplicate"))]
+    async fn add_magnet(&self, uri: String, options: String) -> zbus::fdo::Result<(String, bool)> {
+        if !uri.starts_with("magnet:") {
+            return Err(zbus::fdo::Error::InvalidArgs(
+                "Invalid magnet link".to_string(),
+            ));
+        }
+        let options = parse_options(&options)?;
+        self.add(EngineCommand::AddMagnet { uri, options }).await
+    }
+
+    /// Add a .torrent file from a local path
+    #[zbus(out_args("gid", "duplicate"))]
+    async fn add_torrent(
+        &self,
+        path: String,
+        options: String,
+    ) -> zbus::fdo::Result<(String, bool)> {
+        let options = parse_options(&options)?;
+        let path = torrent_path(&path)?;
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| zbus::fdo::Error::FileNotFound(format!("{}: {}", path.display(), e)))?;
+        self.add(EngineCommand::AddTorrent { data, options }).await
+    }
+
+    async fn pause(&self, gid: String) -> zbus::fdo::Result<()> {
+        self.send(EngineCommand::Pause(gid)).await
+    }
+
+    async fn resume(&self, gid: String) -> zbus::fdo::Result<()> {
+        self.send(EngineCommand::Resume(gid)).await
+    }
+
+    async fn remove(&self, gid: String, delete_files: bool) -> zbus::fdo::Result<()> {
+        self.send(EngineCommand::Remove { gid, delete_files }).await
+    }
+
+    async fn pause_all(&self) -> zbus::fdo::Result<()> {
+        self.send(EngineCommand::PauseAll).await
+    }
+
+    async fn resume_all(&self) -> zbus::fdo::Result<()> {
+        self.send(EngineCommand::ResumeAll).await
+    }
+
+    /// List the downloads this instance knows about, including those added
+    /// before the bus was registered
+    fn list(&self) -> Vec<DownloadEntry> {
+        let downloads = self.downloads.lock().unwrap();
+        let mut entries: Vec<&Download> = downloads.values().collect();
+        entries.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        entries
+            .into_iter()
+            .map(|d| {
+                (
+                    d.gid.clone(),
+                    d.name.clone(),
+                    d.status.to_string(),
+                    d.completed_size,
+                    d.total_size,
+                    d.download_speed,
+                )
+            })
+            .collect()
+    }
+
+    #[zbus(signal)]
+    async fn download_added(ctxt: &SignalContext<'_>, gid: &str, name: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn download_updated(
+        ctxt: &SignalContext<'_>,
+        gid: &str,
+        status: &str,
+        completed: u64,
+        total: u64,
+        speed: u64,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn download_completed(
+        ctxt: &SignalContext<'_>,
+        gid: &str,
+        name: &str,
+        save_path: &str,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn download_failed(ctxt: &SignalContext<'_>, gid: &str, error: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn download_removed(ctxt: &SignalContext<'_>, gid: &str) -> zbus::Result<()>;
+}
+
+/// Handle for feeding service messages to the bus
+#[derive(Clone)]
+pub struct DbusHandle {
+    sender: async_channel::Sender<UiMessage>,
+}
+
+impl DbusHandle {
+    /// Mirror a service message as a D-Bus signal
+    pub fn emit(&self, msg: &UiMessage) {
+        let _ = self.sender.try_send(msg.clone());
+    }
+}
+
+/// Register on the session bus in a background thread
+///
+/// Failing to get the bus or the name (another instance owns it) is logged
+/// and otherwise ignored; the handle then drops messages.
+pub fn spawn(cmd_sender: async_channel::Sender<EngineCommand>) -> DbusHandle {
+    let (sender, receiver) = async_channel::bounded::<UiMessage>(200);
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
+
+        rt.block_on(async move {
+            let builder = match zbus::connection::Builder::session() {
+                Ok(builder) => builder,
+                Err(e) => {
+                    log::warn!("D-Bus session bus unavailable: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = serve(builder, cmd_sender, receiver).await {
+                log::warn!("D-Bus service stopped: {}", e);
+            }
+        });
+    });
+
+    DbusHandle { sender }
+}
+
+/// Check whether a process (the desktop app or `gosh-fetchd`) has
+/// registered the service on the session bus; false without a session bus
+pub async fn service_running() -> bool {
+    let check = async {
+        let connection = zbus::Connection::session().await?;
+        let proxy = zbus::fdo::DBusProxy::new(&connection).await?;
+        let owned = proxy.name_has_owner(BUS_NAME.try_into()?).await?;
+        Ok::<_, zbus::Error>(owned)
+    };
+    check.await.unwrap_or(false)
+}
+
+/// Export the interface on the given connection and emit signals until the
+/// message channel closes
+pub async fn serve(
+    builder: zbus::connection::Builder<'_>,
+    cmd_sender: async_channel::Sender<EngineCommand>,
+    messages: async_channel::Receiver<UiMessage>,
+) -> Result<()> {
+    let downloads = Arc::new(Mutex::new(HashMap::new()));
+    let interface = FetchInterface {
+        cmd_sender: cmd_sender.clone(),
+        downloads: downloads.clone(),
+    };
+
+    let connection = builder
+        .name(BUS_NAME)
+        .and_then(|b| b.serve_at(OBJECT_PATH, interface))
+        .map_err(dbus_error)?
+        .build()
+        .await
+        .map_err(dbus_error)?;
+    log::info!("Registered {} on the session bus", BUS_NAME);
+
+    let iface_ref = connection
+        .object_server()
+        .interface::<_, FetchInterface>(OBJECT_PATH)
+        .await
+        .map_err(dbus_error)?;
+    let ctxt = iface_ref.signal_context();
+
+    // Downloads added before the bus came up arrive with the answer
+    if let Err(e) = cmd_sender.send(EngineCommand::RefreshDownloads).await {
+        log::warn!("Failed to request the download list: {}", e);
+    }
+
+    while let Ok(msg) = messages.recv().await {
+        let result = match msg {
+            UiMessage::DownloadAdded(download) => {
+                let result =
+                    FetchInterface::download_added(ctxt, &download.gid, &download.name).await;
+                downloads
+                    .lock()
+                    .unwrap()
+                    .insert(download.gid.clone(), download);
+                result
+            }
+            UiMessage::DownloadUpdated(gid, download) => {
+                let result = FetchInterface::download_updated(
+                    ctxt,
+                    &gid,
+                    &download.status.to_string(),
+                    download.completed_size,
+                    download.total_size,
+                    download.download_speed,
+                )
+                .await;
+                downloads.lock().unwrap().insert(gid, download);
+                result
+            }
+            UiMessage::DownloadCompleted(download) => {
+                let result = FetchInterface::download_completed(
+                    ctxt,
+                    &download.gid,
+                    &download.name,
+                    &download.save_path,
+                )
+                .await;
+                downloads
+                    .lock()
+                    .unwrap()
+                    .insert(download.gid.clone(), download);
+                result
+            }
+            UiMessage::DownloadFailed(gid, error) => {
+                if let Some(download) = downloads.lock().unwrap().get_mut(&gid) {
+                    download.status = DownloadState::Error;
+                    download.error_message = Some(error.clone());
+                }
+                FetchInterface::download_failed(ctxt, &gid, &error).await
+            }
+            UiMessage::DownloadRemoved(gid) => {
+                downloads.lock().unwrap().remove(&gid);
+                FetchInterface::download_removed(ctxt, &gid).await
+            }
+            UiMessage::DownloadsList(list) => {
+                let mut downloads = downloads.lock().unwrap();
+                downloads.clear();
+                downloads.extend(list.into_iter().map(|d| (d.gid.clone(), d)));
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            log::debug!("Failed to emit D-Bus signal: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn dbus_error(err: zbus::Error) -> Error {
+    Error::Channel(format!("D-Bus: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    #[test]
+    fn test_torrent_uris_are_decoded() {
+        assert_eq!(
+            torrent_path("file:///home/me/My%20Distro%20%231.torrent").unwrap(),
+            PathBuf::from("/home/me/My Distro #1.torrent")
+        );
+        assert_eq!(
+            torrent_path("/home/me/distro.torrent").unwrap(),
+            PathBuf::from("/home/me/distro.torrent")
+        );
+        assert!(torrent_path("file://otherhost/distro.torrent").is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a dbus-daemon binary; run with --ignored"]
+    async fn test_add_uri_on_private_bus() {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("dbus-daemon must be installed");
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_string();
+
+        let (cmd_sender, cmd_receiver) = async_channel::bounded(8);
+        let (msg_sender, msg_receiver) = async_channel::bounded(8);
+        let builder = zbus::connection::Builder::address(address.as_str()).unwrap();
+        tokio::spawn(serve(builder, cmd_sender, msg_receiver));
+
+        let client = zbus::connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        // The list is asked for as soon as the bus is up and the name is ours
+        let recv = || tokio::time::timeout(Duration::from_secs(5), cmd_receiver.recv());
+        let cmd = recv().await.unwrap();
+        assert!(matches!(cmd, Ok(EngineCommand::RefreshDownloads)));
+
+        // Adding waits for the service's answer and returns the GID
+        let call = client.call_method(
+            Some(BUS_NAME),
+            OBJECT_PATH,
+            Some(BUS_NAME),
+            "AddUri",
+            &("https://example.com/file.iso", r#"{"out":"a.iso"}"#),
+        );
+        let answer = async {
+            let Ok(EngineCommand::AddAndReply(cmd, reply)) = recv().await.unwrap() else {
+                panic!("expected an add waiting for its outcome");
+            };
+            assert!(matches!(
+                *cmd,
+                EngineCommand::AddDownload { ref url, options: Some(ref o) }
+                    if url == "https://example.com/file.iso" && o.out.as_deref() == Some("a.iso")
+            ));
+            reply
+                .send(AddOutcome::Added("new".to_string()))
+                .await
+                .unwrap();
+        };
+        let (reply, ()) = tokio::join!(call, answer);
+        let added: (String, bool) = reply.unwrap().body().deserialize().unwrap();
+        assert_eq!(added, ("new".to_string(), false));
+
+        // ...and its answer fills List with downloads added before
+        let existing = Download {
+            gid: "abc".to_string(),
+            name: "old.iso".to_string(),
+            status: DownloadState::Paused,
+            ..Default::default()
+        };
+        msg_sender
+            .send(UiMessage::DownloadsList(vec![existing]))
+            .await
+            .unwrap();
+        let mut listed = Vec::new();
+        for _ in 0..50 {
+            let reply = client
+                .call_method(Some(BUS_NAME), OBJECT_PATH, Some(BUS_NAME), "List", &())
+                .await
+                .unwrap();
+            listed = reply.body().deserialize::<Vec<DownloadEntry>>().unwrap();
+            if !listed.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(listed.len(), 1);
+        assert_eq!(
+            (listed[0].0.as_str(), listed[0].2.as_str()),
+            ("abc", "paused")
+        );
+
+        let _ = daemon.kill();
+        let _ = daemon.wait();
+    }
+}
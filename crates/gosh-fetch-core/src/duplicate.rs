@@ -0,0 +1,392 @@
+//! Duplicate detection
+//!
+//! Before a download is added, the live engine list and the downloads table
+//! are searched for one with the same info hash, the same URL or the same
+//! target file, in that order. URLs are compared without their fragment and
+//! with the scheme and host lowercased. The engine records HTTP downloads
+//! under the URL they redirect to, so a link that redirects somewhere new
+//! each time is only caught by its target file.
+//!
+//! `check` is the one place an add command's `on_duplicate` action is
+//! applied; the download service, the CLI and the aria2 RPC all go through
+//! it.
+
+use crate::db::{Database, DownloadsDb};
+use crate::engine_adapter::EngineAdapter;
+use crate::error::Result;
+use crate::metalink;
+use crate::scheduler;
+use crate::service::{remove_download, EngineCommand};
+use crate::torrent::parse_torrent;
+use crate::types::{
+    Download, DownloadOptions, DownloadState, DuplicateAction, DuplicateMatch, ExistingDownload,
+};
+use reqwest::Url;
+use std::path::{Path, PathBuf};
+
+/// What identifies a download
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Candidate {
+    /// Normalized URL
+    pub url: Option<String>,
+    /// Lowercase hex info hash
+    pub info_hash: Option<String>,
+    /// File or folder the download saves to
+    pub path: Option<PathBuf>,
+}
+
+impl Candidate {
+    /// Candidate for an add command; `None` for other commands and for
+    /// metalinks, which only name their files once fetched
+    ///
+    /// Downloads without a `dir` are taken to go to `default_dir`.
+    pub fn for_command(cmd: &EngineCommand, default_dir: &Path) -> Option<Self> {
+        match cmd {
+            EngineCommand::AddDownload { url, .. } if metalink::is_metalink(url) => None,
+            EngineCommand::AddDownload { url, options } => {
+                Some(Self::for_url(url, options.as_ref(), default_dir))
+            }
+            EngineCommand::AddMagnet { uri, options } => {
+                Some(Self::for_magnet(uri, options.as_ref(), default_dir))
+            }
+            EngineCommand::AddTorrent { data, options } => {
+                Some(Self::for_torrent(data, options.as_ref(), default_dir))
+            }
+            _ => None,
+        }
+    }
+
+    /// Candidate for an HTTP download, named by `out` or the URL's last
+    /// path segment
+    pub fn for_url(url: &str, options: Option<&DownloadOptions>, default_dir: &Path) -> Self {
+        let name = options.and_then(|o| o.out.clone()).or_else(|| {
+            Url::parse(url.trim()).ok().and_then(|url| {
+                url.path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+            })
+        });
+        Self {
+            url: Some(normalize_url(url)),
+            info_hash: None,
+            path: target_path(name.as_deref(), options, default_dir),
+        }
+    }
+
+    /// Candidate for a magnet link, named by its `dn`
+    pub fn for_magnet(uri: &str, options: Option<&DownloadOptions>, default_dir: &Path) -> Self {
+        let name = Url::parse(uri).ok().and_then(|url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "dn")
+                .map(|(_, value)| value.into_owned())
+        });
+        Self {
+            url: None,
+            info_hash: magnet_info_hash(uri),
+            path: target_path(name.as_deref(), options, default_dir),
+        }
+    }
+
+    /// Candidate for a .torrent file; one that cannot be parsed matches
+    /// nothing
+    pub fn for_torrent(data: &[u8], options: Option<&DownloadOptions>, default_dir: &Path) -> Self {
+        match parse_torrent(data) {
+            Ok(info) => Self {
+                url: None,
+                info_hash: Some(info.info_hash.to_lowercase()),
+                path: target_path(Some(&info.name), options, default_dir),
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Candidate for a recorded download
+    pub fn for_download(download: &Download) -> Self {
+        Self {
+            url: download.url.as_deref().map(normalize_url),
+            info_hash: info_hash_of(download),
+            path: saved_path(download),
+        }
+    }
+}
+
+/// A URL without its fragment, with scheme and host lowercased and the
+/// default port dropped
+pub fn normalize_url(url: &str) -> String {
+    match Url::parse(url.trim()) {
+        Ok(mut url) => {
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => url.trim().to_string(),
+    }
+}
+
+/// The info hash of a magnet link as lowercase hex; base32 hashes are
+/// converted
+pub fn magnet_info_hash(uri: &str) -> Option<String> {
+    let url = Url::parse(uri).ok()?;
+    let hash = url.query_pairs().find_map(|(key, value)| {
+        (key == "xt")
+            .then(|| value.strip_prefix("urn:btih:").map(str::to_string))
+            .flatten()
+    })?;
+
+    match hash.len() {
+        40 if hash.chars().all(|c| c.is_ascii_hexdigit()) => Some(hash.to_lowercase()),
+        32 => base32_to_hex(&hash),
+        _ => None,
+    }
+}
+
+fn base32_to_hex(text: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.chars() {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u32 - 'A' as u32,
+            c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(hex::encode(bytes))
+}
+
+fn target_path(
+    name: Option<&str>,
+    options: Option<&DownloadOptions>,
+    default_dir: &Path,
+) -> Option<PathBuf> {
+    let name = name.filter(|n| !n.is_empty())?;
+    let dir = options
+        .and_then(|o| o.dir.as_ref())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| default_dir.to_path_buf());
+    Some(dir.join(name))
+}
+
+fn info_hash_of(download: &Download) -> Option<String> {
+    download
+        .info_hash
+        .as_deref()
+        .filter(|hash| !hash.is_empty())
+        .map(str::to_lowercase)
+        .or_else(|| download.magnet_uri.as_deref().and_then(magnet_info_hash))
+}
+
+fn saved_path(download: &Download) -> Option<PathBuf> {
+    (!download.name.is_empty()).then(|| Path::new(&download.save_path).join(&download.name))
+}
+
+/// The first of `downloads` the candidate duplicates
+///
+/// A matching info hash wins over a matching URL, and a URL over a target
+/// file. Removed downloads are skipped.
+pub fn find(candidate: &Candidate, downloads: &[Download]) -> Option<ExistingDownload> {
+    let downloads: Vec<&Download> = downloads
+        .iter()
+        .filter(|d| d.status != DownloadState::Removed)
+        .collect();
+    let existing = |download: &Download, matched| ExistingDownload {
+        gid: download.gid.clone(),
+        name: download.name.clone(),
+        state: download.status,
+        matched,
+    };
+
+    if let Some(hash) = &candidate.info_hash {
+        if let Some(d) = downloads
+            .iter()
+            .find(|d| info_hash_of(d).as_ref() == Some(hash))
+        {
+            return Some(existing(d, DuplicateMatch::InfoHash));
+        }
+    }
+    if let Some(url) = &candidate.url {
+        if let Some(d) = downloads
+            .iter()
+            .find(|d| d.url.as_deref().map(normalize_url).as_ref() == Some(url))
+        {
+            return Some(existing(d, DuplicateMatch::Url));
+        }
+    }
+    if let Some(path) = &candidate.path {
+        if let Some(d) = downloads
+            .iter()
+            .find(|d| saved_path(d).as_ref() == Some(path))
+        {
+            return Some(existing(d, DuplicateMatch::Path));
+        }
+    }
+    None
+}
+
+/// The download the candidate duplicates, looking at the engine's downloads
+/// first and then at the downloads table, history included
+pub fn find_existing(
+    adapter: &EngineAdapter,
+    db: &Database,
+    candidate: &Candidate,
+) -> Option<ExistingDownload> {
+    if let Some(existing) = find(candidate, &adapter.get_all()) {
+        return Some(existing);
+    }
+    let recorded = DownloadsDb::get_all(db).unwrap_or_else(|e| {
+        log::error!("Failed to load downloads: {}", e);
+        Vec::new()
+    });
+    find(candidate, &recorded)
+}
+
+/// What an add command's `on_duplicate` action makes of the download it
+/// duplicates
+#[derive(Debug, Clone)]
+pub enum Verdict {
+    /// Nothing is in the way, or the action is to add it anyway
+    Add,
+    /// Not to be added; the existing download is reported instead
+    Report(ExistingDownload),
+    /// To be added again once the existing download is out of the way
+    Restart(ExistingDownload),
+}
+
+/// Apply an add command's `on_duplicate` action to `existing`, the
+/// download it duplicates if any
+pub fn verdict(cmd: &EngineCommand, existing: Option<ExistingDownload>) -> Verdict {
+    let Some(existing) = existing else {
+        return Verdict::Add;
+    };
+    let action = cmd
+        .options()
+        .and_then(|o| o.on_duplicate)
+        .unwrap_or_default();
+    match action {
+        DuplicateAction::Add => Verdict::Add,
+        DuplicateAction::Report => Verdict::Report(existing),
+        DuplicateAction::Restart => Verdict::Restart(existing),
+    }
+}
+
+/// Look for the download an add command duplicates and apply its
+/// `on_duplicate` action
+///
+/// For `Restart` the existing download is cleared out of the way first: a
+/// scheduled one is dropped and one the engine has is removed, keeping its
+/// files. The caller announces the removal with `UiMessage::DownloadRemoved`,
+/// which also drops it from the history. Commands that add nothing, and
+/// metalinks, always get `Add`.
+pub async fn check(adapter: &EngineAdapter, db: &Database, cmd: &EngineCommand) -> Result<Verdict> {
+    let default_dir = adapter.get_config().download_dir;
+    let existing = Candidate::for_command(cmd, &default_dir)
+        .and_then(|candidate| find_existing(adapter, db, &candidate));
+
+    let verdict = verdict(cmd, existing);
+    if let Verdict::Restart(existing) = &verdict {
+        // Downloads only in history have nothing to stop
+        if scheduler::is_scheduled(db, &existing.gid) || adapter.get_status(&existing.gid).is_some()
+        {
+            remove_download(adapter, db, &existing.gid, false).await?;
+        }
+    }
+    Ok(verdict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicates_by_hash_url_and_path() {
+        let downloads = vec![
+            Download {
+                gid: "http".to_string(),
+                name: "distro.iso".to_string(),
+                url: Some("HTTPS://Example.com:443/isos/distro.iso".to_string()),
+                save_path: "/data".to_string(),
+                status: DownloadState::Complete,
+                ..Default::default()
+            },
+            Download {
+                gid: "magnet".to_string(),
+                name: "show".to_string(),
+                magnet_uri: Some(
+                    "magnet:?xt=urn:btih:MFRGGZDFMZTWQ2LKNNWG23TPOBYXE43U".to_string(),
+                ),
+                save_path: "/data".to_string(),
+                status: DownloadState::Active,
+                ..Default::default()
+            },
+            Download {
+                gid: "removed".to_string(),
+                name: "old.zip".to_string(),
+                save_path: "/data".to_string(),
+                status: DownloadState::Removed,
+                ..Default::default()
+            },
+        ];
+        let default_dir = Path::new("/data");
+
+        let by_url = EngineCommand::AddDownload {
+            url: "https://example.com/isos/distro.iso#top".to_string(),
+            options: Some(DownloadOptions {
+                dir: Some("/elsewhere".to_string()),
+                ..Default::default()
+            }),
+        };
+        let candidate = Candidate::for_command(&by_url, default_dir).unwrap();
+        let existing = find(&candidate, &downloads).unwrap();
+        assert_eq!(existing.gid, "http");
+        assert_eq!(existing.state, DownloadState::Complete);
+        assert_eq!(existing.matched, DuplicateMatch::Url);
+
+        let by_hash = Candidate::for_magnet(
+            "magnet:?xt=urn:btih:6162636465666768696A6B6C6D6E6F7071727374&dn=other",
+            None,
+            default_dir,
+        );
+        let existing = find(&by_hash, &downloads).unwrap();
+        assert_eq!(
+            (existing.gid.as_str(), existing.matched),
+            ("magnet", DuplicateMatch::InfoHash)
+        );
+
+        let by_path =
+            Candidate::for_url("https://mirror.example.net/distro.iso", None, default_dir);
+        assert_eq!(
+            find(&by_path, &downloads).map(|e| e.matched),
+            Some(DuplicateMatch::Path)
+        );
+
+        let fresh = Candidate::for_url("https://example.com/old.zip", None, default_dir);
+        assert!(find(&fresh, &downloads).is_none());
+        let metalink = EngineCommand::AddDownload {
+            url: "https://example.com/distro.meta4".to_string(),
+            options: None,
+        };
+        assert!(Candidate::for_command(&metalink, default_dir).is_none());
+
+        let existing = find(&candidate, &downloads);
+        assert!(matches!(
+            verdict(&by_url, existing.clone()),
+            Verdict::Report(e) if e.gid == "http"
+        ));
+        let restart = by_url
+            .clone()
+            .with_duplicate_action(DuplicateAction::Restart);
+        assert!(matches!(
+            verdict(&restart, existing.clone()),
+            Verdict::Restart(_)
+        ));
+        let add = by_url.with_duplicate_action(DuplicateAction::Add);
+        assert!(matches!(verdict(&add, existing), Verdict::Add));
+        assert!(matches!(verdict(&metalink, None), Verdict::Add));
+    }
+}
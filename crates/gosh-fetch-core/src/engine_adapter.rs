@@ -2,14 +2,30 @@
 //!
 //! This module adapts the gosh-dl download engine to the application.
 
-use crate::types::{Download, DownloadOptions as FrontendOptions, DownloadState, DownloadType, GlobalStats};
+use crate::category::{self, CategoryTarget};
+use crate::checksum;
+use crate::conflict::{self, Resolution, SetAside};
+use crate::filename;
+use crate::metalink;
+use crate::torrent::parse_torrent;
+use crate::types::{
+    Category, ConflictOutcome, ConflictPolicy, Download, DownloadOptions as FrontendOptions,
+    DownloadState, DownloadType, GlobalStats, ProbeResult,
+};
+use futures::stream::{self, StreamExt};
 use gosh_dl::{
     DownloadEngine, DownloadId, DownloadOptions, DownloadState as EngineState, DownloadStatus,
     PeerInfo as EnginePeerInfo, TorrentFile,
 };
-use reqwest::header::{CONTENT_DISPOSITION, CONTENT_TYPE, COOKIE, RANGE, REFERER, USER_AGENT};
-use std::path::PathBuf;
-use std::sync::Arc;
+use reqwest::header::{
+    HeaderName, ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+    COOKIE, ETAG, LAST_MODIFIED, LOCATION, RANGE, REFERER, USER_AGENT,
+};
+use reqwest::{Method, StatusCode, Url};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 /// Torrent file info for UI display
 #[derive(Debug, Clone)]
@@ -34,12 +50,108 @@ pub struct PeerInfo {
 #[derive(Clone)]
 pub struct EngineAdapter {
     engine: Arc<DownloadEngine>,
+    categories: Arc<RwLock<Vec<Category>>>,
+    /// Category name by GID; the engine has no notion of categories
+    assigned: Arc<RwLock<HashMap<String, String>>>,
+    /// Downloads added from metalinks, which the engine sees as plain HTTP
+    metalinks: Arc<RwLock<HashSet<String>>>,
+    /// Policy for downloads whose options set none
+    conflict_policy: Arc<RwLock<ConflictPolicy>>,
+    /// What the conflict policy did, by GID
+    conflicts: Arc<RwLock<HashMap<String, ConflictOutcome>>>,
+    /// Downloads skipped because their file already existed; the engine
+    /// never sees them
+    skipped: Arc<RwLock<HashMap<String, Download>>>,
+    /// Client for the requests made before adding a URL, shared so
+    /// connections are kept alive between them. It goes through the
+    /// engine's proxy, or holds the reason that proxy cannot be used.
+    http: Arc<RwLock<Result<reqwest::Client, String>>>,
 }
 
 impl EngineAdapter {
     /// Create a new adapter with the given engine
     pub fn new(engine: Arc<DownloadEngine>) -> Self {
-        Self { engine }
+        let http = probe_client(&engine.get_config(), None);
+        Self {
+            engine,
+            categories: Arc::new(RwLock::new(Vec::new())),
+            assigned: Arc::new(RwLock::new(HashMap::new())),
+            metalinks: Arc::new(RwLock::new(HashSet::new())),
+            conflict_policy: Arc::new(RwLock::new(ConflictPolicy::default())),
+            conflicts: Arc::new(RwLock::new(HashMap::new())),
+            skipped: Arc::new(RwLock::new(HashMap::new())),
+            http: Arc::new(RwLock::new(http)),
+        }
+    }
+
+    /// Replace the categories new downloads are matched against
+    pub fn set_categories(&self, categories: Vec<Category>) {
+        *self.categories.write().unwrap() = categories;
+    }
+
+    /// Record the category of a download added in an earlier session
+    pub fn assign_category(&self, gid: &str, category: String) {
+        self.assigned
+            .write()
+            .unwrap()
+            .insert(gid.to_string(), category);
+    }
+
+    /// Record that a download came from a metalink
+    pub fn mark_metalink(&self, gid: &str) {
+        self.metalinks.write().unwrap().insert(gid.to_string());
+    }
+
+    /// Whether a download came from a metalink
+    pub fn is_metalink(&self, gid: &str) -> bool {
+        self.metalinks.read().unwrap().contains(gid)
+    }
+
+    /// Set the conflict policy for downloads whose options set none
+    pub fn set_conflict_policy(&self, policy: ConflictPolicy) {
+        *self.conflict_policy.write().unwrap() = policy;
+    }
+
+    /// Record what the conflict policy did for a download; `None` when it
+    /// did nothing
+    pub fn record_conflict(&self, gid: &str, outcome: Option<ConflictOutcome>) {
+        let mut conflicts = self.conflicts.write().unwrap();
+        match outcome {
+            Some(outcome) => conflicts.insert(gid.to_string(), outcome),
+            None => conflicts.remove(gid),
+        };
+    }
+
+    /// What the conflict policy did for a download, if anything
+    pub fn conflict(&self, gid: &str) -> Option<ConflictOutcome> {
+        self.conflicts.read().unwrap().get(gid).copied()
+    }
+
+    /// Apply the category a download falls into, if any
+    ///
+    /// Explicit options win over the category's save directory and defaults.
+    fn categorize(&self, options: FrontendOptions, target: &CategoryTarget) -> FrontendOptions {
+        let categories = self.categories.read().unwrap();
+        match category::find(&categories, &options, target) {
+            Some(category) => category::apply(options, category),
+            None => options,
+        }
+    }
+
+    fn remember_category(&self, gid: &str, options: &FrontendOptions) {
+        if let Some(category) = &options.category {
+            self.assign_category(gid, category.clone());
+        }
+    }
+
+    /// Fill in what the engine does not track: category and metalink origin
+    fn annotate(&self, mut download: Download) -> Download {
+        download.category = self.assigned.read().unwrap().get(&download.gid).cloned();
+        download.conflict = self.conflict(&download.gid);
+        if self.is_metalink(&download.gid) {
+            download.download_type = DownloadType::Metalink;
+        }
+        download
     }
 
     /// Get a reference to the engine
@@ -47,46 +159,267 @@ impl EngineAdapter {
         &self.engine
     }
 
+    /// Find out what a URL serves without adding it
+    ///
+    /// This is the request `add_download` makes before adding, with the
+    /// same headers, so a URL it would refuse is an error here too. It goes
+    /// through the download's `proxy` if set, otherwise the engine's; a
+    /// proxy that cannot be used is an error rather than a direct request.
+    pub async fn probe(
+        &self,
+        url: &str,
+        options: Option<FrontendOptions>,
+    ) -> Result<ProbeResult, gosh_dl::EngineError> {
+        let options = options.unwrap_or_default();
+        let client = match options.proxy.as_deref() {
+            Some(proxy) => probe_client(&self.engine.get_config(), Some(proxy)),
+            None => self.http.read().unwrap().clone(),
+        }
+        .map_err(|message| gosh_dl::EngineError::InvalidInput {
+            field: "proxy",
+            message,
+        })?;
+        let request = convert_options(options);
+        resolve_http_url(
+            &client,
+            url,
+            request.referer.as_deref(),
+            request.user_agent.as_deref(),
+            &request.headers,
+            request.cookies.as_deref(),
+        )
+        .await
+    }
+
     /// Add an HTTP download
+    ///
+    /// Without an `out`, the file is named after the server's
+    /// `Content-Disposition`, the URL it redirects to or its MIME type. A
+    /// `checksum_file` is read here and the line for the download's file
+    /// name becomes its checksum. If the file already exists, the conflict
+    /// policy decides whether it is renamed, replaced or continued as
+    /// `readd_download` would; a skipped download never reaches the engine
+    /// and is reported complete.
     pub async fn add_download(
         &self,
         url: String,
         options: Option<FrontendOptions>,
     ) -> Result<String, gosh_dl::EngineError> {
-        let opts = options.map(convert_options).unwrap_or_default();
-        let resolved_url = resolve_http_url(
-            &url,
-            opts.referer.as_deref(),
-            opts.user_agent.as_deref(),
-            &opts.headers,
-            opts.cookies.as_deref(),
-        )
-        .await?;
-        let id = self.engine.add_http(&resolved_url, opts).await?;
-        Ok(id.as_uuid().to_string())
+        let options = options.unwrap_or_default();
+        if let Some(message) = unsupported_checksum(&options) {
+            return Err(checksum_type_error(message));
+        }
+        let resolved = self.probe(&url, Some(options.clone())).await?;
+        let mut claimed = HashSet::new();
+        self.add_resolved(&url, options, resolved, Some(&mut claimed))
+            .await
+    }
+
+    /// Add an HTTP download again into the file it was saving to
+    ///
+    /// This is `add_download` without the conflict policy, for restoring a
+    /// download or retrying one the engine could not resume: the file is
+    /// its own, so it is neither renamed nor replaced. What the engine does
+    /// with the bytes already there is up to it.
+    pub async fn readd_download(
+        &self,
+        url: String,
+        options: Option<FrontendOptions>,
+    ) -> Result<String, gosh_dl::EngineError> {
+        let options = options.unwrap_or_default();
+        if let Some(message) = unsupported_checksum(&options) {
+            return Err(checksum_type_error(message));
+        }
+        let resolved = self.probe(&url, Some(options.clone())).await?;
+        self.add_resolved(&url, options, resolved, None).await
+    }
+
+    /// Add an HTTP download whose URL was already probed
+    ///
+    /// With `claimed`, the conflict policy is applied, the paths in it count
+    /// as taken and the path the download saves to is added to it.
+    async fn add_resolved(
+        &self,
+        url: &str,
+        mut options: FrontendOptions,
+        resolved: ProbeResult,
+        claimed: Option<&mut HashSet<PathBuf>>,
+    ) -> Result<String, gosh_dl::EngineError> {
+        if options.out.is_none() {
+            options.out = resolved.file_name.clone();
+        }
+
+        let target = CategoryTarget::for_url(
+            &[url, resolved.final_url.as_str()],
+            options.out.as_deref(),
+            resolved.mime_type.as_deref(),
+        );
+        let mut options = self.categorize(options, &target);
+        if options.checksum_value.is_none() {
+            if let Some(file_name) = &target.file_name {
+                let expected = checksum::expected(&options, file_name).await.map_err(|e| {
+                    gosh_dl::EngineError::InvalidInput {
+                        field: "checksum_file",
+                        message: e.to_string(),
+                    }
+                })?;
+                if let Some((algorithm, digest)) = expected {
+                    options.checksum_type = Some(algorithm.to_string());
+                    options.checksum_value = Some(digest);
+                }
+            }
+        }
+
+        let dir = options
+            .dir
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.engine.get_config().download_dir);
+        let mut resolution = Resolution::Free;
+        let mut replaced = None;
+        let file_name = target.file_name.as_ref();
+        if let (Some(file_name), Some(claimed)) = (file_name, claimed.as_deref()) {
+            let policy = options
+                .conflict_policy
+                .unwrap_or_else(|| *self.conflict_policy.read().unwrap());
+            resolution = conflict::resolve(&dir, file_name, policy, claimed);
+            match &resolution {
+                Resolution::Rename(name) => options.out = Some(name.clone()),
+                Resolution::Overwrite => {
+                    let path = dir.join(file_name);
+                    let aside =
+                        SetAside::new(&path).map_err(|e| gosh_dl::EngineError::Storage {
+                            path: path.clone(),
+                            message: format!("Failed to replace existing file: {}", e),
+                        })?;
+                    replaced = Some(aside);
+                }
+                Resolution::Skip => {
+                    return Ok(self.skip(&resolved.final_url, &dir, file_name.clone(), &options));
+                }
+                // Handed over like a restored download's own file
+                Resolution::Free | Resolution::Resume => {}
+            }
+        }
+
+        let added = self
+            .engine
+            .add_http(&resolved.final_url, convert_options(options.clone()))
+            .await;
+        // The file being replaced is only gone once its download is in
+        if let Some(aside) = replaced {
+            let result = match added {
+                Ok(_) => aside.discard(),
+                Err(_) => aside.restore(),
+            };
+            if let Err(e) = result {
+                log::error!("Failed to clean up the replaced file: {}", e);
+            }
+        }
+        let gid = added?.as_uuid().to_string();
+        if let (Some(claimed), Some(name)) = (claimed, options.out.as_ref().or(file_name)) {
+            claimed.insert(dir.join(name));
+        }
+        self.remember_category(&gid, &options);
+        self.record_conflict(&gid, resolution.outcome());
+        Ok(gid)
     }
 
-    /// Add multiple downloads
+    /// Record a download whose file is already in `dir` as complete without
+    /// downloading it, returning its new GID
+    fn skip(&self, url: &str, dir: &Path, name: String, options: &FrontendOptions) -> String {
+        let gid = uuid::Uuid::new_v4().to_string();
+        let size = std::fs::metadata(dir.join(&name))
+            .map(|m| m.len())
+            .unwrap_or(0);
+        let now = chrono::Utc::now().to_rfc3339();
+        let download = Download {
+            gid: gid.clone(),
+            name,
+            url: Some(url.to_string()),
+            status: DownloadState::Complete,
+            total_size: size,
+            completed_size: size,
+            save_path: dir.to_string_lossy().to_string(),
+            created_at: now.clone(),
+            completed_at: Some(now),
+            category: options.category.clone(),
+            conflict: Some(ConflictOutcome::Skipped),
+            ..Default::default()
+        };
+        self.skipped.write().unwrap().insert(gid.clone(), download);
+        gid
+    }
+
+    /// Add one HTTP download per file of a metalink document
+    ///
+    /// Returns the GIDs of the added downloads with the options each was
+    /// added with. Files that fail to add are logged and skipped; it is an
+    /// error only when none could be added.
+    pub async fn add_metalink(
+        &self,
+        data: &[u8],
+        options: Option<FrontendOptions>,
+    ) -> Result<Vec<(String, FrontendOptions)>, gosh_dl::EngineError> {
+        let files =
+            metalink::parse_metalink(data).map_err(|e| gosh_dl::EngineError::InvalidInput {
+                field: "metalink",
+                message: e.to_string(),
+            })?;
+        let default_dir = self.engine.get_config().download_dir;
+
+        let mut added = Vec::new();
+        let mut last_error = None;
+        for (url, options) in metalink::downloads(&files, &options.unwrap_or_default(), &default_dir)
+        {
+            match self.add_download(url.clone(), Some(options.clone())).await {
+                Ok(gid) => {
+                    self.mark_metalink(&gid);
+                    added.push((gid, options));
+                }
+                Err(e) => {
+                    log::warn!("Failed to add {} from metalink: {}", url, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) if added.is_empty() => Err(e),
+            _ => Ok(added),
+        }
+    }
+
+    /// Add several HTTP downloads with the same options
+    ///
+    /// Up to `RESOLVE_CONCURRENCY` URLs are probed at once; the downloads
+    /// are then added one by one in the order given, so they queue in that
+    /// order and a name taken by one is renamed around for the next, even
+    /// before its file exists. Returns one result per URL, in the same
+    /// order.
     pub async fn add_urls(
         &self,
         urls: Vec<String>,
         options: Option<FrontendOptions>,
-    ) -> Result<Vec<String>, gosh_dl::EngineError> {
-        let opts = options.map(convert_options).unwrap_or_default();
-        let mut gids = Vec::new();
-        for url in urls {
-            let resolved_url = resolve_http_url(
-                &url,
-                opts.referer.as_deref(),
-                opts.user_agent.as_deref(),
-                &opts.headers,
-                opts.cookies.as_deref(),
-            )
-            .await?;
-            let id = self.engine.add_http(&resolved_url, opts.clone()).await?;
-            gids.push(id.as_uuid().to_string());
+    ) -> Vec<Result<String, gosh_dl::EngineError>> {
+        let options = options.unwrap_or_default();
+        if let Some(message) = unsupported_checksum(&options) {
+            return urls
+                .iter()
+                .map(|_| Err(checksum_type_error(message.clone())))
+                .collect();
         }
-        Ok(gids)
+
+        let mut claimed = HashSet::new();
+        add_each(
+            &urls,
+            |url| self.probe(url, Some(options.clone())),
+            async |url, resolved| {
+                self.add_resolved(url, options.clone(), resolved, Some(&mut claimed))
+                    .await
+            },
+        )
+        .await
     }
 
     /// Pause a download
@@ -128,24 +461,52 @@ impl EngineAdapter {
         gid: &str,
         delete_files: bool,
     ) -> Result<(), gosh_dl::EngineError> {
+        // Skipped downloads only exist here; their file is not ours to delete
+        if self.skipped.write().unwrap().remove(gid).is_some() {
+            return Ok(());
+        }
         let id = parse_gid(gid)?;
-        self.engine.cancel(id, delete_files).await
+        self.engine.cancel(id, delete_files).await?;
+        self.assigned.write().unwrap().remove(gid);
+        Ok(())
     }
 
     /// Get status of a single download
+    ///
+    /// Downloads skipped by the conflict policy are found too.
     pub fn get_status(&self, gid: &str) -> Option<Download> {
+        if let Some(download) = self.skipped.read().unwrap().get(gid) {
+            return Some(download.clone());
+        }
         let id = parse_gid(gid).ok()?;
-        self.engine.status(id).map(convert_status)
+        self.engine
+            .status(id)
+            .map(|s| self.annotate(convert_status(s)))
     }
 
     /// Get all downloads
+    ///
+    /// Downloads skipped by the conflict policy this session are included;
+    /// like any other, they are kept in the database through their
+    /// `DownloadAdded` message.
     pub fn get_all(&self) -> Vec<Download> {
-        self.engine.list().into_iter().map(convert_status).collect()
+        let mut downloads: Vec<Download> = self
+            .engine
+            .list()
+            .into_iter()
+            .map(|s| self.annotate(convert_status(s)))
+            .collect();
+        downloads.extend(self.skipped.read().unwrap().values().cloned());
+        downloads
     }
 
     /// Get active downloads
     pub fn get_active(&self) -> Vec<Download> {
-        self.engine.active().into_iter().map(convert_status).collect()
+        self.engine
+            .active()
+            .into_iter()
+            .map(|s| self.annotate(convert_status(s)))
+            .collect()
     }
 
     /// Get global stats
@@ -157,6 +518,7 @@ impl EngineAdapter {
             num_active: stats.num_active as u32,
             num_waiting: stats.num_waiting as u32,
             num_stopped: stats.num_stopped as u32,
+            bandwidth_profile: None,
         }
     }
 
@@ -172,15 +534,42 @@ impl EngineAdapter {
         self.engine.set_config(config)
     }
 
+    /// Set a running download's own speed limits from its frontend
+    /// options; `None` or "0" lifts a limit
+    pub fn set_download_limits(
+        &self,
+        gid: &str,
+        options: &FrontendOptions,
+    ) -> Result<(), gosh_dl::EngineError> {
+        let id = parse_gid(gid)?;
+        let limit =
+            |value: &Option<String>| value.as_deref().and_then(parse_speed).filter(|n| *n > 0);
+        self.engine.set_download_limits(
+            id,
+            limit(&options.max_download_limit),
+            limit(&options.max_upload_limit),
+        )
+    }
+
     /// Add a torrent from file data
     pub async fn add_torrent(
         &self,
         torrent_data: &[u8],
         options: Option<FrontendOptions>,
     ) -> Result<String, gosh_dl::EngineError> {
-        let opts = options.map(convert_options).unwrap_or_default();
-        let id = self.engine.add_torrent(torrent_data, opts).await?;
-        Ok(id.as_uuid().to_string())
+        // A file we cannot parse is still handed over; the engine reports why
+        let target = match parse_torrent(torrent_data) {
+            Ok(info) => CategoryTarget::for_torrent(&info),
+            Err(_) => CategoryTarget::default(),
+        };
+        let options = self.categorize(options.unwrap_or_default(), &target);
+        let id = self
+            .engine
+            .add_torrent(torrent_data, convert_options(options.clone()))
+            .await?;
+        let gid = id.as_uuid().to_string();
+        self.remember_category(&gid, &options);
+        Ok(gid)
     }
 
     /// Add a magnet link
@@ -189,9 +578,15 @@ impl EngineAdapter {
         magnet_uri: &str,
         options: Option<FrontendOptions>,
     ) -> Result<String, gosh_dl::EngineError> {
-        let opts = options.map(convert_options).unwrap_or_default();
-        let id = self.engine.add_magnet(magnet_uri, opts).await?;
-        Ok(id.as_uuid().to_string())
+        let target = CategoryTarget::for_magnet(magnet_uri);
+        let options = self.categorize(options.unwrap_or_default(), &target);
+        let id = self
+            .engine
+            .add_magnet(magnet_uri, convert_options(options.clone()))
+            .await?;
+        let gid = id.as_uuid().to_string();
+        self.remember_category(&gid, &options);
+        Ok(gid)
     }
 
     /// Get torrent files
@@ -232,14 +627,25 @@ impl EngineAdapter {
     }
 
     /// Update engine configuration
+    ///
+    /// URL probes follow the new proxy and user agent too.
     pub fn update_config(&self, config: gosh_dl::EngineConfig) -> Result<(), gosh_dl::EngineError> {
-        self.engine.set_config(config)
+        let http = probe_client(&config, None);
+        self.engine.set_config(config)?;
+        *self.http.write().unwrap() = http;
+        Ok(())
     }
 
     /// Get current engine configuration
     pub fn get_config(&self) -> gosh_dl::EngineConfig {
         self.engine.get_config()
     }
+
+    /// Stop the engine's downloads and save its session, before the
+    /// process exits
+    pub async fn shutdown(&self) -> Result<(), gosh_dl::EngineError> {
+        self.engine.shutdown().await
+    }
 }
 
 /// Parse a GID string to a DownloadId
@@ -252,84 +658,150 @@ fn parse_gid(gid: &str) -> Result<DownloadId, gosh_dl::EngineError> {
     })
 }
 
+/// Redirects followed before a URL is given up on
+const MAX_REDIRECTS: usize = 10;
+
+/// URLs `add_urls` probes at once
+const RESOLVE_CONCURRENCY: usize = 8;
+
+/// Why the options' checksum type cannot be checked, if it cannot
+fn unsupported_checksum(options: &FrontendOptions) -> Option<String> {
+    let kind = options.checksum_type.as_ref()?;
+    if checksum::normalize(kind).is_some() {
+        return None;
+    }
+    Some(format!(
+        "Unsupported checksum type {}; expected one of {}",
+        kind,
+        checksum::ALGORITHMS.join(", ")
+    ))
+}
+
+fn checksum_type_error(message: String) -> gosh_dl::EngineError {
+    gosh_dl::EngineError::InvalidInput {
+        field: "checksum_type",
+        message,
+    }
+}
+
+/// Probe `urls` up to `RESOLVE_CONCURRENCY` at a time, then `add` each one
+/// that probed fine, in order
+///
+/// A URL that fails either step gets its own error; the others are added
+/// regardless. Returns one result per URL, in the same order.
+async fn add_each<'a, T, E, Fut>(
+    urls: &'a [String],
+    probe: impl Fn(&'a str) -> Fut,
+    mut add: impl AsyncFnMut(&'a str, T) -> Result<String, E>,
+) -> Vec<Result<String, E>>
+where
+    Fut: std::future::Future<Output = Result<T, E>>,
+{
+    let probed: Vec<_> = stream::iter(urls)
+        .map(|url| probe(url))
+        .buffered(RESOLVE_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut results = Vec::with_capacity(urls.len());
+    for (url, probed) in urls.iter().zip(probed) {
+        let result = match probed {
+            Ok(probed) => add(url, probed).await,
+            Err(e) => Err(e),
+        };
+        results.push(result);
+    }
+    results
+}
+
+/// Client for probing URLs with the engine's user agent and proxy
+///
+/// `proxy` replaces the engine's proxy, and an empty one connects
+/// directly. Without either, the usual proxy environment variables apply,
+/// as they do for the engine. Proxy credentials are taken from the URL.
+///
+/// It does not follow redirects itself so `resolve_http_url` can report
+/// them. A server that accepts the connection but never answers gives up
+/// after a minute rather than holding up a batch.
+fn probe_client(
+    config: &gosh_dl::EngineConfig,
+    proxy: Option<&str>,
+) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .connect_timeout(Duration::from_secs(15))
+        .timeout(Duration::from_secs(60));
+    if !config.user_agent.is_empty() {
+        builder = builder.user_agent(config.user_agent.as_str());
+    }
+    match proxy.or(config.http.proxy_url.as_deref()) {
+        Some("") => builder = builder.no_proxy(),
+        Some(proxy) => {
+            let proxy = reqwest::Proxy::all(proxy).map_err(|e| format!("Invalid proxy: {}", e))?;
+            builder = builder.proxy(proxy);
+        }
+        None => {}
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+/// Follow a URL's redirects with a HEAD request, or a one-byte ranged GET
+/// for servers that refuse HEAD, and report what it serves
+///
+/// Redirects are followed here rather than by reqwest so the chain can be
+/// reported. Custom headers and cookies are only sent to the host of the
+/// original URL.
 async fn resolve_http_url(
+    client: &reqwest::Client,
     url: &str,
     referer: Option<&str>,
     user_agent: Option<&str>,
     headers: &[(String, String)],
     cookies: Option<&[String]>,
-) -> Result<String, gosh_dl::EngineError> {
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::limited(10))
-        .build()
-        .map_err(|e| gosh_dl::EngineError::Internal(format!("Failed to build HTTP client: {}", e)))?;
-
-    let mut head_req = client.head(url);
-    if let Some(ua) = user_agent {
-        head_req = head_req.header(USER_AGENT, ua);
-    }
-    if let Some(r) = referer {
-        head_req = head_req.header(REFERER, r);
-    }
-    for (key, value) in headers {
-        head_req = head_req.header(key, value);
-    }
-    if let Some(cookie_list) = cookies {
-        let cookie_header = cookie_list.join("; ");
-        head_req = head_req.header(COOKIE, cookie_header);
-    }
-
-    let head_resp = head_req.send().await;
-    let (final_url, content_type, content_disp) = match head_resp {
-        Ok(resp) if resp.status().is_success() => {
-            let content_type = resp
-                .headers()
-                .get(CONTENT_TYPE)
-                .and_then(|v| v.to_str().ok())
-                .map(str::to_string);
-            let content_disp = resp
-                .headers()
-                .get(CONTENT_DISPOSITION)
-                .and_then(|v| v.to_str().ok())
-                .map(str::to_string);
-            (resp.url().to_string(), content_type, content_disp)
+) -> Result<ProbeResult, gosh_dl::EngineError> {
+    let start = Url::parse(url).map_err(|e| gosh_dl::EngineError::InvalidInput {
+        field: "url",
+        message: format!("Invalid URL {}: {}", url, e),
+    })?;
+
+    let request = |method: Method, target: &Url| {
+        let mut req = client.request(method, target.clone());
+        if let Some(ua) = user_agent {
+            req = req.header(USER_AGENT, ua);
         }
-        _ => {
-            let mut get_req = client.get(url).header(RANGE, "bytes=0-0");
-            if let Some(ua) = user_agent {
-                get_req = get_req.header(USER_AGENT, ua);
-            }
-            if let Some(r) = referer {
-                get_req = get_req.header(REFERER, r);
-            }
+        if let Some(r) = referer {
+            req = req.header(REFERER, r);
+        }
+        if target.host_str() == start.host_str() {
             for (key, value) in headers {
-                get_req = get_req.header(key, value);
+                req = req.header(key, value);
             }
             if let Some(cookie_list) = cookies {
-                let cookie_header = cookie_list.join("; ");
-                get_req = get_req.header(COOKIE, cookie_header);
+                req = req.header(COOKIE, cookie_list.join("; "));
             }
-            let resp = get_req.send().await.map_err(|e| {
-                gosh_dl::EngineError::Network {
-                    kind: gosh_dl::NetworkErrorKind::Other,
-                    message: format!("Failed to resolve URL: {}", e),
-                    retryable: true,
-                }
-            })?;
-            let content_type = resp
-                .headers()
-                .get(CONTENT_TYPE)
-                .and_then(|v| v.to_str().ok())
-                .map(str::to_string);
-            let content_disp = resp
-                .headers()
-                .get(CONTENT_DISPOSITION)
-                .and_then(|v| v.to_str().ok())
-                .map(str::to_string);
-            (resp.url().to_string(), content_type, content_disp)
         }
+        req
     };
 
+    let head = follow_redirects(&start, |target| request(Method::HEAD, target)).await;
+    let (resp, redirects) = match head {
+        Ok((resp, redirects)) if resp.status().is_success() => (resp, redirects),
+        _ => follow_redirects(&start, |target| {
+            request(Method::GET, target).header(RANGE, "bytes=0-0")
+        })
+        .await
+        .map_err(|e| gosh_dl::EngineError::Network {
+            kind: gosh_dl::NetworkErrorKind::Other,
+            message: format!("Failed to resolve URL: {}", e),
+            retryable: true,
+        })?,
+    };
+
+    let final_url = resp.url().to_string();
+    let content_type = header_value(&resp, CONTENT_TYPE);
+    let content_disp = header_value(&resp, CONTENT_DISPOSITION);
     if looks_like_html_download(&final_url, content_type.as_deref(), content_disp.as_deref()) {
         return Err(gosh_dl::EngineError::InvalidInput {
             field: "url",
@@ -337,7 +809,65 @@ async fn resolve_http_url(
         });
     }
 
-    Ok(final_url)
+    // A ranged GET answers with the size after the slash of Content-Range
+    let partial = resp.status() == StatusCode::PARTIAL_CONTENT;
+    let content_length = if partial {
+        header_value(&resp, CONTENT_RANGE)
+            .and_then(|range| range.rsplit('/').next().and_then(|n| n.parse().ok()))
+    } else {
+        header_value(&resp, CONTENT_LENGTH).and_then(|n| n.parse().ok())
+    };
+    let resumable = partial
+        || header_value(&resp, ACCEPT_RANGES)
+            .is_some_and(|ranges| ranges.eq_ignore_ascii_case("bytes"));
+
+    Ok(ProbeResult {
+        url: url.to_string(),
+        file_name: filename::suggest(content_disp.as_deref(), &final_url, content_type.as_deref()),
+        final_url,
+        redirects,
+        content_length,
+        mime_type: content_type.and_then(|t| t.split(';').next().map(|t| t.trim().to_string())),
+        resumable,
+        etag: header_value(&resp, ETAG),
+        last_modified: header_value(&resp, LAST_MODIFIED),
+    })
+}
+
+/// Send the request `build` makes for `start` and for each URL it
+/// redirects to, returning the last response and the URLs redirected to
+async fn follow_redirects<F>(
+    start: &Url,
+    build: F,
+) -> Result<(reqwest::Response, Vec<String>), String>
+where
+    F: Fn(&Url) -> reqwest::RequestBuilder,
+{
+    let mut current = start.clone();
+    let mut redirects = Vec::new();
+    loop {
+        let resp = build(&current).send().await.map_err(|e| e.to_string())?;
+        if !resp.status().is_redirection() {
+            return Ok((resp, redirects));
+        }
+        let Some(location) = header_value(&resp, LOCATION) else {
+            return Ok((resp, redirects));
+        };
+        if redirects.len() == MAX_REDIRECTS {
+            return Err(format!("more than {} redirects", MAX_REDIRECTS));
+        }
+        current = current
+            .join(&location)
+            .map_err(|e| format!("bad redirect to {}: {}", location, e))?;
+        redirects.push(current.to_string());
+    }
+}
+
+fn header_value(resp: &reqwest::Response, name: HeaderName) -> Option<String> {
+    resp.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
 }
 
 fn looks_like_html_download(url: &str, content_type: Option<&str>, content_disp: Option<&str>) -> bool {
@@ -376,12 +906,13 @@ fn convert_options(opts: FrontendOptions) -> DownloadOptions {
             .collect()
     });
 
-    // Build checksum if provided
+    // Build checksum if provided; the service checks the other algorithms
+    // once the file is complete
     let checksum = opts.checksum_type.zip(opts.checksum_value).and_then(|(t, v)| {
         use gosh_dl::http::ExpectedChecksum;
-        match t.to_lowercase().as_str() {
-            "md5" => Some(ExpectedChecksum::md5(v)),
-            "sha256" => Some(ExpectedChecksum::sha256(v)),
+        match checksum::normalize(&t) {
+            Some("md5") => Some(ExpectedChecksum::md5(v)),
+            Some("sha256") => Some(ExpectedChecksum::sha256(v)),
             _ => None,
         }
     });
@@ -484,6 +1015,10 @@ fn convert_status(status: DownloadStatus) -> Download {
                 .map(|f| f.index)
                 .collect()
         }),
+        category: None,
+        scheduled_start: None,
+        checksum: None,
+        conflict: None,
     }
 }
 
@@ -522,4 +1057,123 @@ mod tests {
             Some("attachment; filename=\"file.bin\"")
         ));
     }
+
+    #[tokio::test]
+    async fn test_one_failing_url_leaves_the_rest_of_a_batch() {
+        let urls: Vec<String> = ["a", "unreachable", "b", "full", "c"]
+            .map(String::from)
+            .to_vec();
+        let mut added = Vec::new();
+        let results = add_each(
+            &urls,
+            |url| async move {
+                if url == "unreachable" {
+                    Err(format!("{} timed out", url))
+                } else {
+                    Ok(url.len())
+                }
+            },
+            async |url, _| {
+                if url == "full" {
+                    return Err("disk full".to_string());
+                }
+                added.push(url);
+                Ok(format!("gid-{}", url))
+            },
+        )
+        .await;
+
+        assert_eq!(
+            results,
+            [
+                Ok("gid-a".to_string()),
+                Err("unreachable timed out".to_string()),
+                Ok("gid-b".to_string()),
+                Err("disk full".to_string()),
+                Ok("gid-c".to_string()),
+            ]
+        );
+        assert_eq!(added, ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_probe_follows_redirects() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let n = stream.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]).to_string();
+                let response = if request.starts_with("HEAD /get?id=7 ") {
+                    "HTTP/1.1 302 Found\r\nLocation: /files/release\r\n"
+                } else if request.starts_with("HEAD /files/release ") {
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/zip\r\nContent-Length: 1234\r\n\
+                     Accept-Ranges: bytes\r\nETag: \"v1\"\r\n"
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n"
+                };
+                let response = format!("{}Connection: close\r\n\r\n", response);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let url = format!("{}/get?id=7", base);
+        let client = probe_client(&gosh_dl::EngineConfig::default(), Some("")).unwrap();
+        let probe = resolve_http_url(&client, &url, None, None, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(probe.url, url);
+        assert_eq!(probe.final_url, format!("{}/files/release", base));
+        assert_eq!(probe.redirects, vec![probe.final_url.clone()]);
+        assert_eq!(probe.content_length, Some(1234));
+        assert_eq!(probe.mime_type.as_deref(), Some("application/zip"));
+        assert_eq!(probe.file_name.as_deref(), Some("release.zip"));
+        assert!(probe.resumable);
+        assert_eq!(probe.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(probe.last_modified, None);
+    }
+
+    #[tokio::test]
+    async fn test_probe_goes_through_proxy() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let settings = crate::types::Settings {
+            proxy_enabled: true,
+            proxy_url: listener.local_addr().unwrap().to_string(),
+            proxy_user: Some("me".to_string()),
+            proxy_pass: Some("p@ss:w/rd".to_string()),
+            ..Default::default()
+        };
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let n = stream.read(&mut request).await.unwrap();
+            let response = "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n";
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request[..n]).to_lowercase()
+        });
+
+        let mut config = gosh_dl::EngineConfig {
+            user_agent: "gosh-fetch-test".to_string(),
+            ..Default::default()
+        };
+        config.http.proxy_url = crate::service::proxy_url(&settings);
+        let client = probe_client(&config, None).unwrap();
+        let url = "http://downloads.invalid/file.iso";
+        let probe = resolve_http_url(&client, url, None, None, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(probe.content_length, Some(10));
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("head http://downloads.invalid/file.iso "));
+        assert!(request.contains("proxy-authorization: basic bwu6cebzczp3l3jk"));
+        assert!(request.contains("user-agent: gosh-fetch-test"));
+
+        assert!(probe_client(&config, Some("not a proxy")).is_err());
+    }
 }
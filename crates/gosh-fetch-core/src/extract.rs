@@ -0,0 +1,412 @@
+//! Archive extraction
+//!
+//! Unpacks zip, tar, tar.gz and tar.zst archives. Entries are written to a
+//! hidden staging folder first and only moved into place once the whole
+//! archive has unpacked, so a failure leaves nothing behind. Archives with
+//! absolute or `..` paths are refused, links are skipped, and unpacking
+//! stops once the output outgrows the archive by more than `MAX_RATIO`.
+
+use crate::error::{Error, Result};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Most an archive may expand to, as a multiple of its own size
+const MAX_RATIO: u64 = 100;
+/// Expansion allowed to any archive, however small
+const MIN_LIMIT: u64 = 256 * 1024 * 1024;
+/// Most entries an archive may hold
+const MAX_ENTRIES: usize = 100_000;
+
+/// Archive formats, by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+const EXTENSIONS: &[(&str, Format)] = &[
+    (".tar.gz", Format::TarGz),
+    (".tgz", Format::TarGz),
+    (".tar.zst", Format::TarZst),
+    (".tzst", Format::TarZst),
+    (".tar", Format::Tar),
+    (".zip", Format::Zip),
+];
+
+/// The format of an archive file name and the name without its extension
+pub fn format_of(name: &str) -> Option<(Format, &str)> {
+    let lower = name.to_lowercase();
+    EXTENSIONS.iter().find_map(|(extension, format)| {
+        lower
+            .ends_with(extension)
+            .then(|| (*format, &name[..name.len() - extension.len()]))
+            .filter(|(_, stem)| !stem.is_empty())
+    })
+}
+
+/// Whether a file name looks like an archive this module unpacks
+pub fn is_archive(name: &str) -> bool {
+    format_of(name).is_some()
+}
+
+/// The folder an archive is unpacked to next to itself
+pub fn sibling_dir(archive: &Path) -> Result<PathBuf> {
+    let name = archive.file_name().unwrap_or_default().to_string_lossy();
+    let (_, stem) = format_of(&name)
+        .ok_or_else(|| Error::InvalidInput(format!("{} is not an archive", name)))?;
+    Ok(archive.with_file_name(stem))
+}
+
+/// Unpack `archive` into `dest`
+///
+/// Without `merge`, `dest` must not exist yet and is created. With it the
+/// archive's top-level entries are added to the existing `dest`, failing
+/// before anything is moved if one of them is already there. `progress`
+/// is called with the bytes of the archive read so far and its size.
+pub fn extract(
+    archive: &Path,
+    dest: &Path,
+    merge: bool,
+    progress: &dyn Fn(u64, u64),
+) -> Result<()> {
+    let size = std::fs::metadata(archive)?.len();
+    let limit = size.saturating_mul(MAX_RATIO).max(MIN_LIMIT);
+    extract_limited(archive, dest, merge, limit, progress)
+}
+
+fn extract_limited(
+    archive: &Path,
+    dest: &Path,
+    merge: bool,
+    limit: u64,
+    progress: &dyn Fn(u64, u64),
+) -> Result<()> {
+    let name = archive.file_name().unwrap_or_default().to_string_lossy();
+    let (format, _) = format_of(&name)
+        .ok_or_else(|| Error::InvalidInput(format!("{} is not an archive", name)))?;
+    if !merge && dest.exists() {
+        return Err(Error::InvalidInput(format!(
+            "{} already exists",
+            dest.display()
+        )));
+    }
+
+    // Stage next to where the entries end up, so moving them is a rename
+    let parent = if merge {
+        dest.to_path_buf()
+    } else {
+        dest.parent().map(Path::to_path_buf).unwrap_or_default()
+    };
+    std::fs::create_dir_all(&parent)?;
+    let stage = parent.join(format!(".gosh-extract-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&stage)?;
+
+    let result =
+        unpack(archive, format, &stage, limit, progress).and_then(|()| commit(&stage, dest, merge));
+    if stage.exists() {
+        if let Err(e) = std::fs::remove_dir_all(&stage) {
+            log::warn!("Failed to remove {}: {}", stage.display(), e);
+        }
+    }
+    result
+}
+
+fn unpack(
+    archive: &Path,
+    format: Format,
+    stage: &Path,
+    limit: u64,
+    progress: &dyn Fn(u64, u64),
+) -> Result<()> {
+    let file = File::open(archive)?;
+    let size = file.metadata()?.len();
+    let mut limits = Limits {
+        remaining: limit,
+        entries: 0,
+    };
+
+    match format {
+        Format::Zip => unpack_zip(file, size, stage, &mut limits, progress),
+        Format::Tar => unpack_tar(Progress::new(file, size, progress), stage, &mut limits),
+        Format::TarGz => {
+            let reader = flate2::read::MultiGzDecoder::new(Progress::new(file, size, progress));
+            unpack_tar(reader, stage, &mut limits)
+        }
+        Format::TarZst => {
+            let reader = zstd::Decoder::new(Progress::new(file, size, progress))?;
+            unpack_tar(reader, stage, &mut limits)
+        }
+    }
+}
+
+/// Move staged entries into place
+fn commit(stage: &Path, dest: &Path, merge: bool) -> Result<()> {
+    if !merge {
+        std::fs::rename(stage, dest)?;
+        return Ok(());
+    }
+
+    let entries: Vec<_> = std::fs::read_dir(stage)?.collect::<std::io::Result<_>>()?;
+    if let Some(taken) = entries
+        .iter()
+        .map(|entry| dest.join(entry.file_name()))
+        .find(|target| target.exists())
+    {
+        return Err(Error::InvalidInput(format!(
+            "{} already exists",
+            taken.display()
+        )));
+    }
+    for entry in entries {
+        std::fs::rename(entry.path(), dest.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// What is left of the size and entry budgets
+struct Limits {
+    remaining: u64,
+    entries: usize,
+}
+
+impl Limits {
+    fn next_entry(&mut self) -> Result<()> {
+        self.entries += 1;
+        if self.entries > MAX_ENTRIES {
+            return Err(Error::InvalidInput(format!(
+                "archive has more than {} entries",
+                MAX_ENTRIES
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Reader that reports how much of the archive has been read, once per
+/// percent
+struct Progress<'a, R> {
+    inner: R,
+    read: u64,
+    size: u64,
+    reported: u64,
+    progress: &'a dyn Fn(u64, u64),
+}
+
+impl<'a> Progress<'a, BufReader<File>> {
+    fn new(file: File, size: u64, progress: &'a dyn Fn(u64, u64)) -> Self {
+        Self {
+            inner: BufReader::new(file),
+            read: 0,
+            size,
+            reported: 0,
+            progress,
+        }
+    }
+}
+
+impl<R: Read> Read for Progress<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        let percent = self.read * 100 / self.size.max(1);
+        if percent > self.reported {
+            self.reported = percent;
+            (self.progress)(self.read, self.size);
+        }
+        Ok(n)
+    }
+}
+
+/// An entry's path inside the archive, or `None` if it would leave it
+fn safe_path(path: &Path) -> Option<PathBuf> {
+    let mut safe = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => safe.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!safe.as_os_str().is_empty()).then_some(safe)
+}
+
+fn unsafe_entry(name: &str) -> Error {
+    Error::InvalidInput(format!("archive entry {} points outside the folder", name))
+}
+
+fn write_file(
+    stage: &Path,
+    relative: &Path,
+    reader: &mut dyn Read,
+    mode: Option<u32>,
+    limits: &mut Limits,
+) -> Result<()> {
+    let path = stage.join(relative);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(&path)?;
+
+    // Count what is actually written rather than trusting the headers
+    let written = std::io::copy(&mut reader.take(limits.remaining + 1), &mut file)?;
+    if written > limits.remaining {
+        return Err(Error::InvalidInput(
+            "archive expands to more than its size allows".to_string(),
+        ));
+    }
+    limits.remaining -= written;
+
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        // Keep the executable bits, never setuid or group/world write
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode & 0o755))?;
+    }
+    Ok(())
+}
+
+fn unpack_tar(reader: impl Read, stage: &Path, limits: &mut Limits) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        limits.next_entry()?;
+
+        let path = entry.path()?.into_owned();
+        let relative = safe_path(&path).ok_or_else(|| unsafe_entry(&path.to_string_lossy()))?;
+        let entry_type = entry.header().entry_type();
+        let mode = entry.header().mode().ok();
+        if entry_type.is_dir() {
+            std::fs::create_dir_all(stage.join(&relative))?;
+        } else if entry_type.is_file() {
+            write_file(stage, &relative, &mut entry, mode, limits)?;
+        } else {
+            log::warn!("Skipping {} ({:?}) in archive", path.display(), entry_type);
+        }
+    }
+    Ok(())
+}
+
+fn unpack_zip(
+    file: File,
+    size: u64,
+    stage: &Path,
+    limits: &mut Limits,
+    progress: &dyn Fn(u64, u64),
+) -> Result<()> {
+    let invalid = |e: zip::result::ZipError| Error::InvalidInput(format!("Invalid zip: {}", e));
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(invalid)?;
+    let mut read = 0;
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(invalid)?;
+        limits.next_entry()?;
+
+        let relative = entry
+            .enclosed_name()
+            .and_then(|path| safe_path(&path))
+            .ok_or_else(|| unsafe_entry(entry.name()))?;
+        if entry.is_dir() {
+            std::fs::create_dir_all(stage.join(&relative))?;
+        } else if entry.is_symlink() {
+            log::warn!("Skipping symlink {} in archive", entry.name());
+        } else {
+            let mode = entry.unix_mode();
+            write_file(stage, &relative, &mut entry, mode, limits)?;
+        }
+
+        read += entry.compressed_size();
+        progress(read.min(size), size);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn tar_gz(path: &Path, files: &[(&str, &[u8])]) {
+        let encoder =
+            flate2::write::GzEncoder::new(File::create(path).unwrap(), Default::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_guards_and_merges() {
+        let root = std::env::temp_dir().join(format!("gosh-fetch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let no_progress = |_: u64, _: u64| {};
+
+        assert_eq!(
+            format_of("Release.TAR.GZ"),
+            Some((Format::TarGz, "Release"))
+        );
+        assert_eq!(format_of("notes.txt"), None);
+        assert_eq!(format_of(".zip"), None);
+
+        // A tar.gz goes into a sibling folder named after it
+        let archive = root.join("bundle.tar.gz");
+        tar_gz(
+            &archive,
+            &[("docs/readme.txt", b"hello"), ("run.sh", b"#!/bin/sh\n")],
+        );
+        let sibling = sibling_dir(&archive).unwrap();
+        assert_eq!(sibling, root.join("bundle"));
+        extract(&archive, &sibling, false, &no_progress).unwrap();
+        assert_eq!(
+            std::fs::read(sibling.join("docs/readme.txt")).unwrap(),
+            b"hello"
+        );
+        assert!(extract(&archive, &sibling, false, &no_progress).is_err());
+
+        // Merging refuses to overwrite
+        let shared = root.join("shared");
+        std::fs::create_dir_all(&shared).unwrap();
+        extract(&archive, &shared, true, &no_progress).unwrap();
+        assert!(shared.join("run.sh").is_file());
+        assert!(extract(&archive, &shared, true, &no_progress).is_err());
+
+        // Output beyond the limit is refused and nothing is left behind
+        let big = root.join("big.tar.gz");
+        tar_gz(&big, &[("zeros", &[0u8; 4096])]);
+        let dest = root.join("big");
+        assert!(extract_limited(&big, &dest, false, 1024, &no_progress).is_err());
+        assert!(!dest.exists());
+
+        // Zip entries that climb out of the folder are refused
+        let zip_path = root.join("evil.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("ok.txt", options).unwrap();
+        writer.write_all(b"fine").unwrap();
+        writer.start_file("../escape.txt", options).unwrap();
+        writer.write_all(b"bad").unwrap();
+        writer.finish().unwrap();
+        let dest = root.join("evil");
+        assert!(extract(&zip_path, &dest, false, &no_progress).is_err());
+        assert!(!dest.exists() && !root.join("escape.txt").exists());
+
+        let leftovers = std::fs::read_dir(&root)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with(".gosh-extract-")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,426 @@
+//! RSS/Atom and podcast feed subscriptions
+//!
+//! A background poller checks each enabled feed once its poll interval has
+//! passed. Items whose title passes the feed's filters and that were not
+//! downloaded before (by GUID or link) are handed to the download service as
+//! `EngineCommand`s: enclosures as HTTP downloads, `.torrent` links as
+//! torrents and magnet links as magnets.
+
+use crate::db::{Database, FeedsDb};
+use crate::error::{Error, Result};
+use crate::service::EngineCommand;
+use crate::types::{DownloadOptions, Feed};
+use regex::{Regex, RegexBuilder};
+use roxmltree::{Document, Node};
+use std::time::Duration;
+
+/// How often the poller looks for feeds that are due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What an item links to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedLink {
+    Url(String),
+    Torrent(String),
+    Magnet(String),
+}
+
+impl FeedLink {
+    fn new(url: &str, mime_type: Option<&str>) -> Self {
+        let url = url.trim().to_string();
+        let path = url.split(['?', '#']).next().unwrap_or("").to_lowercase();
+        if url.starts_with("magnet:") {
+            FeedLink::Magnet(url)
+        } else if mime_type == Some("application/x-bittorrent") || path.ends_with(".torrent") {
+            FeedLink::Torrent(url)
+        } else {
+            FeedLink::Url(url)
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        match self {
+            FeedLink::Url(url) | FeedLink::Torrent(url) | FeedLink::Magnet(url) => url,
+        }
+    }
+}
+
+/// Downloadable item of a feed
+#[derive(Debug, Clone)]
+pub struct FeedItem {
+    pub guid: String,
+    pub title: String,
+    pub link: FeedLink,
+}
+
+/// Parsed feed document
+#[derive(Debug, Clone, Default)]
+pub struct ParsedFeed {
+    pub title: Option<String>,
+    /// Items with something to download, in document order
+    pub items: Vec<FeedItem>,
+}
+
+/// Parse an RSS 2.0, RSS 1.0 or Atom document
+///
+/// Items without an enclosure, `.torrent` link or magnet link are skipped.
+pub fn parse_feed(xml: &str) -> Result<ParsedFeed> {
+    let doc =
+        Document::parse(xml).map_err(|e| Error::InvalidInput(format!("Invalid feed: {}", e)))?;
+    let root = doc.root_element();
+
+    let channel = match root.tag_name().name() {
+        "rss" => element(root, "channel").unwrap_or(root),
+        "RDF" => element(root, "channel").unwrap_or(root),
+        "feed" => root,
+        other => {
+            return Err(Error::InvalidInput(format!(
+                "Invalid feed: unexpected <{}> element",
+                other
+            )))
+        }
+    };
+
+    let items = root
+        .descendants()
+        .filter(|n| matches!(n.tag_name().name(), "item" | "entry"))
+        .filter_map(parse_item)
+        .collect();
+
+    Ok(ParsedFeed {
+        title: text(channel, "title"),
+        items,
+    })
+}
+
+fn element<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn text(node: Node, name: &str) -> Option<String> {
+    element(node, name)
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+fn parse_item(item: Node) -> Option<FeedItem> {
+    let mut enclosures = Vec::new();
+    let mut links = Vec::new();
+
+    for child in item.children().filter(Node::is_element) {
+        match child.tag_name().name() {
+            "enclosure" => {
+                if let Some(url) = child.attribute("url") {
+                    enclosures.push(FeedLink::new(url, child.attribute("type")));
+                }
+            }
+            // ezRSS torrent namespace
+            "magnetURI" => {
+                if let Some(uri) = child.text() {
+                    links.push(FeedLink::new(uri, None));
+                }
+            }
+            "link" => match child.attribute("href") {
+                // Atom
+                Some(href) => {
+                    let link = FeedLink::new(href, child.attribute("type"));
+                    if child.attribute("rel") == Some("enclosure") {
+                        enclosures.push(link);
+                    } else {
+                        links.push(link);
+                    }
+                }
+                // RSS
+                None => {
+                    if let Some(url) = child.text() {
+                        links.push(FeedLink::new(url, None));
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // A plain link is only worth downloading when it is a torrent or magnet
+    let link = enclosures
+        .into_iter()
+        .next()
+        .or_else(|| links.into_iter().find(|l| !matches!(l, FeedLink::Url(_))))?;
+    let guid = text(item, "guid")
+        .or_else(|| text(item, "id"))
+        .unwrap_or_else(|| link.url().to_string());
+
+    Some(FeedItem {
+        guid,
+        title: text(item, "title").unwrap_or_default(),
+        link,
+    })
+}
+
+/// A feed's include and exclude patterns
+pub struct Filter {
+    include: Option<Regex>,
+    exclude: Option<Regex>,
+}
+
+impl Filter {
+    pub fn new(feed: &Feed) -> Result<Self> {
+        Ok(Self {
+            include: compile(feed.include_pattern.as_deref())?,
+            exclude: compile(feed.exclude_pattern.as_deref())?,
+        })
+    }
+
+    /// Whether an item with this title should be downloaded
+    pub fn matches(&self, title: &str) -> bool {
+        self.include.as_ref().is_none_or(|re| re.is_match(title))
+            && !self.exclude.as_ref().is_some_and(|re| re.is_match(title))
+    }
+}
+
+fn compile(pattern: Option<&str>) -> Result<Option<Regex>> {
+    match pattern.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(pattern) => RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(Some)
+            .map_err(|e| Error::InvalidInput(format!("Invalid feed filter: {}", e))),
+        None => Ok(None),
+    }
+}
+
+/// HTTP client for fetching feeds, the torrents they link to and metalinks
+pub fn http_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .user_agent(concat!("Gosh-Fetch/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| Error::Network(e.to_string()))
+}
+
+pub(crate) async fn fetch(client: &reqwest::Client, url: &str) -> Result<Vec<u8>> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| Error::Network(format!("Failed to fetch {}: {}", url, e)))?;
+    if !response.status().is_success() {
+        return Err(Error::Network(format!(
+            "Failed to fetch {}: HTTP {}",
+            url,
+            response.status()
+        )));
+    }
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| Error::Network(format!("Failed to read {}: {}", url, e)))?;
+    Ok(body.to_vec())
+}
+
+/// Check a feed once, queue its new matching items and record the outcome
+///
+/// Returns the number of downloads queued.
+pub async fn check_feed(
+    client: &reqwest::Client,
+    db: &Database,
+    feed: &Feed,
+    cmd_sender: &async_channel::Sender<EngineCommand>,
+) -> Result<usize> {
+    let result = poll(client, db, feed, cmd_sender).await;
+    let now = chrono::Utc::now().timestamp();
+    let (title, error) = match &result {
+        Ok((title, _)) => (title.as_deref(), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    FeedsDb::set_checked(db, feed.id, title, now, error.as_deref())?;
+    result.map(|(_, queued)| queued)
+}
+
+async fn poll(
+    client: &reqwest::Client,
+    db: &Database,
+    feed: &Feed,
+    cmd_sender: &async_channel::Sender<EngineCommand>,
+) -> Result<(Option<String>, usize)> {
+    let filter = Filter::new(feed)?;
+    let body = fetch(client, &feed.url).await?;
+    let parsed = parse_feed(&String::from_utf8_lossy(&body))?;
+
+    let options = feed.category.as_ref().map(|category| DownloadOptions {
+        category: Some(category.clone()),
+        ..Default::default()
+    });
+
+    // Feeds list the newest item first; queue in publication order
+    let mut queued = 0;
+    for item in parsed.items.iter().rev() {
+        let url = item.link.url();
+        if !filter.matches(&item.title) || FeedsDb::is_seen(db, feed.id, &item.guid, url)? {
+            continue;
+        }
+
+        let options = options.clone();
+        let cmd = match &item.link {
+            FeedLink::Url(url) => EngineCommand::AddDownload {
+                url: url.clone(),
+                options,
+            },
+            FeedLink::Magnet(uri) => EngineCommand::AddMagnet {
+                uri: uri.clone(),
+                options,
+            },
+            FeedLink::Torrent(url) => match fetch(client, url).await {
+                Ok(data) => EngineCommand::AddTorrent { data, options },
+                Err(e) => {
+                    // Not marked as seen, so the next check tries again
+                    log::warn!("Skipping feed item {}: {}", item.title, e);
+                    continue;
+                }
+            },
+        };
+
+        log::info!("Queueing {} from feed {}", item.title, feed.url);
+        cmd_sender.send(cmd).await?;
+        FeedsDb::mark_seen(db, feed.id, &item.guid, url, &item.title)?;
+        queued += 1;
+    }
+
+    Ok((parsed.title, queued))
+}
+
+/// Poll due feeds in a background thread, queueing downloads on `cmd_sender`
+///
+/// Stops when the command channel closes.
+pub fn spawn(db: Database, cmd_sender: async_channel::Sender<EngineCommand>) {
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
+
+        rt.block_on(async move {
+            let client = match http_client() {
+                Ok(client) => client,
+                Err(e) => {
+                    log::error!("Feed poller disabled: {}", e);
+                    return;
+                }
+            };
+
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            while !cmd_sender.is_closed() {
+                interval.tick().await;
+
+                let now = chrono::Utc::now().timestamp();
+                let feeds = FeedsDb::get_due(&db, now).unwrap_or_else(|e| {
+                    log::error!("Failed to load feeds: {}", e);
+                    Vec::new()
+                });
+                for feed in feeds {
+                    if let Err(e) = check_feed(&client, &db, &feed, &cmd_sender).await {
+                        log::warn!("Failed to check feed {}: {}", feed.url, e);
+                    }
+                }
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const TORRENT: &[u8] = b"d4:infod4:name8:ep2.isoee";
+
+    /// Serve the feed built for the server's base URL at `/feed.xml`, and a
+    /// torrent at every other path
+    async fn serve(feed: impl FnOnce(&str) -> String) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let feed = feed(&base);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let body: Vec<u8> = if request.starts_with("GET /feed.xml") {
+                    feed.clone().into_bytes()
+                } else {
+                    TORRENT.to_vec()
+                };
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(&body).await;
+            }
+        });
+        base
+    }
+
+    #[tokio::test]
+    async fn test_check_feed_queues_new_items_once() {
+        let db = crate::db::in_memory();
+        let base = serve(|base| {
+            format!(
+                r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel><title>Show</title>
+              <item><title>Episode 3 (trailer)</title><guid>ep3t</guid>
+                <enclosure url="{base}/ep3-trailer.mp3" type="audio/mpeg"/></item>
+              <item><title>Episode 3</title><guid>ep3</guid>
+                <enclosure url="{base}/ep3.mp3" type="audio/mpeg"/></item>
+              <item><title>Episode 2</title><guid>ep2</guid>
+                <link>{base}/ep2.torrent</link></item>
+              <item><title>Episode 1</title>
+                <link>magnet:?xt=urn:btih:abc&amp;dn=ep1</link></item>
+              <item><title>Episode 0 show notes</title><link>{base}/notes.html</link></item>
+            </channel></rss>"#
+            )
+        })
+        .await;
+
+        let feed = Feed {
+            url: format!("{}/feed.xml", base),
+            include_pattern: Some("^episode".to_string()),
+            exclude_pattern: Some("trailer".to_string()),
+            category: Some("Podcasts".to_string()),
+            ..Default::default()
+        };
+        let feed = Feed {
+            id: FeedsDb::save(&db, &feed).unwrap(),
+            ..feed
+        };
+
+        let (sender, receiver) = async_channel::unbounded();
+        let client = http_client().unwrap();
+        assert_eq!(check_feed(&client, &db, &feed, &sender).await.unwrap(), 3);
+
+        let commands: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert!(matches!(
+            &commands[0],
+            EngineCommand::AddMagnet { uri, .. } if uri.ends_with("dn=ep1")
+        ));
+        assert!(matches!(
+            &commands[1],
+            EngineCommand::AddTorrent { data, .. } if data == TORRENT
+        ));
+        match &commands[2] {
+            EngineCommand::AddDownload { url, options } => {
+                assert!(url.ends_with("/ep3.mp3"));
+                let category = options.as_ref().and_then(|o| o.category.as_deref());
+                assert_eq!(category, Some("Podcasts"));
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+
+        // Seen items are not queued again
+        assert_eq!(check_feed(&client, &db, &feed, &sender).await.unwrap(), 0);
+        let feed = FeedsDb::get(&db, feed.id).unwrap().unwrap();
+        assert_eq!(feed.title, "Show");
+        assert!(feed.last_checked.is_some());
+        assert_eq!(feed.last_error, None);
+    }
+}
@@ -0,0 +1,307 @@
+//! Download file names
+//!
+//! Picks the name an HTTP download is saved under when the user did not
+//! give one: the server's `Content-Disposition` filename, preferring the
+//! RFC 5987 `filename*` form, then the last segment of the URL after
+//! redirects. A name without an extension gets one from the MIME type, so
+//! `download?id=123` is saved as `download.zip` rather than `download`.
+
+use reqwest::Url;
+
+/// Longest name most Linux filesystems accept, in bytes
+const MAX_NAME_BYTES: usize = 255;
+
+/// Name for a response without a usable filename or URL path
+const FALLBACK_NAME: &str = "download";
+
+/// Extensions of the server-side scripts that hand out files, which say
+/// nothing about the file itself
+const SCRIPT_EXTENSIONS: &[&str] = &["php", "asp", "aspx", "cgi", "jsp", "pl", "py"];
+
+/// The name to save a download under
+pub fn suggest(
+    content_disposition: Option<&str>,
+    final_url: &str,
+    content_type: Option<&str>,
+) -> Option<String> {
+    if let Some(name) = content_disposition.and_then(from_content_disposition) {
+        return Some(name);
+    }
+    let name = from_url(final_url);
+    let extension = content_type.and_then(extension_for_mime);
+    match (name, extension) {
+        (Some(name), Some(extension)) if lacks_extension(&name) => {
+            let stem = name
+                .rsplit_once('.')
+                .map_or(name.as_str(), |(stem, _)| stem);
+            sanitize(&format!("{}.{}", stem, extension))
+        }
+        (Some(name), _) => Some(name),
+        (None, Some(extension)) => Some(format!("{}.{}", FALLBACK_NAME, extension)),
+        (None, None) => None,
+    }
+}
+
+fn lacks_extension(name: &str) -> bool {
+    match name.rsplit_once('.') {
+        Some((_, extension)) => SCRIPT_EXTENSIONS
+            .iter()
+            .any(|script| extension.eq_ignore_ascii_case(script)),
+        None => true,
+    }
+}
+
+/// The filename of a `Content-Disposition` header
+///
+/// `filename*=UTF-8''...` (or `ISO-8859-1''...`) wins over `filename=`
+/// when both are given and it can be decoded.
+pub fn from_content_disposition(header: &str) -> Option<String> {
+    let mut plain = None;
+    let mut extended = None;
+    for param in split_params(header).into_iter().skip(1) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename*" => extended = decode_extended(value.trim()),
+            "filename" => plain = Some(unquote(value.trim())),
+            _ => {}
+        }
+    }
+    extended
+        .and_then(|name| sanitize(&name))
+        .or_else(|| plain.and_then(|name| sanitize(&name)))
+}
+
+/// The last segment of a URL's path, percent-decoded
+pub fn from_url(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let segment = url.path_segments()?.next_back()?;
+    let bytes = percent_decode(segment);
+    sanitize(&String::from_utf8_lossy(&bytes))
+}
+
+/// Make a server-supplied name safe to create in the download directory
+///
+/// Only the part after the last slash or backslash is kept, control
+/// characters are dropped, and leading dots and surrounding whitespace are
+/// trimmed so the file is neither hidden nor outside the directory. Names
+/// too long for the filesystem are shortened, keeping the extension.
+pub fn sanitize(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let name: String = name.chars().filter(|c| !c.is_control()).collect();
+    let name = name.trim().trim_start_matches('.').trim_end();
+    if name.is_empty() {
+        return None;
+    }
+    Some(truncate(name))
+}
+
+fn truncate(name: &str) -> String {
+    if name.len() <= MAX_NAME_BYTES {
+        return name.to_string();
+    }
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if name.len() - dot <= 16 => name.split_at(dot),
+        _ => (name, ""),
+    };
+    let mut end = MAX_NAME_BYTES - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &stem[..end], extension)
+}
+
+/// Split header parameters on `;`, leaving quoted strings intact
+fn split_params(header: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in header.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                params.push(&header[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&header[start..]);
+    params
+}
+
+fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"') else {
+        return value.to_string();
+    };
+    let inner = inner.strip_suffix('"').unwrap_or(inner);
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// Decode an RFC 5987 `charset'language'value`
+fn decode_extended(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let bytes = percent_decode(&unquote(parts.next()?));
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = match (bytes[i], bytes.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match byte {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// The usual extension for a MIME type, for names that lack one
+fn extension_for_mime(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    let extension = match mime.as_str() {
+        "application/zip" | "application/x-zip-compressed" => "zip",
+        "application/gzip" | "application/x-gzip" => "gz",
+        "application/x-tar" => "tar",
+        "application/zstd" => "zst",
+        "application/x-xz" => "xz",
+        "application/x-bzip2" => "bz2",
+        "application/x-7z-compressed" => "7z",
+        "application/vnd.rar" | "application/x-rar-compressed" => "rar",
+        "application/pdf" => "pdf",
+        "application/json" => "json",
+        "application/xml" | "text/xml" => "xml",
+        "application/x-bittorrent" => "torrent",
+        "application/metalink4+xml" => "meta4",
+        "application/x-iso9660-image" => "iso",
+        "application/vnd.debian.binary-package" => "deb",
+        "application/x-rpm" => "rpm",
+        "application/vnd.android.package-archive" => "apk",
+        "text/plain" => "txt",
+        "text/csv" => "csv",
+        "text/html" => "html",
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        "audio/mpeg" => "mp3",
+        "audio/ogg" => "ogg",
+        "audio/flac" => "flac",
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        "video/x-matroska" => "mkv",
+        _ => return None,
+    };
+    Some(extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_from_headers_urls_and_mime_types() {
+        assert_eq!(
+            from_content_disposition(
+                "attachment; filename=\"fallback.txt\"; filename*=UTF-8''%E2%82%AC%20rates.csv"
+            )
+            .as_deref(),
+            Some("€ rates.csv")
+        );
+        assert_eq!(
+            from_content_disposition("attachment; filename=\"a \\\"quoted\\\"; name.zip\"")
+                .as_deref(),
+            Some("a \"quoted\"; name.zip")
+        );
+        assert_eq!(
+            from_content_disposition("attachment; filename*=ISO-8859-1'en'%A3%20rates.txt")
+                .as_deref(),
+            Some("£ rates.txt")
+        );
+        assert_eq!(
+            from_content_disposition("attachment; filename=\"../../etc/.bashrc\"").as_deref(),
+            Some("bashrc")
+        );
+        assert_eq!(from_content_disposition("inline"), None);
+        assert_eq!(
+            from_content_disposition("attachment; filename=\"..\""),
+            None
+        );
+
+        assert_eq!(
+            suggest(
+                Some("attachment"),
+                "https://example.com/files/report%202026.pdf?x=1",
+                None
+            )
+            .as_deref(),
+            Some("report 2026.pdf")
+        );
+        assert_eq!(
+            suggest(
+                None,
+                "https://example.com/download?id=123",
+                Some("application/zip")
+            )
+            .as_deref(),
+            Some("download.zip")
+        );
+        assert_eq!(
+            suggest(
+                None,
+                "https://example.com/get.php?file=7",
+                Some("application/x-iso9660-image")
+            )
+            .as_deref(),
+            Some("get.iso")
+        );
+        assert_eq!(
+            suggest(
+                None,
+                "https://example.com/",
+                Some("application/pdf; charset=binary")
+            )
+            .as_deref(),
+            Some("download.pdf")
+        );
+        assert_eq!(suggest(None, "https://example.com/", None), None);
+
+        let long = format!("{}.tar.gz", "x".repeat(300));
+        let short = sanitize(&long).unwrap();
+        assert_eq!(short.len(), MAX_NAME_BYTES);
+        assert!(short.ends_with("x.gz"));
+    }
+}
@@ -0,0 +1,270 @@
+//! aria2 input files
+//!
+//! The format `aria2c -i` reads: each line lists the URIs of one download,
+//! separated by tabs, and the indented `name=value` lines after it set that
+//! download's options. Lines starting with `#` are comments. Extra URIs
+//! become mirrors, and options this app has no equivalent for are skipped.
+
+use crate::db::{Database, DownloadsDb};
+use crate::error::{Error, Result};
+use crate::service::EngineCommand;
+use crate::types::{Download, DownloadOptions, DownloadType};
+
+/// One download of an input file
+#[derive(Debug, Clone, Default)]
+pub struct InputEntry {
+    /// The download's URI followed by its mirrors
+    pub uris: Vec<String>,
+    pub options: DownloadOptions,
+}
+
+impl InputEntry {
+    /// Entry that adds a recorded download again
+    ///
+    /// Torrents are written as magnet links, since the file holds no
+    /// .torrent data. Returns `None` when there is nothing to add it from.
+    pub fn from_download(download: &Download, options: Option<DownloadOptions>) -> Option<Self> {
+        let uri = match download.download_type {
+            DownloadType::Http | DownloadType::Ftp | DownloadType::Metalink => {
+                download.url.clone()
+            }
+            DownloadType::Magnet => download.magnet_uri.clone(),
+            DownloadType::Torrent => download.magnet_uri.clone().or_else(|| {
+                download
+                    .info_hash
+                    .as_ref()
+                    .map(|h| format!("magnet:?xt=urn:btih:{}", h))
+            }),
+        }?;
+
+        let mut options = options.unwrap_or_default();
+        if options.dir.is_none() && !download.save_path.is_empty() {
+            options.dir = Some(download.save_path.clone());
+        }
+        let mut uris = vec![uri];
+        uris.extend(options.mirror_urls.take().unwrap_or_default());
+        Some(Self { uris, options })
+    }
+
+    /// Command that adds this download
+    pub fn into_command(self) -> EngineCommand {
+        let mut uris = self.uris.into_iter();
+        let uri = uris.next().unwrap_or_default();
+        let mut options = self.options;
+
+        if uri.starts_with("magnet:") {
+            return EngineCommand::AddMagnet {
+                uri,
+                options: Some(options),
+            };
+        }
+        let mirrors: Vec<String> = uris.collect();
+        if !mirrors.is_empty() {
+            options
+                .mirror_urls
+                .get_or_insert_with(Vec::new)
+                .extend(mirrors);
+        }
+        EngineCommand::AddDownload {
+            url: uri,
+            options: Some(options),
+        }
+    }
+}
+
+/// Parse an input file
+///
+/// Fails on option lines that are not `name=value` or that come before the
+/// first URI line.
+pub fn parse(text: &str) -> Result<Vec<InputEntry>> {
+    let mut entries: Vec<InputEntry> = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if !line.starts_with([' ', '\t']) {
+            entries.push(InputEntry {
+                uris: line
+                    .split('\t')
+                    .map(str::trim)
+                    .filter(|u| !u.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                options: DownloadOptions::default(),
+            });
+            continue;
+        }
+
+        let line_error = |message: &str| {
+            Error::InvalidInput(format!("Input file line {}: {}", index + 1, message))
+        };
+        let entry = entries
+            .last_mut()
+            .ok_or_else(|| line_error("option before the first URI"))?;
+        let (name, value) = line
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| line_error("expected name=value"))?;
+        set_option(&mut entry.options, name.trim(), value.trim());
+    }
+
+    Ok(entries)
+}
+
+/// Parse an input file into the commands that add its downloads
+pub fn parse_commands(text: &str) -> Result<Vec<EngineCommand>> {
+    Ok(parse(text)?
+        .into_iter()
+        .map(InputEntry::into_command)
+        .collect())
+}
+
+fn set_option(options: &mut DownloadOptions, name: &str, value: &str) {
+    let value = value.to_string();
+    match name {
+        "dir" => options.dir = Some(value),
+        "out" => options.out = Some(value),
+        "header" => options.header.get_or_insert_with(Vec::new).push(value),
+        "checksum" => match value.split_once('=') {
+            Some((kind, digest)) => {
+                // aria2 spells the hash types "sha-256", "sha-1", ...
+                options.checksum_type = Some(kind.to_lowercase().replace('-', ""));
+                options.checksum_value = Some(digest.to_string());
+            }
+            None => log::warn!("Ignoring checksum without a type: {}", value),
+        },
+        "user-agent" => options.user_agent = Some(value),
+        "referer" => options.referer = Some(value),
+        "all-proxy" => options.proxy = Some(value),
+        "max-connection-per-server" => options.max_connection_per_server = Some(value),
+        "max-download-limit" => options.max_download_limit = Some(value),
+        "max-upload-limit" => options.max_upload_limit = Some(value),
+        "select-file" => options.select_file = Some(value),
+        "seed-ratio" => options.seed_ratio = Some(value),
+        _ => log::warn!("Ignoring unsupported input file option {}", name),
+    }
+}
+
+/// Write entries in input file format
+pub fn write(entries: &[InputEntry]) -> String {
+    let mut text = String::new();
+    for entry in entries {
+        text.push_str(&entry.uris.join("\t"));
+        text.push('\n');
+
+        let options = &entry.options;
+        let mut option = |name: &str, value: &Option<String>| {
+            if let Some(value) = value {
+                text.push_str(&format!("  {}={}\n", name, value));
+            }
+        };
+        option("dir", &options.dir);
+        option("out", &options.out);
+        option(
+            "checksum",
+            &options
+                .checksum_type
+                .as_ref()
+                .zip(options.checksum_value.as_ref())
+                .map(|(kind, digest)| format!("{}={}", aria2_hash_name(kind), digest)),
+        );
+        for header in options.header.iter().flatten() {
+            option("header", &Some(header.clone()));
+        }
+        option("user-agent", &options.user_agent);
+        option("referer", &options.referer);
+        option(
+            "max-connection-per-server",
+            &options.max_connection_per_server,
+        );
+        option("max-download-limit", &options.max_download_limit);
+        option("max-upload-limit", &options.max_upload_limit);
+        option("select-file", &options.select_file);
+        option("seed-ratio", &options.seed_ratio);
+    }
+    text
+}
+
+/// "sha256" as aria2 writes it, "sha-256"
+fn aria2_hash_name(kind: &str) -> String {
+    let kind = kind.to_lowercase();
+    match kind.strip_prefix("sha") {
+        Some(bits) if !bits.starts_with('-') => format!("sha-{}", bits),
+        _ => kind,
+    }
+}
+
+/// Entries for every unfinished download, oldest first, with the options
+/// (credential headers included) each was added with
+pub fn queue(db: &Database) -> Result<Vec<InputEntry>> {
+    let mut downloads = DownloadsDb::get_incomplete(db)?;
+    downloads.reverse();
+    downloads.extend(DownloadsDb::get_scheduled(db)?);
+
+    let mut entries = Vec::new();
+    for download in &downloads {
+        let options = DownloadsDb::get_options(db, &download.gid)?;
+        match InputEntry::from_download(download, options) {
+            Some(entry) => entries.push(entry),
+            None => log::warn!("Not exporting {}: no URI recorded", download.name),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "# nightly images\n\
+        https://a.example.com/x.iso\thttps://b.example.com/x.iso\n\
+        \x20 dir=/srv/isos\n\
+        \x20 out=nightly.iso\n\
+        \x20 checksum=sha-256=abc123\n\
+        \x20 header=Authorization: Bearer t\n\
+        \x20 header=X-Trace: 1\n\
+        \tmax-connection-per-server=4\n\
+        \x20 split=8\n\
+        \n\
+        magnet:?xt=urn:btih:def456\n\
+        \x20 select-file=1,3\n";
+
+    #[test]
+    fn test_parse_and_write_round_trip() {
+        let entries = parse(INPUT).unwrap();
+        assert_eq!(entries.len(), 2);
+        let options = &entries[0].options;
+        assert_eq!(entries[0].uris.len(), 2);
+        assert_eq!(options.dir.as_deref(), Some("/srv/isos"));
+        assert_eq!(options.checksum_type.as_deref(), Some("sha256"));
+        assert_eq!(options.checksum_value.as_deref(), Some("abc123"));
+        assert_eq!(options.header.as_ref().map(Vec::len), Some(2));
+        assert_eq!(options.max_connection_per_server.as_deref(), Some("4"));
+
+        match entries[0].clone().into_command() {
+            EngineCommand::AddDownload { url, options } => {
+                assert_eq!(url, "https://a.example.com/x.iso");
+                let mirrors = options.and_then(|o| o.mirror_urls).unwrap();
+                assert_eq!(mirrors, ["https://b.example.com/x.iso"]);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        assert!(matches!(
+            entries[1].clone().into_command(),
+            EngineCommand::AddMagnet { options: Some(o), .. } if o.select_file.as_deref() == Some("1,3")
+        ));
+
+        let written = write(&entries);
+        assert!(written.contains("  checksum=sha-256=abc123\n"));
+        let reparsed = parse(&written).unwrap();
+        assert_eq!(reparsed.len(), 2);
+        assert_eq!(reparsed[0].uris, entries[0].uris);
+        assert_eq!(reparsed[0].options.header, entries[0].options.header);
+
+        assert!(parse("  dir=/tmp\nhttps://example.com/a\n").is_err());
+        assert!(parse("https://example.com/a\n  dir\n").is_err());
+    }
+}
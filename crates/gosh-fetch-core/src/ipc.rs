@@ -0,0 +1,347 @@
+//! Daemon control socket
+//!
+//! `gosh-fetchd` owns the `DownloadService` and exposes it over a Unix socket
+//! under `$XDG_RUNTIME_DIR`. The protocol is JSON lines: clients write one
+//! serialized `EngineCommand` per line and receive every `UiMessage` the
+//! service emits, one per line.
+//!
+//! Whoever can reach the socket controls the engine and sees every command,
+//! cookies and credential headers included, so both ends refuse a socket
+//! that is not the current user's or lives in a directory anyone else can
+//! write to.
+
+use crate::error::{Error, Result};
+use crate::service::{EngineCommand, UiMessage};
+use serde::Serialize;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+
+/// Environment variable that overrides the socket location
+pub const SOCKET_ENV: &str = "GOSH_FETCH_SOCKET";
+
+/// Get the daemon socket path
+///
+/// Without `$XDG_RUNTIME_DIR` the socket goes in `gosh-fetch-<uid>` under
+/// the temp directory, so users sharing `/tmp` do not share a directory.
+pub fn socket_path() -> PathBuf {
+    if let Ok(path) = std::env::var(SOCKET_ENV) {
+        if !path.trim().is_empty() {
+            return PathBuf::from(path);
+        }
+    }
+
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir.join("gosh-fetch"),
+        None => std::env::temp_dir().join(format!("gosh-fetch-{}", current_uid())),
+    };
+    dir.join("gosh-fetchd.sock")
+}
+
+/// Check whether a daemon of this user is accepting connections at `path`
+pub fn daemon_available(path: &Path) -> bool {
+    if std::fs::symlink_metadata(path).is_err() {
+        return false;
+    }
+    if let Err(e) = check_private(path) {
+        log::warn!("Not attaching to gosh-fetchd: {}", e);
+        return false;
+    }
+    std::os::unix::net::UnixStream::connect(path).is_ok()
+}
+
+/// Bind the control socket, replacing a stale socket file left by a crashed daemon
+///
+/// The directory is created with mode 0700 if missing; an existing one must
+/// already be the current user's with that mode.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if let Some(parent) = path.parent() {
+        if !parent.exists() {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(parent)?;
+        }
+        check_private_dir(parent)?;
+    }
+
+    if std::fs::symlink_metadata(path).is_ok() {
+        if daemon_available(path) {
+            return Err(Error::InvalidInput(format!(
+                "a daemon is already listening on {}",
+                path.display()
+            )));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and always succeeds
+    unsafe { libc::getuid() }
+}
+
+/// Check that `dir` is a directory of the current user that nobody else
+/// can write to or list
+fn check_private_dir(dir: &Path) -> Result<()> {
+    let meta = std::fs::symlink_metadata(dir)?;
+    if !meta.is_dir() || meta.uid() != current_uid() || meta.mode() & 0o777 != 0o700 {
+        return Err(Error::InvalidInput(format!(
+            "{} must be a directory owned by the current user with mode 0700",
+            dir.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Check that `path` is a socket of the current user in a private directory
+fn check_private(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        check_private_dir(parent)?;
+    }
+    let meta = std::fs::symlink_metadata(path)?;
+    if !meta.file_type().is_socket() || meta.uid() != current_uid() {
+        return Err(Error::InvalidInput(format!(
+            "{} is not a socket owned by the current user",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Send a single command to the daemon and disconnect
+pub fn send_command(path: &Path, cmd: &EngineCommand) -> Result<()> {
+    use std::io::{BufRead, Write};
+
+    check_private(path)?;
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+
+    // Wait for the greeting so the daemon is reading before we hang up
+    let mut greeting = String::new();
+    std::io::BufReader::new(&stream).read_line(&mut greeting)?;
+
+    let mut line = serde_json::to_string(cmd)?;
+    line.push('\n');
+    (&stream).write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Accept clients forever, forwarding their commands to the service and
+/// streaming service messages back to them
+pub async fn serve(
+    listener: UnixListener,
+    cmd_sender: async_channel::Sender<EngineCommand>,
+    events: broadcast::Sender<UiMessage>,
+) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let cmd_sender = cmd_sender.clone();
+        let events = events.subscribe();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, cmd_sender, events).await {
+                log::debug!("Control client disconnected: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_client(
+    stream: UnixStream,
+    cmd_sender: async_channel::Sender<EngineCommand>,
+    mut events: broadcast::Receiver<UiMessage>,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    write_line(&mut writer, &UiMessage::EngineReady).await?;
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else { return Ok(()) };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<EngineCommand>(&line) {
+                    Ok(cmd) => cmd_sender.send(cmd).await?,
+                    Err(e) => {
+                        let msg = UiMessage::Error(format!("Invalid command: {}", e));
+                        write_line(&mut writer, &msg).await?;
+                    }
+                }
+            }
+
+            event = events.recv() => match event {
+                Ok(msg) => write_line(&mut writer, &msg).await?,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Control client lagged, dropped {} messages", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Connection to a running `gosh-fetchd`
+///
+/// Mirrors `DownloadService`: once spawned, the same command and message
+/// channels a frontend would hand to the service are bridged to the daemon.
+pub struct DaemonClient {
+    stream: std::os::unix::net::UnixStream,
+}
+
+impl DaemonClient {
+    /// Connect to the daemon listening at `path`
+    pub fn connect(path: &Path) -> Result<Self> {
+        check_private(path)?;
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self { stream })
+    }
+
+    /// Spawn the bridge in a background thread
+    pub fn spawn(
+        self,
+        ui_sender: async_channel::Sender<UiMessage>,
+        cmd_receiver: async_channel::Receiver<EngineCommand>,
+    ) {
+        let stream = self.stream;
+
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
+
+            rt.block_on(async move {
+                let stream = match UnixStream::from_std(stream) {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+                        return;
+                    }
+                };
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+
+                loop {
+                    tokio::select! {
+                        cmd_result = cmd_receiver.recv() => {
+                            let Ok(cmd) = cmd_result else {
+                                log::debug!("Command channel closed");
+                                break;
+                            };
+                            if let Err(e) = write_line(&mut writer, &cmd).await {
+                                let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+                            }
+                        }
+
+                        line = lines.next_line() => match line {
+                            Ok(Some(line)) => match serde_json::from_str::<UiMessage>(&line) {
+                                Ok(msg) => {
+                                    let _ = ui_sender.send(msg).await;
+                                }
+                                Err(e) => log::warn!("Ignoring malformed daemon message: {}", e),
+                            },
+                            _ => {
+                                let _ = ui_sender
+                                    .send(UiMessage::Error("Lost connection to gosh-fetchd".to_string()))
+                                    .await;
+                                break;
+                            }
+                        }
+                    }
+                }
+            });
+        });
+    }
+}
+
+async fn write_line<W, T>(writer: &mut W, value: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_daemon_round_trip() {
+        let dir = std::env::temp_dir().join(format!("gosh-fetch-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("gosh-fetchd.sock");
+        let listener = bind(&path).unwrap();
+        assert!(daemon_available(&path));
+
+        let (cmd_sender, cmd_receiver) = async_channel::bounded(8);
+        let (events, _) = broadcast::channel(8);
+        tokio::spawn(serve(listener, cmd_sender, events.clone()));
+
+        let (ui_sender, ui_receiver) = async_channel::bounded(8);
+        let (client_sender, client_receiver) = async_channel::bounded(8);
+        DaemonClient::connect(&path)
+            .unwrap()
+            .spawn(ui_sender, client_receiver);
+
+        let timeout = Duration::from_secs(5);
+        let greeting = tokio::time::timeout(timeout, ui_receiver.recv())
+            .await
+            .unwrap();
+        assert!(matches!(greeting, Ok(UiMessage::EngineReady)));
+
+        client_sender
+            .send(EngineCommand::Remove {
+                gid: "abc".to_string(),
+                delete_files: true,
+            })
+            .await
+            .unwrap();
+        let cmd = tokio::time::timeout(timeout, cmd_receiver.recv())
+            .await
+            .unwrap();
+        assert!(matches!(
+            cmd,
+            Ok(EngineCommand::Remove { ref gid, delete_files: true }) if gid == "abc"
+        ));
+
+        events
+            .send(UiMessage::DownloadRemoved("abc".to_string()))
+            .unwrap();
+        let msg = tokio::time::timeout(timeout, ui_receiver.recv())
+            .await
+            .unwrap();
+        assert!(matches!(msg, Ok(UiMessage::DownloadRemoved(ref gid)) if gid == "abc"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_shared_socket_directory_is_refused() {
+        let dir = std::env::temp_dir().join(format!("gosh-fetch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        let path = dir.join("gosh-fetchd.sock");
+
+        // A socket planted in a directory others can write to is not trusted
+        let _planted = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        assert!(check_private(&path).is_err());
+        assert!(!daemon_available(&path));
+        assert!(DaemonClient::connect(&path).is_err());
+        assert!(bind(&path).is_err());
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(check_private(&path).is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
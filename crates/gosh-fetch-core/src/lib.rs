@@ -4,18 +4,44 @@
 //! database operations, and service layer for the Gosh-Fetch download manager.
 //! It is UI-agnostic and can be used with any frontend (Qt, COSMIC, CLI, etc.)
 
+pub mod backup;
+pub mod bandwidth;
+pub mod category;
+pub mod checksum;
+pub mod completion;
+pub mod conflict;
 pub mod db;
+pub mod dbus;
+pub mod duplicate;
 pub mod engine_adapter;
 pub mod error;
+pub mod extract;
+pub mod feeds;
+pub mod filename;
+pub mod input_file;
+pub mod ipc;
+pub mod metalink;
+mod scheduler;
 pub mod service;
+pub mod torrent;
 pub mod types;
 pub mod utils;
+pub mod watch;
 
 // Re-exports for convenience
-pub use db::{get_db_path, init_database, Database, DownloadsDb, SettingsDb, TrackersDb};
+pub use db::{
+    get_db_path, init_database, open_database, CategoriesDb, Database, DownloadsDb, FeedsDb,
+    SettingsDb, TrackersDb, WatchFoldersDb,
+};
+pub use dbus::DbusHandle;
 pub use engine_adapter::{EngineAdapter, PeerInfo, TorrentFileInfo};
 pub use error::{Error, Result};
-pub use service::{settings_to_engine_config, DownloadService, EngineCommand, UiMessage};
+pub use ipc::DaemonClient;
+pub use service::{
+    finish_download, record_ui_message, remove_download, restore_incomplete_downloads,
+    settings_to_engine_config, AddOutcome, DownloadService, EngineCommand, UiMessage,
+};
+pub use torrent::parse_torrent;
 pub use types::*;
 pub use utils::{calculate_progress, format_bytes, format_eta, format_speed, TrackerUpdater};
 
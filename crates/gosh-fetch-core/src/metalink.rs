@@ -0,0 +1,279 @@
+//! Metalink documents (RFC 5854 and Metalink 3.0)
+//!
+//! A metalink lists files together with the mirrors they can be fetched
+//! from and their hashes. Each file becomes an HTTP download: the
+//! best-ranked mirror is its URL, the other mirrors fill `mirror_urls` and
+//! the strongest hash listed fills the checksum options.
+
+use crate::error::{Error, Result};
+use crate::feeds;
+use crate::types::DownloadOptions;
+use roxmltree::{Document, Node};
+use std::path::{Path, PathBuf};
+
+const NS_V3: &str = "http://www.metalinker.org/";
+
+/// Hash types that can be verified, strongest first
+const HASH_PREFERENCE: &[&str] = &["sha512", "sha256", "sha1", "md5"];
+
+/// File listed in a metalink
+#[derive(Debug, Clone, Default)]
+pub struct MetalinkFile {
+    /// Relative path, possibly with subdirectories
+    pub name: String,
+    pub size: Option<u64>,
+    /// HTTP(S) sources, best first
+    pub urls: Vec<String>,
+    /// `(type, digest)` pairs, with types written like "sha256"
+    pub hashes: Vec<(String, String)>,
+}
+
+/// Whether a URL or path names a metalink by its extension
+pub fn is_metalink(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or("").to_lowercase();
+    path.ends_with(".metalink") || path.ends_with(".meta4")
+}
+
+/// Parse a Metalink 4 (`.meta4`) or 3.0 (`.metalink`) document
+///
+/// Files without an HTTP(S) source are skipped; a document left with no
+/// files is an error.
+pub fn parse_metalink(data: &[u8]) -> Result<Vec<MetalinkFile>> {
+    let invalid = |message: String| Error::InvalidInput(format!("Invalid metalink: {}", message));
+    let text = std::str::from_utf8(data).map_err(|e| invalid(e.to_string()))?;
+    let doc = Document::parse(text).map_err(|e| invalid(e.to_string()))?;
+
+    let root = doc.root_element();
+    if root.tag_name().name() != "metalink" {
+        return Err(invalid(format!(
+            "unexpected <{}> element",
+            root.tag_name().name()
+        )));
+    }
+    let v3 = root.tag_name().namespace() == Some(NS_V3)
+        || root
+            .attribute("version")
+            .is_some_and(|v| v.starts_with('3'));
+
+    let files: Vec<MetalinkFile> = root
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "file")
+        .filter_map(|n| parse_file(n, v3))
+        .collect();
+    if files.is_empty() {
+        return Err(invalid("no files with HTTP sources".to_string()));
+    }
+    Ok(files)
+}
+
+fn element<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn children<'a, 'input>(
+    node: Option<Node<'a, 'input>>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.into_iter()
+        .flat_map(|n| n.children())
+        .filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn parse_file(file: Node, v3: bool) -> Option<MetalinkFile> {
+    let name = sanitize_name(file.attribute("name")?)?;
+    let size = element(file, "size")
+        .and_then(|n| n.text())
+        .and_then(|s| s.trim().parse().ok());
+
+    // Metalink 3 nests hashes and URLs in <verification> and <resources>;
+    // only direct <hash> children count, not the piece hashes
+    let (hash_parent, url_parent) = if v3 {
+        (element(file, "verification"), element(file, "resources"))
+    } else {
+        (Some(file), Some(file))
+    };
+
+    let hashes = children(hash_parent, "hash")
+        .filter_map(|hash| {
+            let kind = hash.attribute("type")?.to_lowercase().replace('-', "");
+            let digest = hash.text()?.trim().to_lowercase();
+            Some((kind, digest))
+        })
+        .collect();
+
+    // Lower rank is better: Metalink 4 priorities count up from 1, while
+    // Metalink 3 preferences run from 100 (best) down to 0
+    let mut urls: Vec<(i64, String)> = children(url_parent, "url")
+        .filter_map(|url| {
+            let text = url.text()?.trim();
+            if !(text.starts_with("http://") || text.starts_with("https://")) {
+                return None;
+            }
+            let rank = if v3 {
+                100 - url
+                    .attribute("preference")
+                    .and_then(|p| p.parse::<i64>().ok())
+                    .unwrap_or(0)
+            } else {
+                url.attribute("priority")
+                    .and_then(|p| p.parse().ok())
+                    .unwrap_or(999_999)
+            };
+            Some((rank, text.to_string()))
+        })
+        .collect();
+    urls.sort_by_key(|(rank, _)| *rank);
+
+    if urls.is_empty() {
+        log::warn!("Skipping metalink file {}: no HTTP sources", name);
+        return None;
+    }
+
+    Some(MetalinkFile {
+        name,
+        size,
+        urls: urls.into_iter().map(|(_, url)| url).collect(),
+        hashes,
+    })
+}
+
+/// Drop empty, `.` and `..` components so a name cannot leave the
+/// download directory
+fn sanitize_name(name: &str) -> Option<String> {
+    let parts: Vec<&str> = name
+        .split(['/', '\\'])
+        .filter(|p| !p.is_empty() && *p != "." && *p != "..")
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// The URL and options to add each file with
+///
+/// Files whose names have subdirectories go into those subdirectories of
+/// `options.dir`, or of `default_dir` when it is unset. A checksum already
+/// in `options` is kept.
+pub fn downloads(
+    files: &[MetalinkFile],
+    options: &DownloadOptions,
+    default_dir: &Path,
+) -> Vec<(String, DownloadOptions)> {
+    files
+        .iter()
+        .filter_map(|file| {
+            let (url, mirrors) = file.urls.split_first()?;
+            let mut options = options.clone();
+
+            let (subdir, file_name) = file.name.rsplit_once('/').unwrap_or(("", &file.name));
+            if !subdir.is_empty() {
+                let base = options
+                    .dir
+                    .as_ref()
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| default_dir.to_path_buf());
+                options.dir = Some(base.join(subdir).to_string_lossy().to_string());
+            }
+            // An output name only makes sense for a single file
+            if files.len() > 1 || options.out.is_none() {
+                options.out = Some(file_name.to_string());
+            }
+
+            if !mirrors.is_empty() {
+                let mut urls = mirrors.to_vec();
+                urls.extend(options.mirror_urls.take().unwrap_or_default());
+                options.mirror_urls = Some(urls);
+            }
+
+            if options.checksum_type.is_none() {
+                let hash = HASH_PREFERENCE
+                    .iter()
+                    .find_map(|kind| file.hashes.iter().find(|(k, _)| k == kind));
+                if let Some((kind, digest)) = hash {
+                    options.checksum_type = Some(kind.clone());
+                    options.checksum_value = Some(digest.clone());
+                }
+            }
+
+            Some((url.clone(), options))
+        })
+        .collect()
+}
+
+/// Download a metalink document
+pub async fn fetch(url: &str) -> Result<Vec<u8>> {
+    let client = feeds::http_client()?;
+    feeds::fetch(&client, url).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const META4: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <metalink xmlns="urn:ietf:params:xml:ns:metalink">
+          <file name="../iso/distro.iso">
+            <size>1048576</size>
+            <hash type="sha-1">aaaa</hash>
+            <hash type="sha-256">BBBB</hash>
+            <pieces length="262144" type="sha-1"><hash>cccc</hash></pieces>
+            <url location="de" priority="2">https://de.example.com/distro.iso</url>
+            <url location="us" priority="1">https://us.example.com/distro.iso</url>
+            <url>ftp://ftp.example.com/distro.iso</url>
+            <metaurl mediatype="torrent">https://example.com/distro.torrent</metaurl>
+          </file>
+          <file name="distro.iso.sig">
+            <url>https://example.com/distro.iso.sig</url>
+          </file>
+          <file name="torrent-only">
+            <metaurl mediatype="torrent">https://example.com/t.torrent</metaurl>
+          </file>
+        </metalink>"#;
+
+    const METALINK3: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <metalink version="3.0" xmlns="http://www.metalinker.org/">
+          <files>
+            <file name="distro.iso">
+              <verification><hash type="md5">dddd</hash></verification>
+              <resources>
+                <url type="http" preference="10">http://slow.example.com/distro.iso</url>
+                <url type="http" preference="100">http://fast.example.com/distro.iso</url>
+              </resources>
+            </file>
+          </files>
+        </metalink>"#;
+
+    #[test]
+    fn test_metalink_files_become_downloads() {
+        let files = parse_metalink(META4.as_bytes()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].name, "iso/distro.iso");
+        assert_eq!(files[0].size, Some(1048576));
+        assert_eq!(files[0].hashes.len(), 2);
+
+        let options = DownloadOptions {
+            dir: Some("/data".to_string()),
+            ..Default::default()
+        };
+        let downloads = downloads(&files, &options, Path::new("/unused"));
+        let (url, options) = &downloads[0];
+        assert_eq!(url, "https://us.example.com/distro.iso");
+        assert_eq!(
+            options.mirror_urls.as_deref(),
+            Some(&["https://de.example.com/distro.iso".to_string()][..])
+        );
+        assert_eq!(options.dir.as_deref(), Some("/data/iso"));
+        assert_eq!(options.out.as_deref(), Some("distro.iso"));
+        assert_eq!(options.checksum_type.as_deref(), Some("sha256"));
+        assert_eq!(options.checksum_value.as_deref(), Some("bbbb"));
+        assert_eq!(downloads[1].1.dir.as_deref(), Some("/data"));
+        assert_eq!(downloads[1].1.checksum_type, None);
+
+        let files = parse_metalink(METALINK3.as_bytes()).unwrap();
+        assert_eq!(files[0].urls[0], "http://fast.example.com/distro.iso");
+        assert_eq!(files[0].hashes, [("md5".to_string(), "dddd".to_string())]);
+
+        assert!(parse_metalink(b"<rss/>").is_err());
+        assert!(is_metalink("https://example.com/distro.iso.meta4?mirror=1"));
+        assert!(!is_metalink("https://example.com/distro.iso"));
+    }
+}
@@ -0,0 +1,387 @@
+//! Scheduled downloads
+//!
+//! Downloads added with a `scheduled_start` in the future are not handed to
+//! the engine yet. They are recorded in the downloads table in the
+//! `scheduled` state, with their options and .torrent data stored like any
+//! other download, and the service starts them once they are due. Due times
+//! are compared against the wall clock on every tick, so a download whose
+//! time passed while the app was closed or the machine was suspended starts
+//! as soon as the service runs again.
+
+use crate::category::CategoryTarget;
+use crate::db::{Database, DownloadsDb};
+use crate::engine_adapter::EngineAdapter;
+use crate::error::{Error, Result};
+use crate::service::{replace_download, stored_options, UiMessage};
+use crate::torrent::parse_torrent;
+use crate::types::{Download, DownloadOptions, DownloadState, DownloadType};
+use std::time::Duration;
+
+/// How often the service looks for due downloads
+pub(crate) const TICK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// What a scheduled download will be added from
+pub(crate) enum Source {
+    Url(String),
+    Magnet(String),
+    Torrent(Vec<u8>),
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// The start time of options that ask to start later
+pub(crate) fn future_start(options: Option<&DownloadOptions>) -> Option<i64> {
+    options
+        .and_then(|o| o.scheduled_start)
+        .filter(|start| *start > now())
+}
+
+/// Whether the GID belongs to a download waiting for its start time
+pub(crate) fn is_scheduled(db: &Database, gid: &str) -> bool {
+    matches!(
+        DownloadsDb::get_by_gid(db, gid),
+        Ok(Some(download)) if download.status == DownloadState::Scheduled
+    )
+}
+
+/// Downloads waiting for their start time
+pub(crate) fn scheduled(db: &Database) -> Vec<Download> {
+    DownloadsDb::get_scheduled(db).unwrap_or_else(|e| {
+        log::error!("Failed to load scheduled downloads: {}", e);
+        Vec::new()
+    })
+}
+
+/// Record a download to be started at `start`
+pub(crate) async fn schedule(
+    adapter: &EngineAdapter,
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    source: Source,
+    options: DownloadOptions,
+    start: i64,
+) {
+    let mut download = Download {
+        gid: uuid::Uuid::new_v4().to_string(),
+        status: DownloadState::Scheduled,
+        save_path: options.dir.clone().unwrap_or_else(|| {
+            adapter
+                .get_config()
+                .download_dir
+                .to_string_lossy()
+                .to_string()
+        }),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        category: options.category.clone(),
+        scheduled_start: Some(start),
+        ..Default::default()
+    };
+
+    let target = match &source {
+        Source::Url(url) => {
+            download.download_type = DownloadType::Http;
+            download.url = Some(url.clone());
+            CategoryTarget::for_url(&[url.as_str()], options.out.as_deref(), None)
+        }
+        Source::Magnet(uri) => {
+            download.download_type = DownloadType::Magnet;
+            download.magnet_uri = Some(uri.clone());
+            CategoryTarget::for_magnet(uri)
+        }
+        Source::Torrent(data) => {
+            download.download_type = DownloadType::Torrent;
+            match parse_torrent(data) {
+                Ok(info) => {
+                    download.info_hash = Some(info.info_hash.clone());
+                    download.total_size = info.total_size;
+                    download.name = info.name;
+                }
+                Err(e) => {
+                    let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+                    return;
+                }
+            }
+            CategoryTarget::default()
+        }
+    };
+    if download.name.is_empty() {
+        download.name = options
+            .out
+            .clone()
+            .or(target.file_name)
+            .or_else(|| download.url.clone())
+            .or_else(|| download.magnet_uri.clone())
+            .unwrap_or_default();
+    }
+
+    let result = DownloadsDb::save(db, &download)
+        .and_then(|_| DownloadsDb::save_options(db, &download.gid, &options))
+        .and_then(|_| match &source {
+            Source::Torrent(data) => DownloadsDb::save_torrent(db, &download.gid, data),
+            _ => Ok(()),
+        });
+
+    match result {
+        Ok(()) => {
+            log::info!("Scheduled {} for {}", download.name, start);
+            let _ = ui_sender.send(UiMessage::DownloadAdded(download)).await;
+        }
+        Err(e) => {
+            let _ = DownloadsDb::delete(db, &download.gid);
+            let message = format!("Failed to schedule {}: {}", download.name, e);
+            let _ = ui_sender.send(UiMessage::Error(message)).await;
+        }
+    }
+}
+
+/// Start every scheduled download whose time has come
+pub(crate) async fn start_due(
+    adapter: &EngineAdapter,
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+) {
+    for download in due(scheduled(db), now()) {
+        start(adapter, db, ui_sender, download).await;
+    }
+}
+
+/// The downloads whose start time is `now` or earlier, including ones that
+/// came due while the service was not running
+fn due(downloads: Vec<Download>, now: i64) -> Vec<Download> {
+    downloads
+        .into_iter()
+        .filter(|d| d.scheduled_start.is_some_and(|start| start <= now))
+        .collect()
+}
+
+async fn start(
+    adapter: &EngineAdapter,
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    download: Download,
+) {
+    let gid = download.gid.clone();
+    let mut options = stored_options(db, &gid).unwrap_or_default();
+    options.scheduled_start = None;
+
+    let mut torrent = None;
+    let result = match download.download_type {
+        DownloadType::Http | DownloadType::Metalink => match &download.url {
+            Some(url) => adapter
+                .add_download(url.clone(), Some(options.clone()))
+                .await
+                .map_err(|e| e.to_string()),
+            None => Err("no URL recorded".to_string()),
+        },
+        DownloadType::Magnet => match &download.magnet_uri {
+            Some(uri) => adapter
+                .add_magnet(uri, Some(options.clone()))
+                .await
+                .map_err(|e| e.to_string()),
+            None => Err("no magnet link recorded".to_string()),
+        },
+        DownloadType::Torrent => match DownloadsDb::get_torrent(db, &gid) {
+            Ok(Some(data)) => {
+                let result = adapter
+                    .add_torrent(&data, Some(options.clone()))
+                    .await
+                    .map_err(|e| e.to_string());
+                torrent = Some(data);
+                result
+            }
+            Ok(None) => Err("no .torrent file stored".to_string()),
+            Err(e) => Err(e.to_string()),
+        },
+        DownloadType::Ftp => Err("FTP is not supported".to_string()),
+    };
+
+    match result {
+        Ok(new_gid) => {
+            log::info!("Starting scheduled download {}", download.name);
+            if let Some(data) = &torrent {
+                if let Err(e) = DownloadsDb::save_torrent(db, &new_gid, data) {
+                    log::error!("Failed to save torrent file: {}", e);
+                }
+            }
+            // Drop the placeholder now so the next tick cannot start it again
+            if let Err(e) = DownloadsDb::delete(db, &gid) {
+                log::error!("Failed to remove scheduled download {}: {}", gid, e);
+            }
+            replace_download(adapter, db, ui_sender, gid, &new_gid, Some(&options)).await;
+        }
+        Err(e) => {
+            let message = format!("Failed to start {}: {}", download.name, e);
+            log::error!("{}", message);
+            let failed = record_failure(db, download, &message);
+            let _ = ui_sender
+                .send(UiMessage::DownloadUpdated(gid.clone(), failed))
+                .await;
+            let _ = ui_sender
+                .send(UiMessage::DownloadFailed(gid, message))
+                .await;
+        }
+    }
+}
+
+/// Mark a scheduled download that could not be started as failed, with
+/// the reason as its error message
+fn record_failure(db: &Database, download: Download, message: &str) -> Download {
+    let failed = Download {
+        status: DownloadState::Error,
+        error_message: Some(message.to_string()),
+        ..download
+    };
+    if let Err(e) = DownloadsDb::save(db, &failed) {
+        log::error!("Failed to record scheduled download failure: {}", e);
+    }
+    failed
+}
+
+/// Move a scheduled download to a new start time
+///
+/// A time that has already passed starts the download right away.
+pub(crate) async fn reschedule(
+    adapter: &EngineAdapter,
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    gid: String,
+    start: i64,
+) {
+    let download = match set_start(db, &gid, start) {
+        Ok(download) => download,
+        Err(e) => {
+            let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+            return;
+        }
+    };
+
+    if start <= now() {
+        start_due(adapter, db, ui_sender).await;
+    } else {
+        let _ = ui_sender
+            .send(UiMessage::DownloadUpdated(gid, download))
+            .await;
+    }
+}
+
+/// Store a new start time for a scheduled download
+fn set_start(db: &Database, gid: &str, start: i64) -> Result<Download> {
+    let download = match DownloadsDb::get_by_gid(db, gid)? {
+        Some(download) if download.status == DownloadState::Scheduled => download,
+        _ => return Err(not_scheduled(gid)),
+    };
+    DownloadsDb::set_scheduled_start(db, gid, start)?;
+    Ok(Download {
+        scheduled_start: Some(start),
+        ..download
+    })
+}
+
+/// Drop a scheduled download before it starts
+pub(crate) async fn cancel(
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    gid: String,
+) {
+    match drop_scheduled(db, &gid) {
+        Ok(()) => {
+            let _ = ui_sender.send(UiMessage::DownloadRemoved(gid)).await;
+        }
+        Err(e) => {
+            let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+        }
+    }
+}
+
+/// Delete a scheduled download's row, without announcing it
+pub(crate) fn drop_scheduled(db: &Database, gid: &str) -> Result<()> {
+    if !is_scheduled(db, gid) {
+        return Err(not_scheduled(gid));
+    }
+    DownloadsDb::delete(db, gid)
+}
+
+fn not_scheduled(gid: &str) -> Error {
+    Error::InvalidInput(format!("Download {} is not scheduled", gid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduled_at(gid: &str, start: i64) -> Download {
+        Download {
+            gid: gid.to_string(),
+            name: format!("{}.iso", gid),
+            url: Some(format!("https://example.com/{}.iso", gid)),
+            status: DownloadState::Scheduled,
+            scheduled_start: Some(start),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_due_downloads_include_missed_starts() {
+        let now = 1_700_000_000;
+        let downloads = vec![
+            // Came due while the machine was off
+            scheduled_at("missed", now - 3 * 3600),
+            scheduled_at("now", now),
+            scheduled_at("later", now + 60),
+            Download {
+                scheduled_start: None,
+                ..scheduled_at("unset", now)
+            },
+        ];
+        let gids: Vec<_> = due(downloads, now).into_iter().map(|d| d.gid).collect();
+        assert_eq!(gids, ["missed", "now"]);
+    }
+
+    #[tokio::test]
+    async fn test_reschedule_and_cancel() {
+        let db = crate::db::in_memory();
+        let (sender, receiver) = async_channel::unbounded();
+        DownloadsDb::save(&db, &scheduled_at("abc", now() + 60)).unwrap();
+
+        let later = now() + 3600;
+        let moved = set_start(&db, "abc", later).unwrap();
+        assert_eq!(moved.scheduled_start, Some(later));
+        let stored = DownloadsDb::get_by_gid(&db, "abc").unwrap().unwrap();
+        assert_eq!(stored.scheduled_start, Some(later));
+        assert!(matches!(
+            set_start(&db, "missing", later),
+            Err(Error::InvalidInput(_))
+        ));
+
+        cancel(&db, &sender, "abc".to_string()).await;
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(UiMessage::DownloadRemoved(gid)) if gid == "abc"
+        ));
+        assert!(DownloadsDb::get_by_gid(&db, "abc").unwrap().is_none());
+
+        // It is gone, so neither can happen again
+        cancel(&db, &sender, "abc".to_string()).await;
+        assert!(matches!(receiver.try_recv(), Ok(UiMessage::Error(_))));
+        assert!(set_start(&db, "abc", later).is_err());
+    }
+
+    #[test]
+    fn test_failed_start_keeps_the_reason() {
+        let db = crate::db::in_memory();
+        let download = scheduled_at("abc", now() - 60);
+        DownloadsDb::save(&db, &download).unwrap();
+
+        record_failure(&db, download, "Failed to start abc.iso: no URL recorded");
+        let stored = DownloadsDb::get_by_gid(&db, "abc").unwrap().unwrap();
+        assert_eq!(stored.status, DownloadState::Error);
+        assert_eq!(
+            stored.error_message.as_deref(),
+            Some("Failed to start abc.iso: no URL recorded")
+        );
+        // ...and it is not started again on the next tick
+        assert!(scheduled(&db).is_empty());
+    }
+}
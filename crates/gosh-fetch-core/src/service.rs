@@ -1,17 +1,41 @@
 //! Download service - bridges tokio async runtime with UI main loop
 
+use crate::bandwidth::{self, Bandwidth};
+use crate::checksum;
+use crate::completion;
+use crate::db::{CategoriesDb, Database, DownloadsDb};
+use crate::duplicate::{self, Candidate, Verdict};
 use crate::engine_adapter::EngineAdapter;
-use crate::types::{Download, DownloadOptions, DownloadType, GlobalStats, Settings};
+use crate::metalink;
+use crate::scheduler::{self, Source};
+use crate::torrent;
+use crate::types::{
+    ChecksumResult, CompletionAction, Download, DownloadOptions, DownloadState,
+    DownloadType, DuplicateAction, ExistingDownload, GlobalStats, ProbeResult, Settings,
+};
 use gosh_dl::{DownloadEngine, DownloadEvent, EngineConfig};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// Commands sent from UI to the engine (via async channel)
-#[derive(Debug, Clone)]
+///
+/// Commands are serializable so they can be sent to `gosh-fetchd` over its
+/// control socket. `UpdateConfig` carries a gosh-dl type and is local-only;
+/// remote clients send `UpdateSettings` instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EngineCommand {
-    /// Add an HTTP/HTTPS download
+    /// Add an HTTP/HTTPS download; a `.metalink` or `.meta4` URL is
+    /// fetched and its files are added instead
     AddDownload {
         url: String,
         options: Option<DownloadOptions>,
     },
+    /// Add several HTTP downloads with the same options; their URLs are
+    /// resolved in parallel and each one that fails is reported on its own
+    AddDownloads {
+        urls: Vec<String>,
+        options: Option<DownloadOptions>,
+    },
     /// Add a magnet link
     AddMagnet {
         uri: String,
@@ -22,6 +46,11 @@ pub enum EngineCommand {
         data: Vec<u8>,
         options: Option<DownloadOptions>,
     },
+    /// Add one download per file of a metalink document
+    AddMetalink {
+        data: Vec<u8>,
+        options: Option<DownloadOptions>,
+    },
     /// Pause a download
     Pause(String),
     /// Resume a download
@@ -31,22 +60,88 @@ pub enum EngineCommand {
         gid: String,
         delete_files: bool,
     },
+    /// Re-add a download recorded in the database with its stored options
+    RestoreDownload(String),
+    /// Move a scheduled download to a new start time (Unix timestamp);
+    /// a time in the past starts it now
+    Reschedule { gid: String, start: i64 },
+    /// Drop a scheduled download before it starts
+    CancelScheduled(String),
+    /// Hash a completed download again and record the result in history
+    VerifyChecksum(String),
+    /// Find out what an HTTP URL serves without adding it; answered with
+    /// `UrlProbed` or `ProbeFailed`
+    ProbeUrl {
+        url: String,
+        options: Option<DownloadOptions>,
+    },
     /// Pause all downloads
     PauseAll,
     /// Resume all downloads
     ResumeAll,
     /// Update engine configuration
+    #[serde(skip)]
     UpdateConfig(EngineConfig),
+    /// Rebuild the engine configuration from application settings
+    UpdateSettings(Settings),
+    /// Re-read categories from the database after they were edited
+    ReloadCategories,
+    /// Use a bandwidth profile until the schedule next changes profile;
+    /// `None` goes back to the schedule
+    SetBandwidthProfile(Option<String>),
     /// Request current downloads list
     RefreshDownloads,
     /// Request global stats
     RefreshStats,
+    /// Run an add command and also answer with what became of it, for
+    /// callers that return the GID
+    #[serde(skip)]
+    AddAndReply(Box<EngineCommand>, async_channel::Sender<AddOutcome>),
     /// Shutdown the service
     Shutdown,
 }
 
-/// Messages sent from engine to UI (via channel)
+/// What became of an `EngineCommand::AddAndReply` command
 #[derive(Debug, Clone)]
+pub enum AddOutcome {
+    /// Added, or scheduled, under this GID
+    Added(String),
+    /// Held back as a duplicate of this download
+    Duplicate(ExistingDownload),
+    /// Not added, for this reason
+    Failed(String),
+}
+
+impl EngineCommand {
+    /// Options of an add command
+    pub fn options(&self) -> Option<&DownloadOptions> {
+        match self {
+            EngineCommand::AddDownload { options, .. }
+            | EngineCommand::AddDownloads { options, .. }
+            | EngineCommand::AddMagnet { options, .. }
+            | EngineCommand::AddTorrent { options, .. }
+            | EngineCommand::AddMetalink { options, .. } => options.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The same add command, handling a duplicate with `action`; other
+    /// commands are returned unchanged
+    pub fn with_duplicate_action(mut self, action: DuplicateAction) -> Self {
+        if let EngineCommand::AddDownload { options, .. }
+        | EngineCommand::AddDownloads { options, .. }
+        | EngineCommand::AddMagnet { options, .. }
+        | EngineCommand::AddTorrent { options, .. }
+        | EngineCommand::AddMetalink { options, .. } = &mut self
+        {
+            options.get_or_insert_with(Default::default).on_duplicate = Some(action);
+        }
+        self
+    }
+}
+
+/// Messages sent from engine to UI (via channel)
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UiMessage {
     /// A download was added
     DownloadAdded(Download),
@@ -58,6 +153,22 @@ pub enum UiMessage {
     DownloadCompleted(Download),
     /// A download failed
     DownloadFailed(String, String),
+    /// A completed download was hashed on request
+    ChecksumVerified(String, ChecksumResult),
+    /// A completed download's archive is being unpacked: bytes of the
+    /// archive read so far and its size
+    ExtractionProgress(String, u64, u64),
+    /// A completed download's archive was unpacked, with where it went
+    ExtractionFinished(String, String),
+    /// Unpacking a completed download's archive failed
+    ExtractionFailed(String, String),
+    /// An add command was not run because the download already exists; send
+    /// the command again with another `DuplicateAction` to go ahead
+    DuplicateDownload(ExistingDownload, Box<EngineCommand>),
+    /// What a URL serves, for `ProbeUrl`
+    UrlProbed(ProbeResult),
+    /// A `ProbeUrl` failed: the URL and the error
+    ProbeFailed(String, String),
     /// Global stats updated
     StatsUpdated(GlobalStats),
     /// Full downloads list
@@ -71,16 +182,56 @@ pub enum UiMessage {
 /// Download service that runs in a separate thread with tokio
 pub struct DownloadService {
     adapter: EngineAdapter,
+    db: Database,
+    bandwidth: Bandwidth,
 }
 
 impl DownloadService {
     /// Create a new download service with the given settings
-    pub async fn new_async(settings: &Settings) -> Result<Self, gosh_dl::EngineError> {
+    ///
+    /// The database is used to remember the options each download was added
+    /// with, so restores and resume fallbacks can replay them, and supplies
+    /// the categories new downloads are filed under.
+    pub async fn new_async(
+        settings: &Settings,
+        db: Database,
+    ) -> Result<Self, gosh_dl::EngineError> {
         let config = settings_to_engine_config(settings);
         let engine = DownloadEngine::new(config).await?;
         let adapter = EngineAdapter::new(engine);
 
-        Ok(Self { adapter })
+        load_categories(&adapter, &db);
+        adapter.set_conflict_policy(settings.conflict_policy);
+        match DownloadsDb::get_conflict_outcomes(&db) {
+            Ok(outcomes) => {
+                for (gid, outcome) in outcomes {
+                    adapter.record_conflict(&gid, Some(outcome));
+                }
+            }
+            Err(e) => log::error!("Failed to load conflict outcomes: {}", e),
+        }
+        match DownloadsDb::get_category_assignments(&db) {
+            Ok(assignments) => {
+                for (gid, category) in assignments {
+                    adapter.assign_category(&gid, category);
+                }
+            }
+            Err(e) => log::error!("Failed to load download categories: {}", e),
+        }
+        match DownloadsDb::get_gids_of_type(&db, DownloadType::Metalink) {
+            Ok(gids) => {
+                for gid in gids {
+                    adapter.mark_metalink(&gid);
+                }
+            }
+            Err(e) => log::error!("Failed to load metalink downloads: {}", e),
+        }
+
+        Ok(Self {
+            adapter,
+            db,
+            bandwidth: Bandwidth::new(settings),
+        })
     }
 
     /// Get a clone of the engine adapter
@@ -96,6 +247,8 @@ impl DownloadService {
         cmd_receiver: async_channel::Receiver<EngineCommand>,
     ) {
         let adapter = self.adapter;
+        let db = self.db;
+        let mut bandwidth = self.bandwidth;
 
         std::thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
@@ -107,6 +260,13 @@ impl DownloadService {
                 // Notify UI that engine is ready
                 let _ = ui_sender.send(UiMessage::EngineReady).await;
 
+                // Check for due scheduled downloads against the wall clock,
+                // so ones missed during downtime or suspend start right away
+                let mut schedule_tick = tokio::time::interval(scheduler::TICK_INTERVAL);
+                schedule_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+                let mut bandwidth_tick = tokio::time::interval(bandwidth::TICK_INTERVAL);
+                bandwidth_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
                 loop {
                     tokio::select! {
                         // Handle commands from UI
@@ -114,10 +274,28 @@ impl DownloadService {
                             match cmd_result {
                                 Ok(EngineCommand::Shutdown) => {
                                     log::info!("Download service shutting down");
+                                    if let Err(e) = adapter.shutdown().await {
+                                        log::error!("Failed to shut down the engine: {}", e);
+                                    }
                                     break;
                                 }
+                                Ok(EngineCommand::AddAndReply(cmd, reply)) => {
+                                    let outcome = add_and_reply(
+                                        &adapter,
+                                        &db,
+                                        &mut bandwidth,
+                                        &ui_sender,
+                                        *cmd,
+                                    )
+                                    .await;
+                                    let _ = reply.send(outcome).await;
+                                    bandwidth.hold(&adapter).await;
+                                }
                                 Ok(cmd) => {
-                                    handle_command(&adapter, &ui_sender, cmd).await;
+                                    handle_command(&adapter, &db, &mut bandwidth, &ui_sender, cmd)
+                                        .await;
+                                    // Downloads it added or resumed wait out a pausing profile
+                                    bandwidth.hold(&adapter).await;
                                 }
                                 Err(_) => {
                                     log::warn!("Command channel closed");
@@ -129,9 +307,20 @@ impl DownloadService {
                         // Handle events from engine
                         event_result = event_rx.recv() => {
                             if let Ok(event) = event_result {
-                                handle_engine_event(&adapter, &ui_sender, event).await;
+                                handle_engine_event(&adapter, &db, &ui_sender, event).await;
                             }
                         }
+
+                        // Start scheduled downloads that are due
+                        _ = schedule_tick.tick() => {
+                            scheduler::start_due(&adapter, &db, &ui_sender).await;
+                            bandwidth.hold(&adapter).await;
+                        }
+
+                        // Follow the bandwidth schedule
+                        _ = bandwidth_tick.tick() => {
+                            bandwidth.apply(&adapter).await;
+                        }
                     }
                 }
             });
@@ -142,6 +331,7 @@ impl DownloadService {
 /// Handle an event from the engine
 async fn handle_engine_event(
     adapter: &EngineAdapter,
+    db: &Database,
     ui_sender: &async_channel::Sender<UiMessage>,
     event: DownloadEvent,
 ) {
@@ -150,7 +340,9 @@ async fn handle_engine_event(
             let gid = id.as_uuid().to_string();
             if let Some(download) = adapter.get_status(&gid) {
                 log::info!("Download completed: {}", download.name);
-                let _ = ui_sender.send(UiMessage::DownloadCompleted(download)).await;
+                // Hashing a large file or running a command takes a while;
+                // keep handling events
+                tokio::spawn(finish_download(db.clone(), ui_sender.clone(), download));
             }
         }
         DownloadEvent::Failed { id, error, .. } => {
@@ -172,29 +364,138 @@ async fn handle_engine_event(
     }
 }
 
+/// Check a download the engine could not verify, announce it as completed
+/// with the result, then run its completion actions
+///
+/// Failed actions are reported as errors; the download stays complete. A
+/// file that failed its checksum is left alone. Returns the download as it
+/// ended up, with its checksum result and any new save path. The service
+/// runs this for every download the engine completes; a client polling the
+/// engine itself, like the CLI, calls it and records the messages it sends.
+pub async fn finish_download(
+    db: Database,
+    ui_sender: async_channel::Sender<UiMessage>,
+    mut download: Download,
+) -> Download {
+    let options = stored_options(&db, &download.gid);
+    if let Some(options) = options.as_ref().filter(|o| checksum::needs_check(o)) {
+        match checksum::verify(&download, Some(options)).await {
+            Ok(result) => {
+                if result.matched == Some(false) {
+                    let message = format!("Checksum mismatch for {}", download.name);
+                    let _ = ui_sender.send(UiMessage::Error(message)).await;
+                }
+                download.checksum = Some(result);
+            }
+            Err(e) => log::warn!("Failed to verify {}: {}", download.name, e),
+        }
+    }
+    let _ = ui_sender.send(UiMessage::DownloadCompleted(download.clone())).await;
+
+    let actions = completion::actions_for(&db, &download, options.as_ref());
+    let mismatched = download
+        .checksum
+        .as_ref()
+        .is_some_and(|c| c.matched == Some(false));
+    if actions.is_empty() || mismatched {
+        return download;
+    }
+
+    let gid = download.gid.clone();
+    let progress_sender = ui_sender.clone();
+    let progress: completion::ExtractProgress = Arc::new(move |read, size| {
+        let msg = UiMessage::ExtractionProgress(gid.clone(), read, size);
+        let _ = progress_sender.send_blocking(msg);
+    });
+
+    let save_path = download.save_path.clone();
+    for (action, entry) in completion::run(&db, &mut download, &actions, progress).await {
+        let gid = download.gid.clone();
+        let msg = match action {
+            CompletionAction::Extract { .. } if entry.success => {
+                UiMessage::ExtractionFinished(gid, entry.message)
+            }
+            CompletionAction::Extract { .. } => UiMessage::ExtractionFailed(gid, entry.message),
+            _ if entry.success => continue,
+            _ => UiMessage::Error(format!(
+                "Could not {} for {}: {}",
+                entry.action, download.name, entry.message
+            )),
+        };
+        let _ = ui_sender.send(msg).await;
+    }
+    if download.save_path != save_path {
+        let gid = download.gid.clone();
+        let msg = UiMessage::DownloadUpdated(gid, download.clone());
+        let _ = ui_sender.send(msg).await;
+    }
+    download
+}
+
 /// Handle a command from the UI
 async fn handle_command(
     adapter: &EngineAdapter,
+    db: &Database,
+    bandwidth: &mut Bandwidth,
     ui_sender: &async_channel::Sender<UiMessage>,
     cmd: EngineCommand,
 ) {
+    if !may_add(adapter, db, ui_sender, &cmd).await {
+        return;
+    }
+
     match cmd {
         EngineCommand::AddDownload { url, options } => {
-            match adapter.add_download(url, options).await {
-                Ok(gid) => {
-                    if let Some(download) = adapter.get_status(&gid) {
-                        let _ = ui_sender.send(UiMessage::DownloadAdded(download)).await;
-                    }
+            add_download(adapter, db, ui_sender, url, options).await;
+        }
+
+        EngineCommand::AddDownloads { urls, options } => {
+            // Metalinks and scheduled downloads are not resolved up front
+            let scheduled = scheduler::future_start(options.as_ref()).is_some();
+            let mut batch = Vec::new();
+            for url in urls {
+                // Each URL is checked for duplicates and reported on its own
+                let cmd = EngineCommand::AddDownload {
+                    url: url.clone(),
+                    options: options.clone(),
+                };
+                if !may_add(adapter, db, ui_sender, &cmd).await {
+                    continue;
                 }
-                Err(e) => {
-                    let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+                if scheduled || metalink::is_metalink(&url) {
+                    add_download(adapter, db, ui_sender, url, options.clone()).await;
+                } else {
+                    batch.push(url);
+                }
+            }
+
+            let results = adapter.add_urls(batch.clone(), options.clone()).await;
+            for (url, result) in batch.into_iter().zip(results) {
+                match result {
+                    Ok(gid) => {
+                        remember_options(db, &gid, options.as_ref());
+                        if let Some(download) = adapter.get_status(&gid) {
+                            let _ = ui_sender.send(UiMessage::DownloadAdded(download)).await;
+                        }
+                    }
+                    Err(e) => {
+                        let message = format!("Failed to add {}: {}", url, e);
+                        let _ = ui_sender.send(UiMessage::Error(message)).await;
+                    }
                 }
             }
         }
 
         EngineCommand::AddMagnet { uri, options } => {
-            match adapter.add_magnet(&uri, options).await {
+            if let Some(start) = scheduler::future_start(options.as_ref()) {
+                let options = options.unwrap_or_default();
+                let source = Source::Magnet(uri);
+                scheduler::schedule(adapter, db, ui_sender, source, options, start).await;
+                return;
+            }
+            match adapter.add_magnet(&uri, options.clone()).await {
                 Ok(gid) => {
+                    remember_options(db, &gid, options.as_ref());
                     if let Some(download) = adapter.get_status(&gid) {
                         let _ = ui_sender.send(UiMessage::DownloadAdded(download)).await;
                     }
@@ -206,8 +507,18 @@ async fn handle_command(
         }
 
         EngineCommand::AddTorrent { data, options } => {
-            match adapter.add_torrent(&data, options).await {
+            if let Some(start) = scheduler::future_start(options.as_ref()) {
+                let options = options.unwrap_or_default();
+                let source = Source::Torrent(data);
+                scheduler::schedule(adapter, db, ui_sender, source, options, start).await;
+                return;
+            }
+            match adapter.add_torrent(&data, options.clone()).await {
                 Ok(gid) => {
+                    remember_options(db, &gid, options.as_ref());
+                    if let Err(e) = DownloadsDb::save_torrent(db, &gid, &data) {
+                        log::error!("Failed to save torrent file: {}", e);
+                    }
                     if let Some(download) = adapter.get_status(&gid) {
                         let _ = ui_sender.send(UiMessage::DownloadAdded(download)).await;
                     }
@@ -218,6 +529,10 @@ async fn handle_command(
             }
         }
 
+        EngineCommand::AddMetalink { data, options } => {
+            add_metalink(adapter, db, ui_sender, &data, options).await;
+        }
+
         EngineCommand::Pause(gid) => {
             if let Err(e) = adapter.pause(&gid).await {
                 let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
@@ -225,17 +540,32 @@ async fn handle_command(
         }
 
         EngineCommand::Resume(gid) => {
-            if let Err(e) = adapter.resume(&gid).await {
+            // Resuming a scheduled download starts it now
+            if scheduler::is_scheduled(db, &gid) {
+                let now = chrono::Utc::now().timestamp();
+                scheduler::reschedule(adapter, db, ui_sender, gid, now).await;
+            } else if let Err(e) = adapter.resume(&gid).await {
                 let mut retried = false;
                 if let Some(download) = adapter.get_status(&gid) {
-                    if download.download_type == DownloadType::Http {
+                    if matches!(
+                        download.download_type,
+                        DownloadType::Http | DownloadType::Metalink
+                    ) {
                         if let Some(url) = download.url.clone() {
+                            let options = stored_options(db, &gid);
+                            let options = Some(continue_file(options, &download));
                             let _ = adapter.remove(&gid, false).await;
-                            if let Ok(new_gid) = adapter.add_download(url, None).await {
-                                let _ = ui_sender.send(UiMessage::DownloadRemoved(gid)).await;
-                                if let Some(new_download) = adapter.get_status(&new_gid) {
-                                    let _ = ui_sender.send(UiMessage::DownloadAdded(new_download)).await;
-                                }
+                            let added = adapter.readd_download(url, options.clone()).await;
+                            if let Ok(new_gid) = added {
+                                replace_download(
+                                    adapter,
+                                    db,
+                                    ui_sender,
+                                    gid,
+                                    &new_gid,
+                                    options.as_ref(),
+                                )
+                                .await;
                                 retried = true;
                             }
                         }
@@ -249,13 +579,64 @@ async fn handle_command(
         }
 
         EngineCommand::Remove { gid, delete_files } => {
-            if let Err(e) = adapter.remove(&gid, delete_files).await {
-                let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
-            } else {
-                let _ = ui_sender.send(UiMessage::DownloadRemoved(gid)).await;
+            match remove_download(adapter, db, &gid, delete_files).await {
+                Ok(()) => {
+                    let _ = ui_sender.send(UiMessage::DownloadRemoved(gid)).await;
+                }
+                Err(e) => {
+                    let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+                }
+            }
+        }
+
+        EngineCommand::RestoreDownload(gid) => {
+            restore_download(adapter, db, ui_sender, gid).await;
+        }
+
+        EngineCommand::Reschedule { gid, start } => {
+            scheduler::reschedule(adapter, db, ui_sender, gid, start).await;
+        }
+
+        EngineCommand::CancelScheduled(gid) => {
+            scheduler::cancel(db, ui_sender, gid).await;
+        }
+
+        EngineCommand::VerifyChecksum(gid) => {
+            let download = match DownloadsDb::get_by_gid(db, &gid) {
+                Ok(download) => download.or_else(|| adapter.get_status(&gid)),
+                Err(e) => {
+                    log::error!("Failed to load download {}: {}", gid, e);
+                    adapter.get_status(&gid)
+                }
+            };
+            match download {
+                Some(download) if download.status == DownloadState::Complete => {
+                    let options = stored_options(db, &gid);
+                    tokio::spawn(verify_checksum(ui_sender.clone(), download, options));
+                }
+                Some(download) => {
+                    let message = format!("{} has not finished downloading", download.name);
+                    let _ = ui_sender.send(UiMessage::Error(message)).await;
+                }
+                None => {
+                    let message = format!("Download not found: {}", gid);
+                    let _ = ui_sender.send(UiMessage::Error(message)).await;
+                }
             }
         }
 
+        EngineCommand::ProbeUrl { url, options } => {
+            let adapter = adapter.clone();
+            let ui_sender = ui_sender.clone();
+            tokio::spawn(async move {
+                let message = match adapter.probe(&url, options).await {
+                    Ok(result) => UiMessage::UrlProbed(result),
+                    Err(e) => UiMessage::ProbeFailed(url, e.to_string()),
+                };
+                let _ = ui_sender.send(message).await;
+            });
+        }
+
         EngineCommand::PauseAll => {
             if let Err(e) = adapter.pause_all().await {
                 let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
@@ -272,24 +653,478 @@ async fn handle_command(
             if let Err(e) = adapter.update_config(config) {
                 let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
             }
+            // The new config carries the global limits; put the profile back
+            bandwidth.invalidate();
+            bandwidth.apply(adapter).await;
+        }
+
+        EngineCommand::UpdateSettings(settings) => {
+            let config = settings_to_engine_config(&settings);
+            if let Err(e) = adapter.update_config(config) {
+                let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+            }
+            adapter.set_conflict_policy(settings.conflict_policy);
+            bandwidth.update_settings(&settings);
+            bandwidth.apply(adapter).await;
+        }
+
+        EngineCommand::ReloadCategories => {
+            load_categories(adapter, db);
+        }
+
+        EngineCommand::SetBandwidthProfile(name) => {
+            match bandwidth.set_manual(name.as_deref()) {
+                Ok(()) => bandwidth.apply(adapter).await,
+                Err(e) => {
+                    let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+                }
+            }
         }
 
         EngineCommand::RefreshDownloads => {
-            let downloads = adapter.get_all();
+            let mut downloads = adapter.get_all();
+            downloads.extend(scheduler::scheduled(db));
             let _ = ui_sender.send(UiMessage::DownloadsList(downloads)).await;
         }
 
         EngineCommand::RefreshStats => {
-            let stats = adapter.get_global_stats();
+            let mut stats = adapter.get_global_stats();
+            stats.bandwidth_profile = bandwidth.active_profile();
             let _ = ui_sender.send(UiMessage::StatsUpdated(stats)).await;
         }
 
-        EngineCommand::Shutdown => {
+        EngineCommand::AddAndReply(..) | EngineCommand::Shutdown => {
             // Handled in the main loop
         }
     }
 }
 
+/// Handle an add command, passing its messages on and answering with the
+/// first that tells what became of it
+async fn add_and_reply(
+    adapter: &EngineAdapter,
+    db: &Database,
+    bandwidth: &mut Bandwidth,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    cmd: EngineCommand,
+) -> AddOutcome {
+    let (sender, receiver) = async_channel::unbounded();
+    handle_command(adapter, db, bandwidth, &sender, cmd).await;
+
+    let mut outcome = None;
+    while let Ok(msg) = receiver.try_recv() {
+        if outcome.is_none() {
+            outcome = add_outcome(&msg);
+        }
+        let _ = ui_sender.send(msg).await;
+    }
+    outcome.unwrap_or_else(|| AddOutcome::Failed("Nothing was added".to_string()))
+}
+
+fn add_outcome(msg: &UiMessage) -> Option<AddOutcome> {
+    match msg {
+        UiMessage::DownloadAdded(download) => Some(AddOutcome::Added(download.gid.clone())),
+        UiMessage::DuplicateDownload(existing, _) => Some(AddOutcome::Duplicate(existing.clone())),
+        UiMessage::Error(message) => Some(AddOutcome::Failed(message.clone())),
+        _ => None,
+    }
+}
+
+/// Remove a download from the engine, or drop it if it is still waiting
+/// for its scheduled start
+///
+/// This is what `EngineCommand::Remove` does; the caller announces the
+/// removal with `UiMessage::DownloadRemoved`.
+pub async fn remove_download(
+    adapter: &EngineAdapter,
+    db: &Database,
+    gid: &str,
+    delete_files: bool,
+) -> crate::Result<()> {
+    if scheduler::is_scheduled(db, gid) {
+        return scheduler::drop_scheduled(db, gid);
+    }
+    adapter.remove(gid, delete_files).await?;
+    Ok(())
+}
+
+fn load_categories(adapter: &EngineAdapter, db: &Database) {
+    match CategoriesDb::list(db) {
+        Ok(categories) => adapter.set_categories(categories),
+        Err(e) => log::error!("Failed to load categories: {}", e),
+    }
+}
+
+/// Whether an add command should go ahead, applying its `on_duplicate`
+/// action when the download already exists
+///
+/// Commands that add nothing always go ahead.
+async fn may_add(
+    adapter: &EngineAdapter,
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    cmd: &EngineCommand,
+) -> bool {
+    match duplicate::check(adapter, db, cmd).await {
+        Ok(Verdict::Add) => true,
+        Ok(Verdict::Report(existing)) => {
+            let msg = UiMessage::DuplicateDownload(existing, Box::new(cmd.clone()));
+            let _ = ui_sender.send(msg).await;
+            false
+        }
+        Ok(Verdict::Restart(existing)) => {
+            let _ = ui_sender
+                .send(UiMessage::DownloadRemoved(existing.gid))
+                .await;
+            true
+        }
+        Err(e) => {
+            let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+            false
+        }
+    }
+}
+
+/// Add an HTTP download, the files of a metalink URL, or schedule it when
+/// asked to start later
+async fn add_download(
+    adapter: &EngineAdapter,
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    url: String,
+    options: Option<DownloadOptions>,
+) {
+    if metalink::is_metalink(&url) {
+        match metalink::fetch(&url).await {
+            Ok(data) => add_metalink(adapter, db, ui_sender, &data, options).await,
+            Err(e) => {
+                let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+            }
+        }
+        return;
+    }
+    if let Some(start) = scheduler::future_start(options.as_ref()) {
+        let options = options.unwrap_or_default();
+        let source = Source::Url(url);
+        scheduler::schedule(adapter, db, ui_sender, source, options, start).await;
+        return;
+    }
+    match adapter.add_download(url, options.clone()).await {
+        Ok(gid) => {
+            remember_options(db, &gid, options.as_ref());
+            if let Some(download) = adapter.get_status(&gid) {
+                let _ = ui_sender.send(UiMessage::DownloadAdded(download)).await;
+            }
+        }
+        Err(e) => {
+            let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+        }
+    }
+}
+
+/// Add the files of a metalink, or schedule them when asked to start later
+async fn add_metalink(
+    adapter: &EngineAdapter,
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    data: &[u8],
+    options: Option<DownloadOptions>,
+) {
+    if let Some(start) = scheduler::future_start(options.as_ref()) {
+        let files = match metalink::parse_metalink(data) {
+            Ok(files) => files,
+            Err(e) => {
+                let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+                return;
+            }
+        };
+        let default_dir = adapter.get_config().download_dir;
+        let options = options.unwrap_or_default();
+        for (url, options) in metalink::downloads(&files, &options, &default_dir) {
+            scheduler::schedule(adapter, db, ui_sender, Source::Url(url), options, start).await;
+        }
+        return;
+    }
+
+    match adapter.add_metalink(data, options).await {
+        Ok(added) => {
+            for (gid, options) in added {
+                remember_options(db, &gid, Some(&options));
+                if let Some(download) = adapter.get_status(&gid) {
+                    let _ = ui_sender.send(UiMessage::DownloadAdded(download)).await;
+                }
+            }
+        }
+        Err(e) => {
+            let _ = ui_sender.send(UiMessage::Error(e.to_string())).await;
+        }
+    }
+}
+
+async fn verify_checksum(
+    ui_sender: async_channel::Sender<UiMessage>,
+    download: Download,
+    options: Option<DownloadOptions>,
+) {
+    match checksum::verify(&download, options.as_ref()).await {
+        Ok(result) => {
+            let _ = ui_sender
+                .send(UiMessage::ChecksumVerified(download.gid, result))
+                .await;
+        }
+        Err(e) => {
+            let message = format!("Failed to verify {}: {}", download.name, e);
+            let _ = ui_sender.send(UiMessage::Error(message)).await;
+        }
+    }
+}
+
+/// Remember the options a download was added with
+fn remember_options(db: &Database, gid: &str, options: Option<&DownloadOptions>) {
+    if let Some(options) = options {
+        if let Err(e) = DownloadsDb::save_options(db, gid, options) {
+            log::error!("Failed to save download options: {}", e);
+        }
+    }
+}
+
+pub(crate) fn stored_options(db: &Database, gid: &str) -> Option<DownloadOptions> {
+    DownloadsDb::get_options(db, gid).unwrap_or_else(|e| {
+        log::error!("Failed to load download options for {}: {}", gid, e);
+        None
+    })
+}
+
+/// Options to add a download again over the data it already has
+///
+/// Downloads added to the default folder store no `dir`, so the recorded
+/// folder is pinned; otherwise a since-changed download folder or category
+/// would start it over somewhere else. It also stays in the category it
+/// was filed under, even if the rules changed.
+fn restore_options(options: Option<DownloadOptions>, download: &Download) -> DownloadOptions {
+    let mut options = options.unwrap_or_default();
+    if options.dir.is_none() && !download.save_path.is_empty() {
+        options.dir = Some(download.save_path.clone());
+    }
+    if options.category.is_none() {
+        options.category = download.category.clone();
+    }
+    options
+}
+
+/// Options to add a download again into the file it was saving to
+fn continue_file(options: Option<DownloadOptions>, download: &Download) -> DownloadOptions {
+    let mut options = restore_options(options, download);
+    if !download.name.is_empty() {
+        options.out = Some(download.name.clone());
+    }
+    options
+}
+
+/// Announce that `old_gid` was re-added as `new_gid`, carrying its options over
+pub(crate) async fn replace_download(
+    adapter: &EngineAdapter,
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    old_gid: String,
+    new_gid: &str,
+    options: Option<&DownloadOptions>,
+) {
+    if adapter.is_metalink(&old_gid) {
+        adapter.mark_metalink(new_gid);
+    }
+    // Continuing the same file is not a new conflict
+    adapter.record_conflict(new_gid, adapter.conflict(&old_gid));
+    remember_options(db, new_gid, options);
+    let _ = ui_sender.send(UiMessage::DownloadRemoved(old_gid)).await;
+    if let Some(download) = adapter.get_status(new_gid) {
+        let _ = ui_sender.send(UiMessage::DownloadAdded(download)).await;
+    }
+}
+
+/// Re-add a download from its database row and stored options
+async fn restore_download(
+    adapter: &EngineAdapter,
+    db: &Database,
+    ui_sender: &async_channel::Sender<UiMessage>,
+    gid: String,
+) {
+    // The engine already restored it from its own session
+    if adapter.get_status(&gid).is_some() {
+        return;
+    }
+
+    let download = match DownloadsDb::get_by_gid(db, &gid) {
+        Ok(Some(download)) => download,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("Failed to load download {}: {}", gid, e);
+            return;
+        }
+    };
+
+    // ...possibly under a new GID; take the row over instead of adding it twice
+    let candidate = Candidate::for_download(&download);
+    if let Some(existing) = duplicate::find(&candidate, &adapter.get_all()) {
+        if let Ok(Some(data)) = DownloadsDb::get_torrent(db, &gid) {
+            if let Err(e) = DownloadsDb::save_torrent(db, &existing.gid, &data) {
+                log::error!("Failed to save torrent file: {}", e);
+            }
+        }
+        let options = stored_options(db, &gid);
+        replace_download(adapter, db, ui_sender, gid, &existing.gid, options.as_ref()).await;
+        return;
+    }
+
+    let options = Some(restore_options(stored_options(db, &gid), &download));
+
+    let torrent = match download.download_type {
+        DownloadType::Torrent => match DownloadsDb::get_torrent(db, &gid) {
+            Ok(torrent) => torrent,
+            Err(e) => {
+                log::error!("Failed to load torrent file for {}: {}", gid, e);
+                None
+            }
+        },
+        _ => None,
+    };
+
+    let result = match download.download_type {
+        DownloadType::Http | DownloadType::Metalink => match &download.url {
+            Some(url) => {
+                let options = Some(continue_file(options.clone(), &download));
+                adapter.readd_download(url.clone(), options).await
+            }
+            None => return,
+        },
+        DownloadType::Magnet => match &download.magnet_uri {
+            Some(uri) => adapter.add_magnet(uri, options.clone()).await,
+            None => return,
+        },
+        // Re-added over the files already in save_path, which `dir` points at
+        DownloadType::Torrent => match &torrent {
+            Some(data) => adapter.add_torrent(data, options.clone()).await,
+            None => {
+                log::warn!(
+                    "Cannot restore torrent {}: no stored .torrent file",
+                    download.name
+                );
+                return;
+            }
+        },
+        DownloadType::Ftp => {
+            log::warn!(
+                "Skipping FTP download restoration for {}: not supported",
+                download.name
+            );
+            return;
+        }
+    };
+
+    match result {
+        Ok(new_gid) => {
+            if let Some(data) = &torrent {
+                if let Err(e) = DownloadsDb::save_torrent(db, &new_gid, data) {
+                    log::error!("Failed to save torrent file: {}", e);
+                }
+            }
+            replace_download(adapter, db, ui_sender, gid, &new_gid, options.as_ref()).await;
+            if let Some(data) = torrent {
+                let dir = options.and_then(|o| o.dir).unwrap_or(download.save_path);
+                spawn_recheck(data, dir.into(), download.name, ui_sender.clone());
+            }
+        }
+        Err(e) => {
+            let message = format!("Failed to restore {}: {}", download.name, e);
+            let _ = ui_sender.send(UiMessage::Error(message)).await;
+        }
+    }
+}
+
+/// Hash a restored torrent's files against its pieces in the background,
+/// reporting one that came back with missing or damaged data
+fn spawn_recheck(
+    data: Vec<u8>,
+    dir: std::path::PathBuf,
+    name: String,
+    ui_sender: async_channel::Sender<UiMessage>,
+) {
+    tokio::spawn(async move {
+        let recheck = tokio::task::spawn_blocking(move || torrent::recheck(&data, &dir)).await;
+        match recheck {
+            Ok(Ok(recheck)) if recheck.is_complete() => {
+                log::info!("Rechecked {}: all {} pieces intact", name, recheck.pieces);
+            }
+            Ok(Ok(recheck)) => {
+                let message = format!(
+                    "{} was restored with {} of {} pieces intact",
+                    name, recheck.intact, recheck.pieces
+                );
+                let _ = ui_sender.send(UiMessage::Error(message)).await;
+            }
+            Ok(Err(e)) => log::warn!("Could not recheck {}: {}", name, e),
+            Err(e) => log::warn!("Could not recheck {}: {}", name, e),
+        }
+    });
+}
+
+/// Record a UI message in the downloads table
+///
+/// Whichever process owns the engine, a frontend or the daemon, calls this
+/// for every message it receives so the history and restore data stay
+/// current. A frontend attached to the daemon leaves it to the daemon, or
+/// every message would be written twice.
+pub fn record_ui_message(db: &Database, msg: &UiMessage) {
+    let result = match msg {
+        // Cookies and credentials are only needed until the download
+        // finishes; one skipped by the conflict policy is added complete
+        UiMessage::DownloadCompleted(download) => DownloadsDb::save(db, download)
+            .and_then(|_| DownloadsDb::clear_secrets(db, &download.gid)),
+        UiMessage::DownloadAdded(download) if download.status == DownloadState::Complete => {
+            DownloadsDb::save(db, download)
+                .and_then(|_| DownloadsDb::clear_secrets(db, &download.gid))
+        }
+        UiMessage::DownloadAdded(download) | UiMessage::DownloadUpdated(_, download) => {
+            DownloadsDb::save(db, download).map(|_| ())
+        }
+        UiMessage::DownloadRemoved(gid) => DownloadsDb::delete(db, gid),
+        UiMessage::ChecksumVerified(gid, result) => {
+            DownloadsDb::set_checksum_result(db, gid, result)
+        }
+        _ => Ok(()),
+    };
+
+    if let Err(e) = result {
+        log::error!("Failed to record download state: {}", e);
+    }
+}
+
+/// Re-queue downloads that were still incomplete when the app last exited
+///
+/// The service skips downloads the engine already restored from its own
+/// session and re-adds the rest with their stored options.
+pub fn restore_incomplete_downloads(
+    db: &Database,
+    cmd_sender: &async_channel::Sender<EngineCommand>,
+) {
+    match DownloadsDb::get_incomplete(db) {
+        Ok(incomplete) => {
+            if incomplete.is_empty() {
+                return;
+            }
+
+            log::info!("Restoring {} incomplete downloads", incomplete.len());
+
+            for download in incomplete {
+                let _ = cmd_sender.send_blocking(EngineCommand::RestoreDownload(download.gid));
+            }
+        }
+        Err(e) => {
+            log::error!("Failed to restore incomplete downloads: {}", e);
+        }
+    }
+}
+
 /// Convert settings to engine configuration
 pub fn settings_to_engine_config(settings: &Settings) -> EngineConfig {
     let download_dir = std::path::PathBuf::from(&settings.download_path);
@@ -312,20 +1147,6 @@ pub fn settings_to_engine_config(settings: &Settings) -> EngineConfig {
         let _ = std::fs::create_dir_all(parent);
     }
 
-    // Build proxy URL if proxy is enabled
-    // Format: protocol://[user:pass@]host:port
-    let proxy_url = if settings.proxy_enabled && !settings.proxy_url.is_empty() {
-        let auth = match (&settings.proxy_user, &settings.proxy_pass) {
-            (Some(user), Some(pass)) if !user.is_empty() => {
-                format!("{}:{}@", user, pass)
-            }
-            _ => String::new(),
-        };
-        Some(format!("{}://{}{}", settings.proxy_type, auth, settings.proxy_url))
-    } else {
-        None
-    };
-
     let mut config = EngineConfig {
         download_dir,
         max_concurrent_downloads: settings.max_concurrent_downloads as usize,
@@ -352,7 +1173,125 @@ pub fn settings_to_engine_config(settings: &Settings) -> EngineConfig {
     };
 
     // Set proxy URL in HTTP config
-    config.http.proxy_url = proxy_url;
+    config.http.proxy_url = proxy_url(settings);
 
     config
 }
+
+/// The proxy URL for the engine if the proxy is enabled
+///
+/// Format: protocol://[user:pass@]host:port, with the user name and
+/// password percent-encoded so `@`, `:` or `/` in them stay part of them.
+pub(crate) fn proxy_url(settings: &Settings) -> Option<String> {
+    if !settings.proxy_enabled || settings.proxy_url.is_empty() {
+        return None;
+    }
+    let auth = match (&settings.proxy_user, &settings.proxy_pass) {
+        (Some(user), Some(pass)) if !user.is_empty() => {
+            format!("{}:{}@", encode_userinfo(user), encode_userinfo(pass))
+        }
+        _ => String::new(),
+    };
+    Some(format!(
+        "{}://{}{}",
+        settings.proxy_type, auth, settings.proxy_url
+    ))
+}
+
+/// Percent-encode everything but unreserved characters (RFC 3986)
+fn encode_userinfo(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::category;
+    use crate::types::{Category, DuplicateMatch};
+
+    #[test]
+    fn test_continued_file_stays_in_its_folder() {
+        let download = Download {
+            gid: "abc".to_string(),
+            name: "distro.iso".to_string(),
+            save_path: "/home/me/Downloads".to_string(),
+            ..Default::default()
+        };
+        // Added to the default folder, which has since moved to /srv/new
+        let stored = DownloadOptions {
+            max_download_limit: Some("1M".to_string()),
+            ..Default::default()
+        };
+        let options = continue_file(Some(stored), &download);
+        assert_eq!(options.dir.as_deref(), Some("/home/me/Downloads"));
+        assert_eq!(options.out.as_deref(), Some("distro.iso"));
+        assert_eq!(options.max_download_limit.as_deref(), Some("1M"));
+
+        // ...and a category with its own folder does not move it either
+        let isos = Category {
+            name: "ISOs".to_string(),
+            save_dir: "/srv/isos".to_string(),
+            ..Default::default()
+        };
+        let options = category::apply(options, &isos);
+        assert_eq!(options.dir.as_deref(), Some("/home/me/Downloads"));
+
+        // A folder picked when it was added is kept as it was
+        let stored = DownloadOptions {
+            dir: Some("/mnt/usb".to_string()),
+            ..Default::default()
+        };
+        let options = continue_file(Some(stored), &download);
+        assert_eq!(options.dir.as_deref(), Some("/mnt/usb"));
+
+        // Torrents and magnets go back over the files in their folder, in
+        // their category
+        let torrent = Download {
+            download_type: DownloadType::Torrent,
+            category: Some("ISOs".to_string()),
+            ..download
+        };
+        let options = restore_options(None, &torrent);
+        assert_eq!(options.dir.as_deref(), Some("/home/me/Downloads"));
+        assert_eq!(options.category.as_deref(), Some("ISOs"));
+        assert_eq!(options.out, None);
+    }
+
+    #[test]
+    fn test_add_outcome_comes_from_the_first_answer() {
+        let added = UiMessage::DownloadAdded(Download {
+            gid: "abc".to_string(),
+            ..Default::default()
+        });
+        assert!(matches!(add_outcome(&added), Some(AddOutcome::Added(gid)) if gid == "abc"));
+
+        let existing = ExistingDownload {
+            gid: "old".to_string(),
+            name: "distro.iso".to_string(),
+            state: DownloadState::Complete,
+            matched: DuplicateMatch::Url,
+        };
+        let cmd = EngineCommand::AddDownload {
+            url: "https://example.com/distro.iso".to_string(),
+            options: None,
+        };
+        let duplicate = UiMessage::DuplicateDownload(existing, Box::new(cmd));
+        assert!(matches!(
+            add_outcome(&duplicate),
+            Some(AddOutcome::Duplicate(existing)) if existing.gid == "old"
+        ));
+
+        let failed = UiMessage::Error("Invalid URL".to_string());
+        assert!(matches!(add_outcome(&failed), Some(AddOutcome::Failed(m)) if m == "Invalid URL"));
+        assert!(add_outcome(&UiMessage::DownloadRemoved("old".to_string())).is_none());
+    }
+}
@@ -0,0 +1,454 @@
+//! Torrent metainfo parsing
+//!
+//! A small bencode reader, enough to describe a .torrent file before it is
+//! handed to the engine: name, info hash, files and trackers. It also
+//! rechecks the data of a torrent restored over files already on disk.
+
+use crate::error::{Error, Result};
+use crate::types::{TorrentFileEntry, TorrentInfo};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Nesting limit for lists and dictionaries
+const MAX_DEPTH: usize = 64;
+
+/// A decoded bencode value
+#[derive(Debug, Clone, PartialEq)]
+enum Value<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Value<'a>>),
+    Dict(BTreeMap<&'a [u8], Value<'a>>),
+}
+
+impl<'a> Value<'a> {
+    fn get(&self, key: &str) -> Option<&Value<'a>> {
+        match self {
+            Value::Dict(dict) => dict.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<String> {
+        match self {
+            Value::Bytes(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> &[Value<'a>] {
+        match self {
+            Value::List(list) => list,
+            _ => &[],
+        }
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn value(&mut self, depth: usize) -> Result<Value<'a>> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nesting too deep"));
+        }
+        match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                let digits = self.until(b'e')?;
+                std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .map(Value::Int)
+                    .ok_or_else(|| invalid("bad integer"))
+            }
+            b'l' => {
+                self.pos += 1;
+                let mut list = Vec::new();
+                while self.peek()? != b'e' {
+                    list.push(self.value(depth + 1)?);
+                }
+                self.pos += 1;
+                Ok(Value::List(list))
+            }
+            b'd' => {
+                self.pos += 1;
+                let mut dict = BTreeMap::new();
+                while self.peek()? != b'e' {
+                    let key = self.bytes()?;
+                    let value = self.value(depth + 1)?;
+                    dict.insert(key, value);
+                }
+                self.pos += 1;
+                Ok(Value::Dict(dict))
+            }
+            b'0'..=b'9' => self.bytes().map(Value::Bytes),
+            _ => Err(invalid("unexpected byte")),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = std::str::from_utf8(self.until(b':')?)
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .ok_or_else(|| invalid("bad string length"))?;
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid("truncated string"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn until(&mut self, terminator: u8) -> Result<&'a [u8]> {
+        let rest = &self.data[self.pos..];
+        let len = rest
+            .iter()
+            .position(|b| *b == terminator)
+            .ok_or_else(|| invalid("unexpected end of data"))?;
+        self.pos += len + 1;
+        Ok(&rest[..len])
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| invalid("unexpected end of data"))
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidInput(format!("Invalid torrent file: {}", reason))
+}
+
+/// Describe a .torrent file
+pub fn parse_torrent(data: &[u8]) -> Result<TorrentInfo> {
+    let mut decoder = Decoder { data, pos: 0 };
+    let root = decoder.value(0)?;
+
+    // The info hash covers the raw bytes of the info dictionary
+    let info_bytes = info_dict_bytes(data)?;
+    let info_hash = hex::encode(Sha1::digest(info_bytes));
+
+    let info = root.get("info").ok_or_else(|| invalid("missing info"))?;
+    let name = info
+        .get("name.utf-8")
+        .or_else(|| info.get("name"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("missing name"))?;
+
+    let files = match info.get("files") {
+        Some(files) => files
+            .as_list()
+            .iter()
+            .enumerate()
+            .map(|(index, file)| {
+                let path = file
+                    .get("path.utf-8")
+                    .or_else(|| file.get("path"))
+                    .map(|p| {
+                        p.as_list()
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .collect::<Vec<_>>()
+                            .join("/")
+                    })
+                    .unwrap_or_default();
+                TorrentFileEntry {
+                    index,
+                    path,
+                    length: file.get("length").and_then(Value::as_int).unwrap_or(0) as u64,
+                }
+            })
+            .collect(),
+        None => vec![TorrentFileEntry {
+            index: 0,
+            path: name.clone(),
+            length: info.get("length").and_then(Value::as_int).unwrap_or(0) as u64,
+        }],
+    };
+
+    let mut announce_list: Vec<String> = Vec::new();
+    for tracker in root
+        .get("announce")
+        .and_then(Value::as_str)
+        .into_iter()
+        .chain(
+            root.get("announce-list")
+                .map(Value::as_list)
+                .unwrap_or_default()
+                .iter()
+                .flat_map(Value::as_list)
+                .filter_map(Value::as_str),
+        )
+    {
+        if !announce_list.contains(&tracker) {
+            announce_list.push(tracker);
+        }
+    }
+
+    Ok(TorrentInfo {
+        name,
+        info_hash,
+        total_size: files.iter().map(|f| f.length).sum(),
+        files,
+        comment: root.get("comment").and_then(Value::as_str),
+        creation_date: root.get("creation date").and_then(Value::as_int),
+        announce_list,
+    })
+}
+
+/// How much of a torrent's data was found intact on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recheck {
+    pub pieces: usize,
+    pub intact: usize,
+}
+
+impl Recheck {
+    pub fn is_complete(&self) -> bool {
+        self.intact == self.pieces
+    }
+}
+
+/// Hash a torrent's files in `dir` against its piece hashes
+///
+/// Multi-file torrents are looked for in a folder named after the torrent.
+/// A missing or short file fails the pieces it is part of.
+pub fn recheck(data: &[u8], dir: &Path) -> Result<Recheck> {
+    let mut decoder = Decoder { data, pos: 0 };
+    let root = decoder.value(0)?;
+    let info = root.get("info").ok_or_else(|| invalid("missing info"))?;
+    let name = info
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("missing name"))?;
+    let piece_length = info
+        .get("piece length")
+        .and_then(Value::as_int)
+        .filter(|n| *n > 0)
+        .ok_or_else(|| invalid("missing piece length"))? as u64;
+    let hashes = match info.get("pieces") {
+        Some(Value::Bytes(hashes)) if hashes.len() % 20 == 0 => *hashes,
+        _ => return Err(invalid("bad piece hashes")),
+    };
+
+    // Paths that would leave the torrent's folder count as missing
+    let files: Vec<(Option<PathBuf>, u64)> = match info.get("files") {
+        Some(files) => files
+            .as_list()
+            .iter()
+            .map(|file| {
+                let parts: Vec<String> = std::iter::once(name.clone())
+                    .chain(
+                        file.get("path")
+                            .map(Value::as_list)
+                            .unwrap_or_default()
+                            .iter()
+                            .filter_map(Value::as_str),
+                    )
+                    .collect();
+                let length = file.get("length").and_then(Value::as_int).unwrap_or(0);
+                (safe_path(dir, &parts), length.max(0) as u64)
+            })
+            .collect(),
+        None => {
+            let length = info.get("length").and_then(Value::as_int).unwrap_or(0);
+            vec![(safe_path(dir, &[name]), length.max(0) as u64)]
+        }
+    };
+
+    let mut recheck = Recheck {
+        pieces: hashes.len() / 20,
+        intact: 0,
+    };
+    let mut piece = Piece::default();
+    let mut buf = vec![0; 64 * 1024];
+    for (path, mut remaining) in files {
+        let mut file = path.and_then(|p| std::fs::File::open(p).ok());
+        while remaining > 0 {
+            let take = remaining
+                .min(piece_length - piece.filled)
+                .min(buf.len() as u64) as usize;
+            let chunk = &mut buf[..take];
+            if file.as_mut().is_none_or(|f| f.read_exact(chunk).is_err()) {
+                file = None;
+                piece.intact = false;
+            }
+            piece.hasher.update(&chunk[..]);
+            piece.filled += take as u64;
+            remaining -= take as u64;
+            if piece.filled == piece_length {
+                piece.finish(hashes, &mut recheck);
+            }
+        }
+    }
+    if piece.filled > 0 {
+        piece.finish(hashes, &mut recheck);
+    }
+    Ok(recheck)
+}
+
+/// The piece being hashed by `recheck`
+struct Piece {
+    index: usize,
+    hasher: Sha1,
+    filled: u64,
+    intact: bool,
+}
+
+impl Default for Piece {
+    fn default() -> Self {
+        Self {
+            index: 0,
+            hasher: Sha1::new(),
+            filled: 0,
+            intact: true,
+        }
+    }
+}
+
+impl Piece {
+    /// Compare the piece with its hash and start the next one
+    fn finish(&mut self, hashes: &[u8], recheck: &mut Recheck) {
+        let digest = std::mem::take(&mut self.hasher).finalize();
+        let expected = hashes.get(self.index * 20..(self.index + 1) * 20);
+        if self.intact && expected == Some(&digest[..]) {
+            recheck.intact += 1;
+        }
+        *self = Piece {
+            index: self.index + 1,
+            ..Default::default()
+        };
+    }
+}
+
+/// `dir/parts...`, or `None` if a part is empty or would leave `dir`
+fn safe_path(dir: &Path, parts: &[String]) -> Option<PathBuf> {
+    let mut path = dir.to_path_buf();
+    for part in parts {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(part)), None) => path.push(part),
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// Find the raw bytes of the top-level info dictionary
+fn info_dict_bytes(data: &[u8]) -> Result<&[u8]> {
+    let mut decoder = Decoder { data, pos: 0 };
+    if decoder.peek()? != b'd' {
+        return Err(invalid("not a dictionary"));
+    }
+    decoder.pos += 1;
+    while decoder.peek()? != b'e' {
+        let key = decoder.bytes()?;
+        let start = decoder.pos;
+        decoder.value(1)?;
+        if key == b"info" {
+            return Ok(&data[start..decoder.pos]);
+        }
+    }
+    Err(invalid("missing info"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multi_file_torrent() {
+        let data = b"d8:announce18:udp://tracker.a:8013:announce-listll18:udp://tracker.a:80el18:udp://tracker.b:80ee7:comment4:test4:infod5:filesld6:lengthi700e4:pathl6:videos9:movie.mkveed6:lengthi12e4:pathl8:info.nfoeee4:name6:Bundle12:piece lengthi16384e6:pieces0:ee";
+
+        let info = parse_torrent(data).unwrap();
+        assert_eq!(info.name, "Bundle");
+        assert_eq!(info.total_size, 712);
+        assert_eq!(info.files[0].path, "videos/movie.mkv");
+        assert_eq!(info.files[1].path, "info.nfo");
+        assert_eq!(
+            info.announce_list,
+            ["udp://tracker.a:80", "udp://tracker.b:80"]
+        );
+        assert_eq!(info.comment.as_deref(), Some("test"));
+        assert_eq!(info.info_hash.len(), 40);
+
+        assert!(parse_torrent(b"d4:infod4:name").is_err());
+    }
+
+    /// A multi-file torrent of `files` with 4-byte pieces
+    fn torrent(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let content: Vec<u8> = files.iter().flat_map(|(_, data)| data.to_vec()).collect();
+        let pieces: Vec<u8> = content.chunks(4).flat_map(Sha1::digest).collect();
+        let mut out = b"d4:infod5:filesl".to_vec();
+        for (path, data) in files {
+            out.extend(
+                format!("d6:lengthi{}e4:pathl{}:{}ee", data.len(), path.len(), path).bytes(),
+            );
+        }
+        out.extend(b"e4:name6:Bundle12:piece lengthi4e6:pieces");
+        out.extend(format!("{}:", pieces.len()).bytes());
+        out.extend(pieces);
+        out.extend(b"ee");
+        out
+    }
+
+    #[test]
+    fn test_recheck_counts_intact_pieces() {
+        let dir = std::env::temp_dir().join(format!("gosh-fetch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("Bundle")).unwrap();
+        // Pieces: "abcd", "efgh", "ij12", "3456", "7"
+        let data = torrent(&[("a.bin", b"abcdefghij"), ("b.bin", b"1234567")]);
+        let check = || recheck(&data, &dir).unwrap();
+
+        assert_eq!(
+            check(),
+            Recheck {
+                pieces: 5,
+                intact: 0
+            }
+        );
+
+        std::fs::write(dir.join("Bundle/a.bin"), b"abcdefghij").unwrap();
+        // The piece spanning both files fails while the second is missing
+        assert_eq!(
+            check(),
+            Recheck {
+                pieces: 5,
+                intact: 2
+            }
+        );
+
+        std::fs::write(dir.join("Bundle/b.bin"), b"1234567").unwrap();
+        assert!(check().is_complete());
+
+        std::fs::write(dir.join("Bundle/a.bin"), b"abcdXfghij").unwrap();
+        assert_eq!(
+            check(),
+            Recheck {
+                pieces: 5,
+                intact: 4
+            }
+        );
+
+        // A path leaving the folder is never read
+        let escaping = torrent(&[("..", b"abcd")]);
+        assert_eq!(recheck(&escaping, &dir).unwrap().intact, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
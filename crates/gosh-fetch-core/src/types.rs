@@ -27,10 +27,14 @@ pub struct DownloadOptions {
     pub max_download_limit: Option<String>,
     /// Max upload speed
     pub max_upload_limit: Option<String>,
-    /// Checksum type for verification ("md5" or "sha256")
+    /// Checksum type for verification ("md5", "sha1", "sha256", "sha512"
+    /// or "blake3")
     pub checksum_type: Option<String>,
     /// Checksum value to verify against
     pub checksum_value: Option<String>,
+    /// URL or path of a checksum file (e.g. SHA256SUMS) to take the
+    /// checksum from when no value is given
+    pub checksum_file: Option<String>,
     /// Mirror URLs for fallback
     pub mirror_urls: Option<Vec<String>>,
     /// Download priority ("critical", "high", "normal", "low")
@@ -43,6 +47,195 @@ pub struct DownloadOptions {
     pub sequential: Option<bool>,
     /// File preallocation mode ("none", "sparse", "full")
     pub preallocation: Option<String>,
+    /// Category to file the download under instead of matching one by rules
+    pub category: Option<String>,
+    /// Actions to run once the download completes, instead of the global
+    /// ones; an empty list runs none
+    pub completion_actions: Option<Vec<CompletionAction>>,
+    /// What to do when the URL, torrent or target file is already a
+    /// download; reported by default
+    pub on_duplicate: Option<DuplicateAction>,
+    /// What to do when the target file already exists, instead of the
+    /// global policy
+    pub conflict_policy: Option<ConflictPolicy>,
+    /// Proxy for the requests made before adding the download, instead of
+    /// the one in settings (`scheme://[user:pass@]host:port`); an empty
+    /// string connects directly. Only the URL probe uses it: the engine has
+    /// one proxy for every transfer, so the download itself does not.
+    pub proxy: Option<String>,
+}
+
+/// What to do when a download being added already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateAction {
+    /// Leave the existing download alone and report it
+    #[default]
+    Report,
+    /// Add the new download next to the existing one
+    Add,
+    /// Remove the existing download, keeping its files, and add the new one
+    Restart,
+}
+
+/// What to do when a download's target file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Save as `name (1).ext`, `name (2).ext` and so on
+    #[default]
+    Rename,
+    /// Delete the existing file and download it again
+    Overwrite,
+    /// Keep the existing file and record the download as complete
+    Skip,
+    /// Treat the existing file as a partial download and continue it
+    Resume,
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rename" => Ok(ConflictPolicy::Rename),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "resume" => Ok(ConflictPolicy::Resume),
+            _ => Err(format!("unknown conflict policy {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictPolicy::Rename => write!(f, "rename"),
+            ConflictPolicy::Overwrite => write!(f, "overwrite"),
+            ConflictPolicy::Skip => write!(f, "skip"),
+            ConflictPolicy::Resume => write!(f, "resume"),
+        }
+    }
+}
+
+/// What the conflict policy did with a download whose file already existed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictOutcome {
+    Renamed,
+    Overwritten,
+    Skipped,
+    Resumed,
+}
+
+impl std::str::FromStr for ConflictOutcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "renamed" => Ok(ConflictOutcome::Renamed),
+            "overwritten" => Ok(ConflictOutcome::Overwritten),
+            "skipped" => Ok(ConflictOutcome::Skipped),
+            "resumed" => Ok(ConflictOutcome::Resumed),
+            _ => Err(format!("unknown conflict outcome {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for ConflictOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictOutcome::Renamed => write!(f, "renamed"),
+            ConflictOutcome::Overwritten => write!(f, "overwritten"),
+            ConflictOutcome::Skipped => write!(f, "skipped"),
+            ConflictOutcome::Resumed => write!(f, "resumed"),
+        }
+    }
+}
+
+/// What an existing download has in common with one being added
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateMatch {
+    Url,
+    InfoHash,
+    /// Both save to the same file
+    Path,
+}
+
+/// Download that one being added duplicates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExistingDownload {
+    pub gid: String,
+    pub name: String,
+    pub state: DownloadState,
+    pub matched: DuplicateMatch,
+}
+
+/// What a URL serves, learned without downloading it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeResult {
+    /// URL as given
+    pub url: String,
+    /// URL after following redirects
+    pub final_url: String,
+    /// Each URL redirected to, in order; the last is `final_url`
+    pub redirects: Vec<String>,
+    /// Size in bytes, when the server says
+    pub content_length: Option<u64>,
+    pub mime_type: Option<String>,
+    /// Name the download would be saved under without an `out`
+    pub file_name: Option<String>,
+    /// Whether the server takes byte ranges, so the download can resume
+    pub resumable: bool,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Something done with a download once it completes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CompletionAction {
+    /// Move the file into a folder
+    Move { dir: String },
+    /// Copy the file into a folder
+    Copy { dir: String },
+    /// Run a shell command with `GOSH_GID`, `GOSH_PATH`, `GOSH_NAME` and
+    /// `GOSH_URL` set
+    Command { command: String },
+    /// Open the file with the desktop's default application
+    Open,
+    /// Unpack a zip, tar, tar.gz or tar.zst archive; other files are skipped
+    Extract {
+        #[serde(default)]
+        target: ExtractTarget,
+        /// Remove the archive once it is unpacked
+        #[serde(default)]
+        delete_archive: bool,
+    },
+}
+
+/// Where an archive is unpacked to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractTarget {
+    /// A new folder named after the archive, next to it
+    #[default]
+    Sibling,
+    /// Straight into the save directory of the download's category
+    Category,
+}
+
+/// Outcome of a completion action, kept with the download's history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionLogEntry {
+    /// What was done, e.g. "move to /srv/isos"
+    pub action: String,
+    pub success: bool,
+    /// Where the file ended up, the command's exit status, or the error
+    pub message: String,
+    /// Unix timestamp the action ran at
+    pub ran_at: i64,
 }
 
 /// Global download statistics
@@ -53,6 +246,9 @@ pub struct GlobalStats {
     pub num_active: u32,
     pub num_waiting: u32,
     pub num_stopped: u32,
+    /// Bandwidth profile in effect, if any
+    #[serde(default)]
+    pub bandwidth_profile: Option<String>,
 }
 
 /// Torrent file information (for display before adding)
@@ -105,6 +301,18 @@ pub struct Download {
     pub connections: u32,
     pub seeders: u32,
     pub selected_files: Option<Vec<usize>>,
+    /// Name of the category the download was filed under
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Unix timestamp a scheduled download will start at
+    #[serde(default)]
+    pub scheduled_start: Option<i64>,
+    /// Outcome of the last checksum verification
+    #[serde(default)]
+    pub checksum: Option<ChecksumResult>,
+    /// What the conflict policy did because the file already existed
+    #[serde(default)]
+    pub conflict: Option<ConflictOutcome>,
 }
 
 impl Default for Download {
@@ -129,6 +337,119 @@ impl Default for Download {
             connections: 0,
             seeders: 0,
             selected_files: None,
+            category: None,
+            scheduled_start: None,
+            checksum: None,
+            conflict: None,
+        }
+    }
+}
+
+/// Result of hashing a finished download
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChecksumResult {
+    /// Hash algorithm, e.g. "sha256"
+    pub algorithm: String,
+    /// Digest of the file on disk
+    pub digest: String,
+    /// Whether it matched the expected checksum; `None` when there was none
+    pub matched: Option<bool>,
+    /// Unix timestamp of the check
+    pub checked_at: i64,
+}
+
+/// Download category with its own save location and default options
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Category {
+    pub id: i64,
+    pub name: String,
+    /// Directory downloads in this category are saved to
+    pub save_dir: String,
+    /// Options applied where the download does not set its own
+    #[serde(default)]
+    pub options: DownloadOptions,
+    #[serde(default)]
+    pub rules: CategoryRules,
+}
+
+/// What a download has to look like to fall into a category
+///
+/// A download matches when any rule matches. Extensions are given without
+/// the dot, MIME types may end in `/*`, and hosts also match their
+/// subdomains. Tracker rules compare against tracker hostnames.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoryRules {
+    pub extensions: Vec<String>,
+    pub mime_types: Vec<String>,
+    pub hosts: Vec<String>,
+    pub trackers: Vec<String>,
+}
+
+/// RSS/Atom feed subscription
+///
+/// New items whose title passes the include and exclude patterns (regular
+/// expressions, case-insensitive) are downloaded into `category`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Feed {
+    pub id: i64,
+    pub url: String,
+    pub title: String,
+    pub enabled: bool,
+    pub include_pattern: Option<String>,
+    pub exclude_pattern: Option<String>,
+    pub category: Option<String>,
+    /// Seconds between checks
+    pub poll_interval: u64,
+    /// Unix timestamp of the last check
+    pub last_checked: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            url: String::new(),
+            title: String::new(),
+            enabled: true,
+            include_pattern: None,
+            exclude_pattern: None,
+            category: None,
+            poll_interval: 3600,
+            last_checked: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Directory watched for dropped `.torrent`, `.metalink`, `.magnet` and
+/// `.txt` URL list files
+///
+/// Downloads queued from it go to `dir` and `category` when they are set.
+/// Processed files are renamed to `<name>.added`, or moved into an `added`
+/// subfolder with `move_aside`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchFolder {
+    pub id: i64,
+    pub path: String,
+    pub enabled: bool,
+    pub dir: Option<String>,
+    pub category: Option<String>,
+    pub move_aside: bool,
+}
+
+impl Default for WatchFolder {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            path: String::new(),
+            enabled: true,
+            dir: None,
+            category: None,
+            move_aside: false,
         }
     }
 }
@@ -141,6 +462,8 @@ pub enum DownloadType {
     Ftp,
     Torrent,
     Magnet,
+    /// One file of a metalink, fetched over HTTP from its mirrors
+    Metalink,
 }
 
 impl std::fmt::Display for DownloadType {
@@ -150,6 +473,7 @@ impl std::fmt::Display for DownloadType {
             DownloadType::Ftp => write!(f, "ftp"),
             DownloadType::Torrent => write!(f, "torrent"),
             DownloadType::Magnet => write!(f, "magnet"),
+            DownloadType::Metalink => write!(f, "metalink"),
         }
     }
 }
@@ -161,6 +485,7 @@ impl From<&str> for DownloadType {
             "ftp" => DownloadType::Ftp,
             "torrent" => DownloadType::Torrent,
             "magnet" => DownloadType::Magnet,
+            "metalink" => DownloadType::Metalink,
             _ => DownloadType::Http,
         }
     }
@@ -176,6 +501,8 @@ pub enum DownloadState {
     Complete,
     Error,
     Removed,
+    /// Held back until its `scheduled_start`
+    Scheduled,
 }
 
 impl From<&str> for DownloadState {
@@ -187,6 +514,7 @@ impl From<&str> for DownloadState {
             "complete" => DownloadState::Complete,
             "error" => DownloadState::Error,
             "removed" => DownloadState::Removed,
+            "scheduled" => DownloadState::Scheduled,
             _ => DownloadState::Waiting,
         }
     }
@@ -201,6 +529,7 @@ impl std::fmt::Display for DownloadState {
             DownloadState::Complete => write!(f, "complete"),
             DownloadState::Error => write!(f, "error"),
             DownloadState::Removed => write!(f, "removed"),
+            DownloadState::Scheduled => write!(f, "scheduled"),
         }
     }
 }
@@ -234,6 +563,15 @@ pub struct Settings {
     pub min_segment_size: u32, // in KB
     // BitTorrent advanced settings
     pub bt_preallocation: String, // "none", "sparse", "full"
+    // Time-based speed limits
+    #[serde(default)]
+    pub bandwidth_schedule: BandwidthSchedule,
+    /// Actions run on completed downloads whose options and category set none
+    #[serde(default)]
+    pub completion_actions: Vec<CompletionAction>,
+    /// What to do when a download's file already exists
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
 }
 
 impl Default for Settings {
@@ -267,10 +605,38 @@ impl Default for Settings {
             proxy_pass: None,
             min_segment_size: 1024, // 1 MB default
             bt_preallocation: "sparse".to_string(),
+            bandwidth_schedule: BandwidthSchedule::default(),
+            completion_actions: Vec::new(),
+            conflict_policy: ConflictPolicy::default(),
         }
     }
 }
 
+/// Named set of speed limits for the bandwidth schedule
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandwidthProfile {
+    pub name: String,
+    /// Bytes per second, 0 for unlimited
+    pub download_limit: u64,
+    /// Bytes per second, 0 for unlimited
+    pub upload_limit: u64,
+    /// Pause the running downloads while the profile is in effect
+    pub paused: bool,
+}
+
+/// Weekly schedule of bandwidth profiles
+///
+/// `slots` holds a profile name for each hour of the week in local time,
+/// starting Monday 00:00. Hours without a profile use the global limits.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandwidthSchedule {
+    pub enabled: bool,
+    pub profiles: Vec<BandwidthProfile>,
+    pub slots: Vec<Option<String>>,
+}
+
 /// User agent presets
 pub fn get_user_agent_presets() -> Vec<(&'static str, &'static str)> {
     vec![
@@ -0,0 +1,344 @@
+//! Watch folders
+//!
+//! Dropping a `.torrent`, `.metalink`/`.meta4`, `.magnet` or `.txt` URL list
+//! into a watched directory queues its downloads through the same
+//! `EngineCommand`s as adding them by hand, into the folder's download
+//! directory and category. URL lists use the aria2 input file format (see
+//! `input_file`), so a plain list of URLs works too.
+//!
+//! Files are picked up once they are closed after writing or moved into the
+//! folder, so a half-copied file is never read. A processed file is renamed
+//! to `<name>.added`, or moved into an `added` subfolder; one that cannot be
+//! read is renamed to `<name>.failed`. Hidden files are ignored.
+
+use crate::db::{Database, WatchFoldersDb};
+use crate::error::{Error, Result};
+use crate::input_file;
+use crate::service::EngineCommand;
+use crate::types::{DownloadOptions, WatchFolder};
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+/// How often the watcher picks up added, removed and changed folders
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Subfolder processed files are moved into with `move_aside`
+const ADDED_DIR: &str = "added";
+
+/// What a dropped file holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DroppedFile {
+    Torrent,
+    Metalink,
+    /// `.magnet` files and `.txt` URL lists
+    UrlList,
+}
+
+impl DroppedFile {
+    /// The kind of a file by its name; `None` for files that are ignored
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.starts_with('.') {
+            return None;
+        }
+        let extension = Path::new(name).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "torrent" => Some(DroppedFile::Torrent),
+            "metalink" | "meta4" => Some(DroppedFile::Metalink),
+            "magnet" | "txt" => Some(DroppedFile::UrlList),
+            _ => None,
+        }
+    }
+}
+
+/// The commands that queue a dropped file's downloads
+///
+/// The folder's directory and category apply to URL list entries that do
+/// not set their own.
+pub fn commands_for(
+    kind: DroppedFile,
+    data: Vec<u8>,
+    folder: &WatchFolder,
+) -> Result<Vec<EngineCommand>> {
+    let options = (folder.dir.is_some() || folder.category.is_some()).then(|| DownloadOptions {
+        dir: folder.dir.clone(),
+        category: folder.category.clone(),
+        ..Default::default()
+    });
+
+    match kind {
+        DroppedFile::Torrent => Ok(vec![EngineCommand::AddTorrent { data, options }]),
+        DroppedFile::Metalink => Ok(vec![EngineCommand::AddMetalink { data, options }]),
+        DroppedFile::UrlList => {
+            let text = String::from_utf8(data)
+                .map_err(|_| Error::InvalidInput("URL list is not UTF-8 text".to_string()))?;
+            let entries = input_file::parse(&text)?;
+            if entries.is_empty() {
+                return Err(Error::InvalidInput("no URLs listed".to_string()));
+            }
+            Ok(entries
+                .into_iter()
+                .map(|mut entry| {
+                    if entry.options.dir.is_none() {
+                        entry.options.dir = folder.dir.clone();
+                    }
+                    if entry.options.category.is_none() {
+                        entry.options.category = folder.category.clone();
+                    }
+                    entry.into_command()
+                })
+                .collect())
+        }
+    }
+}
+
+/// Queue the downloads of a file dropped into `folder`
+///
+/// Returns the number of downloads queued, or `None` when the file is not
+/// one to pick up. A file that cannot be read is renamed aside and its
+/// error returned; one whose commands could not be sent is left in place.
+pub fn process(
+    path: &Path,
+    folder: &WatchFolder,
+    cmd_sender: &async_channel::Sender<EngineCommand>,
+) -> Result<Option<usize>> {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return Ok(None);
+    };
+    let Some(kind) = DroppedFile::from_name(name) else {
+        return Ok(None);
+    };
+    // Already processed through an earlier event for the same file
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let commands = match std::fs::read(path)
+        .map_err(Error::from)
+        .and_then(|data| commands_for(kind, data, folder))
+    {
+        Ok(commands) => commands,
+        Err(e) => {
+            std::fs::rename(path, path.with_file_name(format!("{}.failed", name)))?;
+            return Err(e);
+        }
+    };
+
+    let queued = commands.len();
+    for cmd in commands {
+        cmd_sender.send_blocking(cmd)?;
+    }
+
+    let done = if folder.move_aside {
+        let dir = Path::new(&folder.path).join(ADDED_DIR);
+        std::fs::create_dir_all(&dir)?;
+        dir.join(name)
+    } else {
+        path.with_file_name(format!("{}.added", name))
+    };
+    std::fs::rename(path, done)?;
+    Ok(Some(queued))
+}
+
+/// Process every file already in a folder
+fn scan(folder: &WatchFolder, cmd_sender: &async_channel::Sender<EngineCommand>) {
+    let entries = match std::fs::read_dir(&folder.path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Failed to read watch folder {}: {}", folder.path, e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        handle(&entry.path(), folder, cmd_sender);
+    }
+}
+
+fn handle(path: &Path, folder: &WatchFolder, cmd_sender: &async_channel::Sender<EngineCommand>) {
+    match process(path, folder, cmd_sender) {
+        Ok(Some(queued)) => log::info!("Queued {} download(s) from {}", queued, path.display()),
+        Ok(None) => {}
+        Err(e) => log::warn!("Failed to add {}: {}", path.display(), e),
+    }
+}
+
+/// Watch the enabled folders in a background thread, queueing downloads on
+/// `cmd_sender`
+///
+/// Files already in a folder are processed when it starts being watched.
+/// Changes to the folder list are picked up every `RELOAD_INTERVAL`. Stops
+/// when the command channel closes.
+pub fn spawn(db: Database, cmd_sender: async_channel::Sender<EngineCommand>) {
+    std::thread::spawn(move || {
+        let (event_sender, events) = std::sync::mpsc::channel::<notify::Result<Event>>();
+        let mut watcher = match notify::recommended_watcher(event_sender) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                log::error!("Watch folders disabled: {}", e);
+                return;
+            }
+        };
+
+        let mut watched: HashMap<PathBuf, WatchFolder> = HashMap::new();
+        reload(&db, &mut watcher, &mut watched, &cmd_sender);
+
+        while !cmd_sender.is_closed() {
+            let event = match events.recv_timeout(RELOAD_INTERVAL) {
+                Ok(Ok(event)) => event,
+                Ok(Err(e)) => {
+                    log::warn!("Watch folder error: {}", e);
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    reload(&db, &mut watcher, &mut watched, &cmd_sender);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            // Events were dropped; look at everything again
+            if event.need_rescan() {
+                for folder in watched.values() {
+                    scan(folder, &cmd_sender);
+                }
+                continue;
+            }
+            if !matches!(
+                event.kind,
+                EventKind::Access(AccessKind::Close(AccessMode::Write))
+                    | EventKind::Modify(ModifyKind::Name(_))
+            ) {
+                continue;
+            }
+            for path in &event.paths {
+                if let Some(folder) = path.parent().and_then(|dir| watched.get(dir)) {
+                    handle(path, folder, &cmd_sender);
+                }
+            }
+        }
+    });
+}
+
+/// Make the watched folders match the enabled folders in the database
+///
+/// Folders that cannot be watched yet, such as ones not created, are tried
+/// again on the next reload.
+fn reload(
+    db: &Database,
+    watcher: &mut impl Watcher,
+    watched: &mut HashMap<PathBuf, WatchFolder>,
+    cmd_sender: &async_channel::Sender<EngineCommand>,
+) {
+    let folders = match WatchFoldersDb::list(db) {
+        Ok(folders) => folders,
+        Err(e) => {
+            log::error!("Failed to load watch folders: {}", e);
+            return;
+        }
+    };
+    let wanted: HashMap<PathBuf, WatchFolder> = folders
+        .into_iter()
+        .filter(|folder| folder.enabled)
+        .map(|folder| (PathBuf::from(&folder.path), folder))
+        .collect();
+
+    watched.retain(|path, _| {
+        let keep = wanted.contains_key(path);
+        if !keep {
+            let _ = watcher.unwatch(path);
+            log::info!("Stopped watching {}", path.display());
+        }
+        keep
+    });
+
+    for (path, folder) in wanted {
+        // Settings may have changed; the watch itself stays
+        if let Some(current) = watched.get_mut(&path) {
+            *current = folder;
+            continue;
+        }
+        if let Err(e) = watcher.watch(&path, RecursiveMode::NonRecursive) {
+            log::warn!("Cannot watch {}: {}", path.display(), e);
+            continue;
+        }
+        log::info!("Watching {} for downloads", path.display());
+        scan(&folder, cmd_sender);
+        watched.insert(path, folder);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dropped_files_are_queued_and_renamed() {
+        let root = std::env::temp_dir().join(format!("gosh-fetch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let folder = WatchFolder {
+            path: root.to_string_lossy().to_string(),
+            dir: Some("/data/builds".to_string()),
+            category: Some("Builds".to_string()),
+            ..Default::default()
+        };
+        let (sender, receiver) = async_channel::unbounded();
+
+        let list = root.join("nightly.txt");
+        std::fs::write(
+            &list,
+            "https://a.example.com/app.tar.gz\thttps://b.example.com/app.tar.gz\n\
+             https://example.com/notes.pdf\n  dir=/data/docs\n\
+             magnet:?xt=urn:btih:abc\n",
+        )
+        .unwrap();
+        assert_eq!(process(&list, &folder, &sender).unwrap(), Some(3));
+        assert!(!list.exists());
+        assert!(root.join("nightly.txt.added").is_file());
+
+        let Ok(EngineCommand::AddDownload { url, options }) = receiver.try_recv() else {
+            panic!("expected an HTTP download");
+        };
+        let options = options.unwrap();
+        assert_eq!(url, "https://a.example.com/app.tar.gz");
+        assert_eq!(options.dir.as_deref(), Some("/data/builds"));
+        assert_eq!(options.category.as_deref(), Some("Builds"));
+        assert_eq!(options.mirror_urls.unwrap().len(), 1);
+        let Ok(EngineCommand::AddDownload { options, .. }) = receiver.try_recv() else {
+            panic!("expected an HTTP download");
+        };
+        assert_eq!(options.unwrap().dir.as_deref(), Some("/data/docs"));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(EngineCommand::AddMagnet { .. })
+        ));
+
+        let folder = WatchFolder {
+            move_aside: true,
+            ..folder
+        };
+        let torrent = root.join("build.torrent");
+        std::fs::write(&torrent, b"d4:infod4:name3:appee").unwrap();
+        assert_eq!(process(&torrent, &folder, &sender).unwrap(), Some(1));
+        assert!(root.join("added/build.torrent").is_file());
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(EngineCommand::AddTorrent { .. })
+        ));
+
+        let empty = root.join("empty.magnet");
+        std::fs::write(&empty, b"# nothing yet\n").unwrap();
+        assert!(process(&empty, &folder, &sender).is_err());
+        assert!(root.join("empty.magnet.failed").is_file());
+
+        let ignored = [".partial.torrent", "readme.md", "nightly.txt.added"];
+        for name in ignored {
+            std::fs::write(root.join(name), b"x").unwrap();
+            assert_eq!(process(&root.join(name), &folder, &sender).unwrap(), None);
+        }
+        assert!(receiver.is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+//! gosh-fetchd - Gosh-Fetch background daemon
+//!
+//! Owns the `DownloadService` so downloads keep running without a window,
+//! and serves the JSON-lines control protocol from `gosh_fetch_core::ipc`.
+//! With `--enable-rpc` it also speaks aria2's JSON-RPC (see `rpc`).
+
+mod rpc;
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use gosh_fetch_core::{
+    dbus, feeds, init_database, ipc, record_ui_message, restore_incomplete_downloads, watch,
+    DownloadService, EngineCommand, Result, Settings, SettingsDb, UiMessage,
+};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "gosh-fetchd",
+    version,
+    about = "Gosh-Fetch background download daemon"
+)]
+struct Cli {
+    /// Control socket path (defaults to $XDG_RUNTIME_DIR/gosh-fetch/gosh-fetchd.sock); its
+    /// directory must be private to the user
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    /// Enable the aria2-compatible JSON-RPC/WebSocket interface
+    #[arg(long)]
+    enable_rpc: bool,
+
+    /// Port for the RPC interface
+    #[arg(long, default_value_t = 6800)]
+    rpc_listen_port: u16,
+
+    /// Listen on all interfaces instead of localhost only
+    #[arg(long)]
+    rpc_listen_all: bool,
+
+    /// Require this secret as the `token:` parameter on every RPC call
+    #[arg(long)]
+    rpc_secret: Option<String>,
+
+    /// Add `Access-Control-Allow-Origin: *` to RPC responses
+    #[arg(long)]
+    rpc_allow_origin_all: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let db = init_database()?;
+    let settings = SettingsDb::load(&db).unwrap_or_else(|e| {
+        log::warn!("Failed to load settings, using defaults: {}", e);
+        Settings::default()
+    });
+
+    let socket = cli.socket.unwrap_or_else(ipc::socket_path);
+    let listener = ipc::bind(&socket)?;
+
+    let (ui_sender, ui_receiver) = async_channel::bounded::<UiMessage>(200);
+    let (cmd_sender, cmd_receiver) = async_channel::bounded::<EngineCommand>(200);
+
+    let service = DownloadService::new_async(&settings, db.clone()).await?;
+    let adapter = service.adapter();
+    service.spawn(ui_sender, cmd_receiver);
+
+    let bus = dbus::spawn(cmd_sender.clone());
+    feeds::spawn(db.clone(), cmd_sender.clone());
+    watch::spawn(db.clone(), cmd_sender.clone());
+
+    // Persist every message, then fan it out to connected clients
+    let (events, _) = broadcast::channel::<UiMessage>(256);
+    let forward_events = events.clone();
+    let forward_db = db.clone();
+    let mut forwarder = tokio::spawn(async move {
+        while let Ok(msg) = ui_receiver.recv().await {
+            record_ui_message(&forward_db, &msg);
+            bus.emit(&msg);
+            let _ = forward_events.send(msg);
+        }
+    });
+
+    if cli.enable_rpc {
+        if cli.rpc_listen_all && cli.rpc_secret.is_none() {
+            log::warn!("RPC is listening on all interfaces without --rpc-secret");
+        }
+        let config = rpc::RpcConfig {
+            listen_port: cli.rpc_listen_port,
+            listen_all: cli.rpc_listen_all,
+            secret: cli.rpc_secret.clone(),
+            allow_origin_all: cli.rpc_allow_origin_all,
+        };
+        let rpc_db = db.clone();
+        let rpc_events = events.clone();
+        tokio::spawn(async move {
+            if let Err(e) = rpc::serve(config, adapter, rpc_db, rpc_events).await {
+                log::error!("aria2 RPC server failed: {}", e);
+            }
+        });
+    }
+
+    let restore_db = db.clone();
+    let restore_sender = cmd_sender.clone();
+    tokio::task::spawn_blocking(move || {
+        restore_incomplete_downloads(&restore_db, &restore_sender);
+    });
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    log::info!("Listening on {}", socket.display());
+
+    tokio::select! {
+        result = ipc::serve(listener, cmd_sender.clone(), events) => result?,
+        _ = &mut forwarder => log::info!("Download service stopped"),
+        _ = tokio::signal::ctrl_c() => stop_service(&cmd_sender, &mut forwarder).await,
+        _ = terminate.recv() => stop_service(&cmd_sender, &mut forwarder).await,
+    }
+
+    let _ = std::fs::remove_file(&socket);
+    Ok(())
+}
+
+/// Ask the service to shut the engine down, then wait until it has and the
+/// messages it sent on the way out were recorded
+async fn stop_service(
+    cmd_sender: &async_channel::Sender<EngineCommand>,
+    forwarder: &mut JoinHandle<()>,
+) {
+    let _ = cmd_sender.send(EngineCommand::Shutdown).await;
+    let _ = forwarder.await;
+}
@@ -0,0 +1,1188 @@
+//! aria2-compatible JSON-RPC interface
+//!
+//! Serves the subset of aria2's RPC used by AriaNg, browser extensions and
+//! scripts on `/jsonrpc`, over HTTP POST and WebSocket. Calls map onto
+//! `EngineAdapter`; `aria2.onDownload*` notifications are derived from the
+//! daemon's `UiMessage` stream and pushed to WebSocket clients.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use base64::Engine as _;
+use gosh_fetch_core::duplicate::{self, Verdict};
+use gosh_fetch_core::{
+    record_ui_message, remove_download, Database, Download, DownloadOptions, DownloadState,
+    DownloadType, DownloadsDb, EngineAdapter, EngineCommand, ExistingDownload, Result,
+    TorrentFileInfo, UiMessage,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::sync::broadcast;
+
+/// aria2's generic error code, used for every failure it reports
+const ARIA2_ERROR: i64 = 1;
+
+/// RPC server options, named after aria2's `--rpc-*` flags
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    pub listen_port: u16,
+    pub listen_all: bool,
+    pub secret: Option<String>,
+    pub allow_origin_all: bool,
+}
+
+#[derive(Clone)]
+struct RpcState {
+    adapter: EngineAdapter,
+    db: Database,
+    events: broadcast::Sender<UiMessage>,
+    notifications: broadcast::Sender<(&'static str, String)>,
+    secret: Option<Arc<str>>,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            code: ARIA2_ERROR,
+            message: message.into(),
+        }
+    }
+}
+
+impl<E: std::fmt::Display> From<E> for RpcError {
+    fn from(err: E) -> Self {
+        Self::new(err.to_string())
+    }
+}
+
+type RpcResult = std::result::Result<Value, RpcError>;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+/// Run the RPC server until it fails
+pub async fn serve(
+    config: RpcConfig,
+    adapter: EngineAdapter,
+    db: Database,
+    events: broadcast::Sender<UiMessage>,
+) -> Result<()> {
+    let (notifications, _) = broadcast::channel(256);
+
+    // Translate service messages into aria2 notifications
+    let mut event_rx = events.subscribe();
+    let notify_tx = notifications.clone();
+    tokio::spawn(async move {
+        loop {
+            match event_rx.recv().await {
+                Ok(msg) => {
+                    if let Some(notification) = notification_for(&msg) {
+                        let _ = notify_tx.send(notification);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    let state = RpcState {
+        adapter,
+        db,
+        events,
+        notifications,
+        secret: config.secret.filter(|s| !s.is_empty()).map(Arc::from),
+    };
+
+    let mut app = Router::new()
+        .route(
+            "/jsonrpc",
+            get(ws_handler).post(http_handler).options(preflight),
+        )
+        .with_state(state);
+    if config.allow_origin_all {
+        app = app.layer(axum::middleware::map_response(allow_any_origin));
+    }
+
+    let ip = if config.listen_all {
+        [0, 0, 0, 0]
+    } else {
+        [127, 0, 0, 1]
+    };
+    let addr = SocketAddr::from((ip, config.listen_port));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    log::info!("aria2 RPC listening on {}", addr);
+
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn allow_any_origin(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        HeaderValue::from_static("*"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("Content-Type"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST, OPTIONS"),
+    );
+    response
+}
+
+async fn preflight() -> StatusCode {
+    StatusCode::NO_CONTENT
+}
+
+async fn http_handler(State(state): State<RpcState>, body: String) -> Json<Value> {
+    Json(handle_body(&state, &body).await)
+}
+
+async fn ws_handler(State(state): State<RpcState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(state, socket))
+        .into_response()
+}
+
+async fn handle_socket(state: RpcState, mut socket: WebSocket) {
+    let mut notifications = state.notifications.subscribe();
+
+    loop {
+        tokio::select! {
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    let response = handle_body(&state, &text).await;
+                    if socket.send(Message::Text(response.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+
+            notification = notifications.recv() => match notification {
+                Ok((method, gid)) => {
+                    let msg = json!({
+                        "jsonrpc": "2.0",
+                        "method": method,
+                        "params": [{ "gid": gid }],
+                    });
+                    if socket.send(Message::Text(msg.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+/// Handle a single request or a batch
+async fn handle_body(state: &RpcState, body: &str) -> Value {
+    match serde_json::from_str::<Value>(body) {
+        Ok(Value::Array(requests)) => {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.push(handle_request(state, request).await);
+            }
+            Value::Array(responses)
+        }
+        Ok(request) => handle_request(state, request).await,
+        Err(_) => error_response(Value::Null, -32700, "Parse error"),
+    }
+}
+
+async fn handle_request(state: &RpcState, request: Value) -> Value {
+    let request: Request = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(_) => return error_response(Value::Null, -32600, "Invalid Request"),
+    };
+
+    match dispatch(state, &request.method, request.params).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+        Err(e) => error_response(request.id, e.code, &e.message),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+async fn dispatch(state: &RpcState, method: &str, mut params: Vec<Value>) -> RpcResult {
+    match method {
+        "system.listMethods" => Ok(json!(METHODS)),
+        "system.listNotifications" => Ok(json!(NOTIFICATIONS)),
+        "system.multicall" => {
+            let calls = params
+                .first()
+                .and_then(Value::as_array)
+                .cloned()
+                .ok_or_else(|| RpcError::new("system.multicall expects an array of calls"))?;
+
+            let mut results = Vec::with_capacity(calls.len());
+            for call in calls {
+                let method = call["methodName"].as_str().unwrap_or_default().to_string();
+                let mut params = call["params"].as_array().cloned().unwrap_or_default();
+                let result = match state.authorize(&mut params) {
+                    Ok(()) => call_method(state, &method, params).await,
+                    Err(e) => Err(e),
+                };
+                results.push(match result {
+                    Ok(value) => json!([value]),
+                    Err(e) => json!({ "code": e.code, "message": e.message }),
+                });
+            }
+            Ok(Value::Array(results))
+        }
+        _ => {
+            state.authorize(&mut params)?;
+            call_method(state, method, params).await
+        }
+    }
+}
+
+const METHODS: &[&str] = &[
+    "aria2.addUri",
+    "aria2.addTorrent",
+    "aria2.addMetalink",
+    "aria2.remove",
+    "aria2.forceRemove",
+    "aria2.pause",
+    "aria2.forcePause",
+    "aria2.pauseAll",
+    "aria2.forcePauseAll",
+    "aria2.unpause",
+    "aria2.unpauseAll",
+    "aria2.tellStatus",
+    "aria2.getUris",
+    "aria2.getFiles",
+    "aria2.getPeers",
+    "aria2.tellActive",
+    "aria2.tellWaiting",
+    "aria2.tellStopped",
+    "aria2.getOption",
+    "aria2.changeOption",
+    "aria2.getGlobalOption",
+    "aria2.changeGlobalOption",
+    "aria2.getGlobalStat",
+    "aria2.removeDownloadResult",
+    "aria2.purgeDownloadResult",
+    "aria2.getVersion",
+    "aria2.saveSession",
+    "system.multicall",
+    "system.listMethods",
+    "system.listNotifications",
+];
+
+const NOTIFICATIONS: &[&str] = &[
+    "aria2.onDownloadStart",
+    "aria2.onDownloadPause",
+    "aria2.onDownloadStop",
+    "aria2.onDownloadComplete",
+    "aria2.onDownloadError",
+    "aria2.onBtDownloadComplete",
+];
+
+impl RpcState {
+    /// Check and strip the leading `token:<secret>` parameter
+    fn authorize(&self, params: &mut Vec<Value>) -> std::result::Result<(), RpcError> {
+        let token = params
+            .first()
+            .and_then(Value::as_str)
+            .and_then(|first| first.strip_prefix("token:"))
+            .map(str::to_string);
+        if token.is_some() {
+            params.remove(0);
+        }
+
+        match &self.secret {
+            Some(secret) if !token.is_some_and(|token| secret_matches(secret, &token)) => {
+                Err(RpcError::new("Unauthorized"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a message and fan it out like the download service would
+    fn publish(&self, msg: UiMessage) {
+        publish(&self.db, &self.events, msg);
+    }
+
+    /// Record and announce a download's state after a call changed it
+    fn publish_updated(&self, download: Download) {
+        self.publish(UiMessage::DownloadUpdated(download.gid.clone(), download));
+    }
+
+    fn publish_added(&self, gid: &str, options: &DownloadOptions) {
+        if let Err(e) = DownloadsDb::save_options(&self.db, gid, options) {
+            log::error!("Failed to save download options: {}", e);
+        }
+        if let Some(download) = self.adapter.get_status(gid) {
+            self.publish(UiMessage::DownloadAdded(download));
+        }
+    }
+
+    /// Apply an add call's `on_duplicate` action through the same check as
+    /// the download service; a duplicate that is only reported is an error
+    async fn may_add(&self, cmd: &EngineCommand) -> std::result::Result<(), RpcError> {
+        match duplicate::check(&self.adapter, &self.db, cmd).await? {
+            Verdict::Add => Ok(()),
+            Verdict::Report(existing) => Err(duplicate_error(&existing)),
+            Verdict::Restart(existing) => {
+                self.publish(UiMessage::DownloadRemoved(existing.gid));
+                Ok(())
+            }
+        }
+    }
+
+    fn find(&self, gid: &str) -> std::result::Result<Download, RpcError> {
+        self.adapter
+            .get_status(gid)
+            .ok_or_else(|| RpcError::new(format!("GID {} is not found", gid)))
+    }
+
+    fn status_json(&self, download: &Download, keys: Option<&[String]>) -> Value {
+        let files = self.adapter.get_torrent_files(&download.gid);
+        filter_keys(aria2_status(download, files), keys)
+    }
+}
+
+async fn call_method(state: &RpcState, method: &str, params: Vec<Value>) -> RpcResult {
+    let adapter = &state.adapter;
+
+    match method {
+        "aria2.addUri" => {
+            let cmd = uri_command(&params)?;
+            state.may_add(&cmd).await?;
+            let (gid, options) = match cmd {
+                EngineCommand::AddMagnet { uri, options } => {
+                    (adapter.add_magnet(&uri, options.clone()).await?, options)
+                }
+                EngineCommand::AddDownload { url, options } => {
+                    (adapter.add_download(url, options.clone()).await?, options)
+                }
+                _ => return Err(RpcError::new("No URI to download")),
+            };
+            state.publish_added(&gid, &options.unwrap_or_default());
+            Ok(json!(gid))
+        }
+
+        "aria2.addTorrent" => {
+            let encoded = param_str(&params, 0)?;
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .map_err(|e| RpcError::new(format!("Invalid torrent data: {}", e)))?;
+            let options = options_from_json(params.get(2));
+            let cmd = EngineCommand::AddTorrent {
+                data: data.clone(),
+                options: Some(options.clone()),
+            };
+            state.may_add(&cmd).await?;
+
+            let gid = adapter.add_torrent(&data, Some(options.clone())).await?;
+            DownloadsDb::save_torrent(&state.db, &gid, &data)?;
+            state.publish_added(&gid, &options);
+            Ok(json!(gid))
+        }
+
+        "aria2.addMetalink" => {
+            let encoded = param_str(&params, 0)?;
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .map_err(|e| RpcError::new(format!("Invalid metalink data: {}", e)))?;
+            let options = options_from_json(params.get(1));
+
+            let added = adapter.add_metalink(&data, Some(options)).await?;
+            let gids: Vec<&str> = added.iter().map(|(gid, _)| gid.as_str()).collect();
+            for (gid, options) in &added {
+                state.publish_added(gid, options);
+            }
+            Ok(json!(gids))
+        }
+
+        "aria2.remove" | "aria2.forceRemove" => {
+            // A scheduled download is dropped, as the service does
+            let gid = param_str(&params, 0)?;
+            remove_download(adapter, &state.db, &gid, false).await?;
+            state.publish(UiMessage::DownloadRemoved(gid.clone()));
+            Ok(json!(gid))
+        }
+
+        "aria2.pause" | "aria2.forcePause" => {
+            let gid = param_str(&params, 0)?;
+            adapter.pause(&gid).await?;
+            if let Some(download) = adapter.get_status(&gid) {
+                state.publish_updated(download);
+            }
+            let _ = state
+                .notifications
+                .send(("aria2.onDownloadPause", gid.clone()));
+            Ok(json!(gid))
+        }
+
+        "aria2.pauseAll" | "aria2.forcePauseAll" => {
+            adapter.pause_all().await?;
+            for download in adapter.get_all() {
+                state.publish_updated(download);
+            }
+            Ok(json!("OK"))
+        }
+
+        "aria2.unpause" => {
+            let gid = param_str(&params, 0)?;
+            adapter.resume(&gid).await?;
+            if let Some(download) = adapter.get_status(&gid) {
+                state.publish_updated(download);
+            }
+            let _ = state
+                .notifications
+                .send(("aria2.onDownloadStart", gid.clone()));
+            Ok(json!(gid))
+        }
+
+        "aria2.unpauseAll" => {
+            adapter.resume_all().await?;
+            for download in adapter.get_all() {
+                state.publish_updated(download);
+            }
+            Ok(json!("OK"))
+        }
+
+        "aria2.tellStatus" => {
+            let gid = param_str(&params, 0)?;
+            let download = state.find(&gid)?;
+            let keys = param_keys(&params, 1);
+            Ok(state.status_json(&download, keys.as_deref()))
+        }
+
+        "aria2.getUris" => {
+            let download = state.find(&param_str(&params, 0)?)?;
+            Ok(json!(uris_json(&download)))
+        }
+
+        "aria2.getFiles" => {
+            let download = state.find(&param_str(&params, 0)?)?;
+            let files = adapter.get_torrent_files(&download.gid);
+            Ok(files_json(&download, files))
+        }
+
+        "aria2.getPeers" => {
+            let gid = param_str(&params, 0)?;
+            let peers = adapter.get_peers(&gid).unwrap_or_default();
+            Ok(Value::Array(
+                peers
+                    .into_iter()
+                    .map(|p| {
+                        json!({
+                            "ip": p.ip,
+                            "port": p.port.to_string(),
+                            "downloadSpeed": p.download_speed.to_string(),
+                            "uploadSpeed": p.upload_speed.to_string(),
+                        })
+                    })
+                    .collect(),
+            ))
+        }
+
+        "aria2.tellActive" => {
+            let keys = param_keys(&params, 0);
+            Ok(Value::Array(
+                adapter
+                    .get_active()
+                    .iter()
+                    .map(|d| state.status_json(d, keys.as_deref()))
+                    .collect(),
+            ))
+        }
+
+        "aria2.tellWaiting" | "aria2.tellStopped" => {
+            let offset = params.first().and_then(Value::as_i64).unwrap_or(0);
+            let num = params.get(1).and_then(Value::as_u64).unwrap_or(1000) as usize;
+            let keys = param_keys(&params, 2);
+
+            let waiting = method == "aria2.tellWaiting";
+            let mut downloads: Vec<Download> = adapter
+                .get_all()
+                .into_iter()
+                .filter(|d| {
+                    let is_waiting =
+                        matches!(d.status, DownloadState::Waiting | DownloadState::Paused);
+                    let is_active = d.status == DownloadState::Active;
+                    if waiting {
+                        is_waiting
+                    } else {
+                        !is_waiting && !is_active
+                    }
+                })
+                .collect();
+
+            // A negative offset counts from the end, walking backwards
+            let start = if offset < 0 {
+                downloads.reverse();
+                (-offset - 1) as usize
+            } else {
+                offset as usize
+            };
+
+            Ok(Value::Array(
+                downloads
+                    .iter()
+                    .skip(start)
+                    .take(num)
+                    .map(|d| state.status_json(d, keys.as_deref()))
+                    .collect(),
+            ))
+        }
+
+        "aria2.getOption" => {
+            let download = state.find(&param_str(&params, 0)?)?;
+            Ok(json!({
+                "dir": download.save_path,
+                "out": download.name,
+            }))
+        }
+
+        "aria2.changeOption" => {
+            // The engine fixes a download's other options when it is added,
+            // and adding it again would change its GID under clients that
+            // keep using the old one, so only the speed limits can change.
+            let download = state.find(&param_str(&params, 0)?)?;
+            let stored = DownloadsDb::get_options(&state.db, &download.gid)?.unwrap_or_default();
+            if let Some(options) = changed_options(&download, stored, params.get(1))? {
+                adapter.set_download_limits(&download.gid, &options)?;
+                DownloadsDb::save_options(&state.db, &download.gid, &options)?;
+            }
+            Ok(json!("OK"))
+        }
+
+        "aria2.getGlobalOption" => {
+            let config = adapter.get_config();
+            let mut options = Map::new();
+            options.insert("dir".into(), json!(config.download_dir.to_string_lossy()));
+            options.insert(
+                "max-concurrent-downloads".into(),
+                json!(config.max_concurrent_downloads.to_string()),
+            );
+            options.insert(
+                "max-connection-per-server".into(),
+                json!(config.max_connections_per_download.to_string()),
+            );
+            options.insert(
+                "max-overall-download-limit".into(),
+                json!(config.global_download_limit.unwrap_or(0).to_string()),
+            );
+            options.insert(
+                "max-overall-upload-limit".into(),
+                json!(config.global_upload_limit.unwrap_or(0).to_string()),
+            );
+            options.insert("seed-ratio".into(), json!(config.seed_ratio.to_string()));
+            options.insert("user-agent".into(), json!(config.user_agent));
+            Ok(Value::Object(options))
+        }
+
+        "aria2.changeGlobalOption" => {
+            let options = params.first().cloned().unwrap_or(Value::Null);
+            let mut config = adapter.get_config();
+
+            if let Some(limit) = option_str(&options, "max-overall-download-limit") {
+                config.global_download_limit = parse_limit(&limit);
+            }
+            if let Some(limit) = option_str(&options, "max-overall-upload-limit") {
+                config.global_upload_limit = parse_limit(&limit);
+            }
+            if let Some(n) =
+                option_str(&options, "max-concurrent-downloads").and_then(|s| s.parse().ok())
+            {
+                config.max_concurrent_downloads = n;
+            }
+            if let Some(n) =
+                option_str(&options, "max-connection-per-server").and_then(|s| s.parse().ok())
+            {
+                config.max_connections_per_download = n;
+            }
+            if let Some(ratio) = option_str(&options, "seed-ratio").and_then(|s| s.parse().ok()) {
+                config.seed_ratio = ratio;
+            }
+            if let Some(ua) = option_str(&options, "user-agent") {
+                config.user_agent = ua;
+            }
+
+            adapter.update_config(config)?;
+            Ok(json!("OK"))
+        }
+
+        "aria2.getGlobalStat" => {
+            let stats = adapter.get_global_stats();
+            Ok(json!({
+                "downloadSpeed": stats.download_speed.to_string(),
+                "uploadSpeed": stats.upload_speed.to_string(),
+                "numActive": stats.num_active.to_string(),
+                "numWaiting": stats.num_waiting.to_string(),
+                "numStopped": stats.num_stopped.to_string(),
+                "numStoppedTotal": stats.num_stopped.to_string(),
+            }))
+        }
+
+        "aria2.removeDownloadResult" => {
+            let download = state.find(&param_str(&params, 0)?)?;
+            if !is_stopped(&download) {
+                return Err(RpcError::new(format!(
+                    "Could not remove download result of GID#{} because it is {}",
+                    download.gid,
+                    aria2_state(download.status)
+                )));
+            }
+            adapter.remove(&download.gid, false).await?;
+            state.publish(UiMessage::DownloadRemoved(download.gid));
+            Ok(json!("OK"))
+        }
+
+        "aria2.purgeDownloadResult" => {
+            for download in adapter.get_all() {
+                if is_stopped(&download) && adapter.remove(&download.gid, false).await.is_ok() {
+                    state.publish(UiMessage::DownloadRemoved(download.gid));
+                }
+            }
+            Ok(json!("OK"))
+        }
+
+        "aria2.getVersion" => Ok(json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "enabledFeatures": ["BitTorrent", "HTTPS"],
+        })),
+
+        // The engine persists its own session
+        "aria2.saveSession" => Ok(json!("OK")),
+
+        _ => Err(RpcError {
+            code: -32601,
+            message: format!("Method not found: {}", method),
+        }),
+    }
+}
+
+/// Compare a token with the secret in time that does not depend on where
+/// they differ, so the secret cannot be guessed byte by byte
+fn secret_matches(secret: &str, token: &str) -> bool {
+    let (secret, token) = (secret.as_bytes(), token.as_bytes());
+    let differences = secret
+        .iter()
+        .zip(token)
+        .fold(0, |acc, (a, b)| acc | (a ^ b));
+    differences == 0 && secret.len() == token.len()
+}
+
+/// The add command for an `aria2.addUri` call: a magnet link, or a URL
+/// with the other URIs as its mirrors
+fn uri_command(params: &[Value]) -> std::result::Result<EngineCommand, RpcError> {
+    let uris: Vec<String> = params
+        .first()
+        .and_then(Value::as_array)
+        .map(|uris| {
+            uris.iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    let Some((uri, mirrors)) = uris.split_first() else {
+        return Err(RpcError::new("No URI to download"));
+    };
+    let mut options = options_from_json(params.get(1));
+
+    if uri.starts_with("magnet:") {
+        return Ok(EngineCommand::AddMagnet {
+            uri: uri.clone(),
+            options: Some(options),
+        });
+    }
+    if !mirrors.is_empty() {
+        options
+            .mirror_urls
+            .get_or_insert_with(Vec::new)
+            .extend(mirrors.iter().cloned());
+    }
+    Ok(EngineCommand::AddDownload {
+        url: uri.clone(),
+        options: Some(options),
+    })
+}
+
+fn duplicate_error(existing: &ExistingDownload) -> RpcError {
+    RpcError::new(format!(
+        "Already added as {} ({})",
+        existing.gid, existing.state
+    ))
+}
+
+fn param_str(params: &[Value], index: usize) -> std::result::Result<String, RpcError> {
+    params
+        .get(index)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| RpcError::new(format!("Missing parameter {}", index + 1)))
+}
+
+fn param_keys(params: &[Value], index: usize) -> Option<Vec<String>> {
+    params.get(index).and_then(Value::as_array).map(|keys| {
+        keys.iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+/// Read an aria2 option, which clients send as strings but sometimes as numbers
+fn option_str(options: &Value, key: &str) -> Option<String> {
+    match options.get(key)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Parse an aria2 speed limit ("0" means unlimited)
+fn parse_limit(value: &str) -> Option<u64> {
+    let value = value.trim().to_uppercase();
+    let (digits, multiplier) = match value.chars().last() {
+        Some('K') => (&value[..value.len() - 1], 1024),
+        Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+        _ => (value.as_str(), 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .map(|n| n * multiplier)
+        .filter(|n| *n > 0)
+}
+
+/// Convert aria2 input options to `DownloadOptions`
+///
+/// The field names already follow aria2, so this is mostly a rename from
+/// dashes to underscores.
+fn options_from_json(options: Option<&Value>) -> DownloadOptions {
+    let Some(options) = options.filter(|o| o.is_object()) else {
+        return DownloadOptions::default();
+    };
+
+    let headers = match options.get("header") {
+        Some(Value::Array(headers)) => Some(
+            headers
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+        ),
+        Some(Value::String(header)) => Some(vec![header.clone()]),
+        _ => None,
+    };
+
+    let (checksum_type, checksum_value) = match option_str(options, "checksum") {
+        Some(checksum) => match checksum.split_once('=') {
+            Some((t, v)) => (Some(t.replace('-', "")), Some(v.to_string())),
+            None => (None, None),
+        },
+        None => (None, None),
+    };
+
+    DownloadOptions {
+        dir: option_str(options, "dir"),
+        out: option_str(options, "out"),
+        max_connection_per_server: option_str(options, "max-connection-per-server"),
+        user_agent: option_str(options, "user-agent"),
+        referer: option_str(options, "referer"),
+        header: headers,
+        select_file: option_str(options, "select-file"),
+        seed_ratio: option_str(options, "seed-ratio"),
+        max_download_limit: option_str(options, "max-download-limit"),
+        max_upload_limit: option_str(options, "max-upload-limit"),
+        checksum_type,
+        checksum_value,
+        sequential: option_str(options, "bt-enable-sequential-download").map(|s| s == "true"),
+        proxy: option_str(options, "all-proxy"),
+        ..Default::default()
+    }
+}
+
+/// Options `aria2.changeOption` can apply to a running download
+const LIVE_OPTIONS: [&str; 2] = ["max-download-limit", "max-upload-limit"];
+
+/// Apply the `changes` of an `aria2.changeOption` call to a download's
+/// stored options, or `None` when the call changes nothing
+///
+/// Any option other than the speed limits refuses the whole call, naming
+/// the options that cannot change while the download runs.
+fn changed_options(
+    download: &Download,
+    mut options: DownloadOptions,
+    changes: Option<&Value>,
+) -> std::result::Result<Option<DownloadOptions>, RpcError> {
+    let Some(changes) = changes.and_then(Value::as_object).filter(|c| !c.is_empty()) else {
+        return Ok(None);
+    };
+    let refused: Vec<&str> = changes
+        .keys()
+        .map(String::as_str)
+        .filter(|name| !LIVE_OPTIONS.contains(name))
+        .collect();
+    if !refused.is_empty() {
+        return Err(RpcError::new(format!(
+            "Cannot change {} of {} after it was added",
+            refused.join(", "),
+            download.gid
+        )));
+    }
+    let changes = Value::Object(changes.clone());
+    if let Some(limit) = option_str(&changes, "max-download-limit") {
+        options.max_download_limit = Some(limit);
+    }
+    if let Some(limit) = option_str(&changes, "max-upload-limit") {
+        options.max_upload_limit = Some(limit);
+    }
+    Ok(Some(options))
+}
+
+/// Record a message in the downloads table and pass it on to attached
+/// frontends and D-Bus subscribers, as the service does for its own
+fn publish(db: &Database, events: &broadcast::Sender<UiMessage>, msg: UiMessage) {
+    record_ui_message(db, &msg);
+    let _ = events.send(msg);
+}
+
+fn notification_for(msg: &UiMessage) -> Option<(&'static str, String)> {
+    match msg {
+        UiMessage::DownloadAdded(download) => Some(("aria2.onDownloadStart", download.gid.clone())),
+        UiMessage::DownloadCompleted(download) => {
+            let method = match download.download_type {
+                DownloadType::Torrent | DownloadType::Magnet => "aria2.onBtDownloadComplete",
+                _ => "aria2.onDownloadComplete",
+            };
+            Some((method, download.gid.clone()))
+        }
+        UiMessage::DownloadFailed(gid, _) => Some(("aria2.onDownloadError", gid.clone())),
+        UiMessage::DownloadRemoved(gid) => Some(("aria2.onDownloadStop", gid.clone())),
+        _ => None,
+    }
+}
+
+/// Whether a download has a result to remove: it is neither running nor
+/// waiting to
+fn is_stopped(download: &Download) -> bool {
+    matches!(
+        download.status,
+        DownloadState::Complete | DownloadState::Error | DownloadState::Removed
+    )
+}
+
+fn aria2_state(state: DownloadState) -> &'static str {
+    match state {
+        DownloadState::Active => "active",
+        DownloadState::Waiting | DownloadState::Scheduled => "waiting",
+        DownloadState::Paused => "paused",
+        DownloadState::Complete => "complete",
+        DownloadState::Error => "error",
+        DownloadState::Removed => "removed",
+    }
+}
+
+fn uris_json(download: &Download) -> Vec<Value> {
+    download
+        .url
+        .iter()
+        .map(|uri| json!({ "uri": uri, "status": "used" }))
+        .collect()
+}
+
+fn files_json(download: &Download, files: Option<Vec<TorrentFileInfo>>) -> Value {
+    let dir = std::path::Path::new(&download.save_path);
+    match files {
+        Some(files) => Value::Array(
+            files
+                .iter()
+                .enumerate()
+                .map(|(i, f)| {
+                    json!({
+                        "index": (i + 1).to_string(),
+                        "path": dir.join(&f.path).to_string_lossy(),
+                        "length": f.size.to_string(),
+                        "completedLength": f.completed.to_string(),
+                        "selected": f.selected.to_string(),
+                        "uris": [],
+                    })
+                })
+                .collect(),
+        ),
+        None => json!([{
+            "index": "1",
+            "path": dir.join(&download.name).to_string_lossy(),
+            "length": download.total_size.to_string(),
+            "completedLength": download.completed_size.to_string(),
+            "selected": "true",
+            "uris": uris_json(download),
+        }]),
+    }
+}
+
+/// Build an aria2 `tellStatus` structure
+fn aria2_status(download: &Download, files: Option<Vec<TorrentFileInfo>>) -> Value {
+    let mut status = json!({
+        "gid": download.gid,
+        "status": aria2_state(download.status),
+        "totalLength": download.total_size.to_string(),
+        "completedLength": download.completed_size.to_string(),
+        "uploadLength": "0",
+        "downloadSpeed": download.download_speed.to_string(),
+        "uploadSpeed": download.upload_speed.to_string(),
+        "connections": download.connections.to_string(),
+        "numSeeders": download.seeders.to_string(),
+        "dir": download.save_path,
+        "errorCode": if download.status == DownloadState::Error { "1" } else { "0" },
+        "files": files_json(download, files),
+    });
+
+    if let Some(message) = &download.error_message {
+        status["errorMessage"] = json!(message);
+    }
+    if let Some(info_hash) = &download.info_hash {
+        status["infoHash"] = json!(info_hash);
+    }
+    if matches!(
+        download.download_type,
+        DownloadType::Torrent | DownloadType::Magnet
+    ) {
+        status["bittorrent"] = json!({ "info": { "name": download.name } });
+    }
+    status
+}
+
+fn filter_keys(value: Value, keys: Option<&[String]>) -> Value {
+    match (value, keys) {
+        (Value::Object(map), Some(keys)) if !keys.is_empty() => Value::Object(
+            map.into_iter()
+                .filter(|(k, _)| keys.iter().any(|key| key == k))
+                .collect(),
+        ),
+        (value, _) => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_from_json() {
+        let options = options_from_json(Some(&json!({
+            "dir": "/tmp/isos",
+            "out": "debian.iso",
+            "max-connection-per-server": 8,
+            "header": ["X-A: 1", "X-B: 2"],
+            "checksum": "sha-256=abcd",
+        })));
+        assert_eq!(options.dir.as_deref(), Some("/tmp/isos"));
+        assert_eq!(options.out.as_deref(), Some("debian.iso"));
+        assert_eq!(options.max_connection_per_server.as_deref(), Some("8"));
+        assert_eq!(options.header.map(|h| h.len()), Some(2));
+        assert_eq!(options.checksum_type.as_deref(), Some("sha256"));
+        assert_eq!(options.checksum_value.as_deref(), Some("abcd"));
+    }
+
+    #[test]
+    fn test_aria2_status_keys() {
+        let download = Download {
+            id: 0,
+            gid: "abc".to_string(),
+            name: "file.bin".to_string(),
+            url: Some("https://example.com/file.bin".to_string()),
+            magnet_uri: None,
+            info_hash: None,
+            download_type: DownloadType::Http,
+            status: DownloadState::Paused,
+            total_size: 100,
+            completed_size: 40,
+            download_speed: 0,
+            upload_speed: 0,
+            save_path: "/tmp".to_string(),
+            created_at: String::new(),
+            completed_at: None,
+            error_message: None,
+            connections: 0,
+            seeders: 0,
+            selected_files: None,
+            category: None,
+            scheduled_start: None,
+            checksum: None,
+            conflict: None,
+        };
+        let status = filter_keys(
+            aria2_status(&download, None),
+            Some(&[
+                "gid".to_string(),
+                "status".to_string(),
+                "totalLength".to_string(),
+            ]),
+        );
+        assert_eq!(
+            status,
+            json!({ "gid": "abc", "status": "paused", "totalLength": "100" })
+        );
+    }
+
+    #[test]
+    fn test_add_uri_is_checked_for_duplicates() {
+        let recorded = Download {
+            gid: "abc".to_string(),
+            name: "distro.iso".to_string(),
+            url: Some("https://example.com/isos/distro.iso".to_string()),
+            save_path: "/data".to_string(),
+            status: DownloadState::Complete,
+            ..Default::default()
+        };
+        let dir = std::path::Path::new("/data");
+        let verdict_for = |params: Value| {
+            let cmd = uri_command(params.as_array().unwrap()).unwrap();
+            let existing = duplicate::Candidate::for_command(&cmd, dir)
+                .and_then(|candidate| duplicate::find(&candidate, std::slice::from_ref(&recorded)));
+            duplicate::verdict(&cmd, existing)
+        };
+
+        let params = json!([[
+            "https://example.com/isos/distro.iso",
+            "https://mirror.example.net/distro.iso"
+        ]]);
+        let Verdict::Report(existing) = verdict_for(params) else {
+            panic!("a URL already downloaded must be reported");
+        };
+        assert_eq!(
+            duplicate_error(&existing).message,
+            "Already added as abc (complete)"
+        );
+
+        let params = json!([["https://example.com/isos/other.iso"], { "dir": "/data" }]);
+        assert!(matches!(verdict_for(params), Verdict::Add));
+        assert!(uri_command(&[json!([])]).is_err());
+    }
+
+    #[test]
+    fn test_change_option_sets_only_speed_limits() {
+        let download = Download {
+            gid: "abc".to_string(),
+            ..Default::default()
+        };
+        let stored = DownloadOptions {
+            dir: Some("/downloads".to_string()),
+            max_upload_limit: Some("100K".to_string()),
+            ..Default::default()
+        };
+        assert!(changed_options(&download, stored.clone(), None)
+            .unwrap()
+            .is_none());
+        assert!(changed_options(&download, stored.clone(), Some(&json!({})))
+            .unwrap()
+            .is_none());
+
+        let changed = changed_options(
+            &download,
+            stored.clone(),
+            Some(&json!({ "max-download-limit": "1M" })),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(changed.max_download_limit.as_deref(), Some("1M"));
+        assert_eq!(changed.max_upload_limit.as_deref(), Some("100K"));
+        assert_eq!(changed.dir.as_deref(), Some("/downloads"));
+
+        let err = changed_options(
+            &download,
+            stored,
+            Some(&json!({ "max-upload-limit": "0", "dir": "/tmp", "split": "4" })),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.message,
+            "Cannot change dir, split of abc after it was added"
+        );
+    }
+
+    #[test]
+    fn test_only_stopped_downloads_have_results() {
+        let download = |status| Download {
+            status,
+            ..Default::default()
+        };
+        assert!(is_stopped(&download(DownloadState::Complete)));
+        assert!(is_stopped(&download(DownloadState::Error)));
+        assert!(!is_stopped(&download(DownloadState::Active)));
+        assert!(!is_stopped(&download(DownloadState::Paused)));
+        assert!(!is_stopped(&download(DownloadState::Waiting)));
+    }
+
+    #[test]
+    fn test_removed_result_leaves_the_history() {
+        let dir = std::env::temp_dir().join(format!("gosh-fetchd-rpc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = gosh_fetch_core::open_database(&dir.join("gosh-fetch.db")).unwrap();
+        let (events, mut event_rx) = broadcast::channel(16);
+        let download = Download {
+            gid: "abc".to_string(),
+            name: "distro.iso".to_string(),
+            status: DownloadState::Complete,
+            completed_at: Some("2026-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        DownloadsDb::save(&db, &download).unwrap();
+
+        publish(&db, &events, UiMessage::DownloadRemoved("abc".to_string()));
+        assert!(DownloadsDb::get_by_gid(&db, "abc").unwrap().is_none());
+        assert!(DownloadsDb::get_completed(&db, 10).unwrap().is_empty());
+        assert!(matches!(
+            event_rx.try_recv(),
+            Ok(UiMessage::DownloadRemoved(gid)) if gid == "abc"
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_secret_matches() {
+        assert!(secret_matches("s3cret", "s3cret"));
+        assert!(!secret_matches("s3cret", "s3creT"));
+        assert!(!secret_matches("s3cret", "s3cre"));
+        assert!(!secret_matches("s3cret", "s3cret!"));
+        assert!(!secret_matches("s3cret", ""));
+    }
+
+    #[test]
+    fn test_parse_limit() {
+        assert_eq!(parse_limit("0"), None);
+        assert_eq!(parse_limit("512K"), Some(512 * 1024));
+        assert_eq!(parse_limit("2M"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_limit("1000"), Some(1000));
+    }
+}
@@ -0,0 +1,263 @@
+//! gosh-fetch-native-host - browser native-messaging host
+//!
+//! Browsers start this binary when the Gosh-Fetch extension connects and talk
+//! to it over stdio: every message is a 32-bit native-endian length followed
+//! by that many bytes of UTF-8 JSON. Intercepted downloads are forwarded to
+//! the running instance, through the `gosh-fetchd` socket when the daemon is
+//! up and the `io.github.gosh.Fetch` D-Bus service otherwise.
+//!
+//! ```text
+//! → {"type":"download","url":"https://example.com/a.iso","referrer":"https://example.com/",
+//!    "cookies":"session=abc","userAgent":"Mozilla/5.0 ...","filename":"a.iso"}
+//! ← {"ok":true}
+//! ```
+
+use std::io::{self, Read, Write};
+
+use gosh_fetch_core::{dbus, ipc, DownloadOptions, EngineCommand, Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Largest message accepted from the browser
+const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Message sent by the extension
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Request {
+    /// Check that the host is installed
+    Ping,
+    /// Hand a download over to Gosh-Fetch
+    Download(DownloadRequest),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadRequest {
+    url: String,
+    #[serde(default)]
+    referrer: Option<String>,
+    /// Cookie header value for the URL ("a=1; b=2")
+    #[serde(default)]
+    cookies: Option<String>,
+    #[serde(default)]
+    user_agent: Option<String>,
+    /// Filename the browser suggested
+    #[serde(default)]
+    filename: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<&'static str>,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+            version: None,
+        }
+    }
+
+    fn error(message: String) -> Self {
+        Self {
+            ok: false,
+            error: Some(message),
+            version: None,
+        }
+    }
+}
+
+fn main() {
+    // stdout carries the protocol; logs go to stderr, which browsers surface
+    // in their extension consoles
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut output = stdout.lock();
+
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            // The browser closed the port
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("Failed to read message: {}", e);
+                break;
+            }
+        };
+
+        let response = match serde_json::from_slice::<Request>(&message) {
+            Ok(request) => handle(request),
+            Err(e) => Response::error(format!("Invalid message: {}", e)),
+        };
+
+        if let Err(e) = write_message(&mut output, &response) {
+            log::error!("Failed to write response: {}", e);
+            break;
+        }
+    }
+}
+
+fn handle(request: Request) -> Response {
+    match request {
+        Request::Ping => Response {
+            version: Some(env!("CARGO_PKG_VERSION")),
+            ..Response::ok()
+        },
+        Request::Download(download) => match forward(download) {
+            Ok(()) => Response::ok(),
+            Err(e) => Response::error(e.to_string()),
+        },
+    }
+}
+
+/// Read one length-prefixed message, or `None` at end of input
+fn read_message<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let len = u32::from_ne_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes exceeds the limit", len),
+        ));
+    }
+
+    let mut message = vec![0u8; len];
+    reader.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+fn write_message<W: Write, T: Serialize>(writer: &mut W, value: &T) -> io::Result<()> {
+    let message = serde_json::to_vec(value)?;
+    writer.write_all(&(message.len() as u32).to_ne_bytes())?;
+    writer.write_all(&message)?;
+    writer.flush()
+}
+
+/// Build the download options the browser context implies
+fn to_options(request: &DownloadRequest) -> DownloadOptions {
+    let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.trim().is_empty());
+
+    DownloadOptions {
+        out: request.filename.as_deref().and_then(file_name),
+        referer: non_empty(&request.referrer),
+        cookies: non_empty(&request.cookies),
+        user_agent: non_empty(&request.user_agent),
+        ..Default::default()
+    }
+}
+
+/// Keep only the final path component of a suggested filename
+fn file_name(suggested: &str) -> Option<String> {
+    suggested
+        .rsplit(['/', '\\'])
+        .next()
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+        .map(str::to_string)
+}
+
+fn forward(request: DownloadRequest) -> Result<()> {
+    let url = request.url.trim().to_string();
+    if url.is_empty() {
+        return Err(Error::InvalidInput("URL is required".to_string()));
+    }
+    let options = to_options(&request);
+
+    let socket = ipc::socket_path();
+    if ipc::daemon_available(&socket) {
+        let cmd = if url.starts_with("magnet:") {
+            EngineCommand::AddMagnet {
+                uri: url,
+                options: Some(options),
+            }
+        } else {
+            EngineCommand::AddDownload {
+                url,
+                options: Some(options),
+            }
+        };
+        return ipc::send_command(&socket, &cmd);
+    }
+
+    let method = if url.starts_with("magnet:") {
+        "AddMagnet"
+    } else {
+        "AddUri"
+    };
+    let options = serde_json::to_string(&options)?;
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    rt.block_on(async {
+        let connection = zbus::Connection::session().await?;
+        connection
+            .call_method(
+                Some(dbus::BUS_NAME),
+                dbus::OBJECT_PATH,
+                Some(dbus::BUS_NAME),
+                method,
+                &(url.as_str(), options.as_str()),
+            )
+            .await?;
+        Ok::<_, zbus::Error>(())
+    })
+    .map_err(|e| match e {
+        zbus::Error::MethodError(name, _, _)
+            if name.as_str() == "org.freedesktop.DBus.Error.ServiceUnknown" =>
+        {
+            Error::NotFound("Gosh-Fetch is not running".to_string())
+        }
+        e => Error::Channel(format!("D-Bus: {}", e)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_framing() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &Response::ok()).unwrap();
+
+        let mut reader = buffer.as_slice();
+        let message = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(message, br#"{"ok":true}"#);
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_download_request_options() {
+        let request: Request = serde_json::from_str(
+            r#"{"type":"download","url":"https://example.com/a.iso",
+                "referrer":"https://example.com/","cookies":"session=abc",
+                "userAgent":"Mozilla/5.0","filename":"../../etc/a.iso"}"#,
+        )
+        .unwrap();
+        let Request::Download(download) = request else {
+            panic!("expected a download request");
+        };
+
+        let options = to_options(&download);
+        assert_eq!(options.out.as_deref(), Some("a.iso"));
+        assert_eq!(options.referer.as_deref(), Some("https://example.com/"));
+        assert_eq!(options.cookies.as_deref(), Some("session=abc"));
+        assert_eq!(options.user_agent.as_deref(), Some("Mozilla/5.0"));
+    }
+}
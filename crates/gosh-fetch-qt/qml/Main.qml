@@ -18,6 +18,10 @@ ApplicationWindow {
 
     property var settings: ({})
     property var stats: ({ download_speed: 0, upload_speed: 0, num_active: 0, num_waiting: 0, num_stopped: 0 })
+    property var bandwidth: ({ enabled: false, profiles: [], slots: [] })
+    // Percent unpacked, by gid, for downloads whose archive is being extracted
+    property var extracting: ({})
+    readonly property var profileColors: ["#7de2d1", "#f7b267", "#e07a9a", "#9ab6f7", "#b7e07a", "#c9a0f2"]
 
     ListModel { id: downloadsModel }
     ListModel { id: completedModel }
@@ -46,6 +50,58 @@ ApplicationWindow {
         model.append(download);
     }
 
+    function completionAction(type) {
+        var actions = settings.completion_actions || [];
+        for (var i = 0; i < actions.length; ++i) {
+            if (actions[i].type === type) return actions[i];
+        }
+        return null;
+    }
+
+    function saveCompletionActions() {
+        var actions = [];
+        var dir = completionDirField.text.trim();
+        if (completionFileCombo.currentIndex > 0 && dir.length > 0) {
+            actions.push({ type: completionFileCombo.currentIndex === 1 ? "move" : "copy", dir: dir });
+        }
+        if (completionExtractCombo.currentIndex > 0) {
+            actions.push({
+                type: "extract",
+                target: completionExtractCombo.currentIndex === 1 ? "sibling" : "category",
+                delete_archive: completionDeleteArchiveCheck.checked
+            });
+        }
+        if (completionCommandField.text.trim().length > 0) {
+            actions.push({ type: "command", command: completionCommandField.text.trim() });
+        }
+        if (completionOpenSwitch.checked) {
+            actions.push({ type: "open" });
+        }
+        controller.set_setting("completion_actions", JSON.stringify(actions));
+        settings.completion_actions = actions;
+    }
+
+    function saveBandwidth(schedule) {
+        bandwidth = schedule;
+        controller.set_setting("bandwidth_schedule", JSON.stringify(schedule));
+    }
+
+    function cycleSlot(slot) {
+        var schedule = JSON.parse(JSON.stringify(bandwidth));
+        while (schedule.slots.length < 168) schedule.slots.push(null);
+        var names = schedule.profiles.map(function(p) { return p.name; });
+        var next = names.indexOf(schedule.slots[slot]) + 1;
+        schedule.slots[slot] = next < names.length ? names[next] : null;
+        saveBandwidth(schedule);
+    }
+
+    function profileIndex(name) {
+        for (var i = 0; i < bandwidth.profiles.length; ++i) {
+            if (bandwidth.profiles[i].name === name) return i;
+        }
+        return -1;
+    }
+
     function removeByGid(model, gid) {
         for (var i = 0; i < model.count; ++i) {
             if (model.get(i).gid === gid) {
@@ -55,14 +111,42 @@ ApplicationWindow {
         }
     }
 
+    function indexOfGid(model, gid) {
+        for (var i = 0; i < model.count; ++i) {
+            if (model.get(i).gid === gid) return i;
+        }
+        return -1;
+    }
+
+    // Switch to the list holding a download and scroll to it
+    function showDownload(gid) {
+        var index = indexOfGid(downloadsModel, gid);
+        if (index >= 0) {
+            navTabs.currentIndex = 0;
+            filterBox.currentIndex = 0;
+            downloadsList.positionViewAtIndex(index, ListView.Center);
+            return;
+        }
+        index = indexOfGid(completedModel, gid);
+        if (index >= 0) {
+            navTabs.currentIndex = 1;
+            completedList.positionViewAtIndex(index, ListView.Center);
+        }
+    }
+
     Component.onCompleted: {
         controller.initialize();
         controller.refresh_downloads();
         controller.refresh_stats();
         controller.load_completed();
+        loadSettings();
+    }
+
+    function loadSettings() {
         var raw = controller.get_settings_json();
         if (raw.length > 0) {
             settings = JSON.parse(raw);
+            if (settings.bandwidth_schedule) bandwidth = settings.bandwidth_schedule;
         }
         uaCombo.currentIndex = controller.get_user_agent_index(settings.user_agent || "");
     }
@@ -143,6 +227,37 @@ ApplicationWindow {
             toastLabel.text = message;
             toast.open();
         }
+
+        function onExtraction_progress(gid, percent) {
+            var next = Object.assign({}, extracting);
+            if (percent < 0) {
+                delete next[gid];
+            } else {
+                next[gid] = percent;
+            }
+            extracting = next;
+        }
+
+        function onDuplicate_found(json, commandJson) {
+            duplicateDialog.existing = JSON.parse(json);
+            duplicateDialog.commandJson = commandJson;
+            duplicateDialog.open();
+        }
+
+        function onUrl_probed(json) {
+            var result = JSON.parse(json);
+            if (result.url === addDialog.probedUrl) {
+                addDialog.probe = result;
+                addDialog.probeError = "";
+            }
+        }
+
+        function onProbe_failed(url, message) {
+            if (url === addDialog.probedUrl) {
+                addDialog.probe = null;
+                addDialog.probeError = message;
+            }
+        }
     }
 
     SystemTrayIcon {
@@ -186,6 +301,60 @@ ApplicationWindow {
         Timer { interval: 3000; running: toast.visible; onTriggered: toast.close() }
     }
 
+    Dialog {
+        id: duplicateDialog
+        property var existing: ({})
+        property string commandJson: ""
+        readonly property var matchText: ({
+            url: "The same URL",
+            info_hash: "The same torrent",
+            path: "A download saving to the same file"
+        })
+        modal: true
+        title: "Already Added"
+        x: (root.width - width) / 2
+        y: (root.height - height) / 2
+        width: 440
+
+        contentItem: Text {
+            text: (duplicateDialog.matchText[duplicateDialog.existing.matched] || "This download")
+                + " is already in the list as \"" + duplicateDialog.existing.name + "\" ("
+                + duplicateDialog.existing.state + ")."
+            color: "#f2f2f2"
+            font.pixelSize: 14
+            wrapMode: Text.WordWrap
+            padding: 12
+        }
+
+        footer: DialogButtonBox {
+            Button {
+                text: "Show"
+                onClicked: {
+                    showDownload(duplicateDialog.existing.gid);
+                    duplicateDialog.close();
+                }
+            }
+            Button {
+                text: "Restart"
+                onClicked: {
+                    controller.resend_add(duplicateDialog.commandJson, "restart");
+                    duplicateDialog.close();
+                }
+            }
+            Button {
+                text: "Add Anyway"
+                onClicked: {
+                    controller.resend_add(duplicateDialog.commandJson, "add");
+                    duplicateDialog.close();
+                }
+            }
+            Button {
+                text: "Cancel"
+                onClicked: duplicateDialog.close()
+            }
+        }
+    }
+
     header: ToolBar {
         height: 58
         background: Rectangle {
@@ -218,6 +387,7 @@ ApplicationWindow {
                     spacing: 8
                     Text { text: "↓ " + formatSpeed(stats.download_speed); color: "#7de2d1"; font.pixelSize: 12 }
                     Text { text: "↑ " + formatSpeed(stats.upload_speed); color: "#f7b267"; font.pixelSize: 12 }
+                    Text { visible: !!stats.bandwidth_profile; text: stats.bandwidth_profile || ""; color: "#9aa3b2"; font.pixelSize: 12 }
                 }
             }
 
@@ -286,7 +456,7 @@ ApplicationWindow {
 
                         ComboBox {
                             id: filterBox
-                            model: ["All", "Active", "Paused", "Error"]
+                            model: ["All", "Active", "Paused", "Error", "Scheduled"]
                             currentIndex: 0
                         }
                     }
@@ -309,6 +479,7 @@ ApplicationWindow {
                                 if (filterBox.currentIndex === 1) return model.status === "active" || model.status === "waiting";
                                 if (filterBox.currentIndex === 2) return model.status === "paused";
                                 if (filterBox.currentIndex === 3) return model.status === "error";
+                                if (filterBox.currentIndex === 4) return model.status === "scheduled";
                                 return true;
                             }
 
@@ -320,7 +491,13 @@ ApplicationWindow {
                                 RowLayout {
                                     Layout.fillWidth: true
                                     Text { text: model.name; color: "#f2f2f2"; font.pixelSize: 16; elide: Text.ElideRight; Layout.fillWidth: true }
-                                    Text { text: model.status; color: "#8c96a6"; font.pixelSize: 12 }
+                                    Text {
+                                        text: model.status === "scheduled" && model.scheduled_start
+                                            ? "scheduled for " + new Date(model.scheduled_start * 1000).toLocaleString(Qt.locale(), Locale.ShortFormat)
+                                            : model.status
+                                        color: "#8c96a6"
+                                        font.pixelSize: 12
+                                    }
                                 }
 
                                 ProgressBar {
@@ -340,6 +517,12 @@ ApplicationWindow {
                                 RowLayout {
                                     Layout.fillWidth: true
                                     Button {
+                                        visible: model.status === "scheduled"
+                                        text: "Start Now"
+                                        onClicked: controller.reschedule_download(model.gid, 0)
+                                    }
+                                    Button {
+                                        visible: model.status !== "scheduled"
                                         text: model.status === "paused" ? "Resume" : "Pause"
                                         onClicked: {
                                             if (model.status === "paused") {
@@ -350,8 +533,14 @@ ApplicationWindow {
                                         }
                                     }
                                     Button {
-                                        text: "Remove"
-                                        onClicked: controller.remove_download(model.gid, settings.delete_files_on_remove === true)
+                                        text: model.status === "scheduled" ? "Cancel" : "Remove"
+                                        onClicked: {
+                                            if (model.status === "scheduled") {
+                                                controller.cancel_scheduled(model.gid)
+                                            } else {
+                                                controller.remove_download(model.gid, settings.delete_files_on_remove === true)
+                                            }
+                                        }
                                     }
                                     Button {
                                         text: "Open"
@@ -402,7 +591,7 @@ ApplicationWindow {
                         model: completedModel
                         delegate: Rectangle {
                             width: completedList.width
-                            height: 110
+                            height: 130
                             radius: 12
                             color: "#161b24"
                             border.color: "#2a3443"
@@ -418,15 +607,53 @@ ApplicationWindow {
                                 RowLayout {
                                     Layout.fillWidth: true
                                     Text { text: formatBytes(model.total_size); color: "#8c96a6"; font.pixelSize: 12 }
+                                    Text {
+                                        visible: !!model.checksum
+                                        text: !model.checksum ? ""
+                                            : model.checksum.matched === true ? model.checksum.algorithm + " verified"
+                                            : model.checksum.matched === false ? model.checksum.algorithm + " mismatch"
+                                            : model.checksum.algorithm + " " + model.checksum.digest.substring(0, 16)
+                                        color: model.checksum && model.checksum.matched === false ? "#e07a7a" : "#7de2d1"
+                                        font.pixelSize: 12
+                                    }
+                                    Text {
+                                        visible: !!model.conflict
+                                        text: model.conflict === "skipped" ? "file already existed"
+                                            : model.conflict === "renamed" ? "renamed, name was taken"
+                                            : model.conflict === "overwritten" ? "replaced existing file"
+                                            : "continued existing file"
+                                        color: "#8c96a6"
+                                        font.pixelSize: 12
+                                    }
+                                    Text {
+                                        visible: extracting[model.gid] !== undefined
+                                        text: "extracting " + extracting[model.gid] + "%"
+                                        color: "#f7b267"
+                                        font.pixelSize: 12
+                                    }
                                     Item { Layout.fillWidth: true }
                                     Text { text: model.completed_at || ""; color: "#6f7a8c"; font.pixelSize: 12 }
                                 }
+                                Text {
+                                    // Depends on save_path so it is re-read when a move updates the entry
+                                    property var actionLog: { model.save_path; return JSON.parse(controller.get_action_log_json(model.gid)); }
+                                    Layout.fillWidth: true
+                                    visible: actionLog.length > 0
+                                    text: actionLog.map(function(e) { return (e.success ? "\u2713 " : "\u2717 ") + e.action; }).join("    ")
+                                    color: actionLog.some(function(e) { return !e.success; }) ? "#e07a7a" : "#8c96a6"
+                                    elide: Text.ElideRight
+                                    font.pixelSize: 12
+                                }
                                 RowLayout {
                                     Layout.fillWidth: true
                                     Button {
                                         text: "Open"
                                         onClicked: controller.open_path(model.save_path)
                                     }
+                                    Button {
+                                        text: "Verify"
+                                        onClicked: controller.verify_checksum(model.gid)
+                                    }
                                     Button {
                                         text: "Remove"
                                         onClicked: controller.remove_download(model.gid, settings.delete_files_on_remove === true)
@@ -472,6 +699,20 @@ ApplicationWindow {
                                     }
                                     Button { text: "Browse"; onClicked: downloadPathDialog.open() }
                                 }
+                                RowLayout {
+                                    Layout.fillWidth: true
+                                    Label { text: "If File Exists"; Layout.preferredWidth: 160 }
+                                    ComboBox {
+                                        readonly property var policies: ["rename", "overwrite", "skip", "resume"]
+                                        model: ["Rename", "Overwrite", "Skip", "Resume"]
+                                        currentIndex: Math.max(0, policies.indexOf(settings.conflict_policy))
+                                        onActivated: {
+                                            var value = policies[currentIndex]
+                                            controller.set_setting("conflict_policy", value)
+                                            settings.conflict_policy = value
+                                        }
+                                    }
+                                }
                                 RowLayout {
                                     Layout.fillWidth: true
                                     Label { text: "Notifications"; Layout.preferredWidth: 160 }
@@ -590,6 +831,119 @@ ApplicationWindow {
                             }
                         }
 
+                        GroupBox {
+                            title: "Bandwidth Schedule"
+                            Layout.fillWidth: true
+                            ColumnLayout {
+                                spacing: 8
+                                RowLayout {
+                                    Layout.fillWidth: true
+                                    Label { text: "Enable Schedule"; Layout.preferredWidth: 160 }
+                                    Switch {
+                                        checked: bandwidth.enabled === true
+                                        onToggled: {
+                                            var schedule = JSON.parse(JSON.stringify(bandwidth));
+                                            schedule.enabled = checked;
+                                            saveBandwidth(schedule);
+                                        }
+                                    }
+                                }
+                                RowLayout {
+                                    Layout.fillWidth: true
+                                    Label { text: "Use Now"; Layout.preferredWidth: 160 }
+                                    ComboBox {
+                                        model: ["Follow schedule"].concat(bandwidth.profiles.map(function(p) { return p.name; }))
+                                        onActivated: controller.set_bandwidth_profile(currentIndex === 0 ? "" : model[currentIndex])
+                                    }
+                                }
+                                Repeater {
+                                    model: bandwidth.profiles
+                                    delegate: RowLayout {
+                                        Layout.fillWidth: true
+                                        Rectangle { width: 12; height: 12; radius: 6; color: profileColors[index % profileColors.length] }
+                                        TextField {
+                                            Layout.preferredWidth: 140
+                                            text: modelData.name
+                                            onEditingFinished: {
+                                                var schedule = JSON.parse(JSON.stringify(bandwidth));
+                                                var old = schedule.profiles[index].name;
+                                                schedule.profiles[index].name = text;
+                                                schedule.slots = schedule.slots.map(function(s) { return s === old ? text : s; });
+                                                saveBandwidth(schedule);
+                                            }
+                                        }
+                                        Label { text: "↓ KB/s" }
+                                        SpinBox {
+                                            from: 0; to: 100000
+                                            value: Math.round(modelData.download_limit / 1024)
+                                            onValueModified: {
+                                                var schedule = JSON.parse(JSON.stringify(bandwidth));
+                                                schedule.profiles[index].download_limit = value * 1024;
+                                                saveBandwidth(schedule);
+                                            }
+                                        }
+                                        Label { text: "↑ KB/s" }
+                                        SpinBox {
+                                            from: 0; to: 100000
+                                            value: Math.round(modelData.upload_limit / 1024)
+                                            onValueModified: {
+                                                var schedule = JSON.parse(JSON.stringify(bandwidth));
+                                                schedule.profiles[index].upload_limit = value * 1024;
+                                                saveBandwidth(schedule);
+                                            }
+                                        }
+                                        CheckBox {
+                                            text: "Pause"
+                                            checked: modelData.paused === true
+                                            onToggled: {
+                                                var schedule = JSON.parse(JSON.stringify(bandwidth));
+                                                schedule.profiles[index].paused = checked;
+                                                saveBandwidth(schedule);
+                                            }
+                                        }
+                                        Button {
+                                            text: "Remove"
+                                            onClicked: {
+                                                var schedule = JSON.parse(JSON.stringify(bandwidth));
+                                                var name = schedule.profiles[index].name;
+                                                schedule.profiles.splice(index, 1);
+                                                schedule.slots = schedule.slots.map(function(s) { return s === name ? null : s; });
+                                                saveBandwidth(schedule);
+                                            }
+                                        }
+                                    }
+                                }
+                                Button {
+                                    text: "Add Profile"
+                                    onClicked: {
+                                        var schedule = JSON.parse(JSON.stringify(bandwidth));
+                                        schedule.profiles.push({ name: "Profile " + (schedule.profiles.length + 1), download_limit: 0, upload_limit: 0, paused: false });
+                                        saveBandwidth(schedule);
+                                    }
+                                }
+                                Label { text: "Click an hour to cycle its profile; empty hours use the limits above."; color: "#8c96a6"; font.pixelSize: 12 }
+                                Repeater {
+                                    model: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+                                    delegate: RowLayout {
+                                        property int day: index
+                                        spacing: 2
+                                        Label { text: modelData; Layout.preferredWidth: 40 }
+                                        Repeater {
+                                            model: 24
+                                            delegate: Rectangle {
+                                                property int slot: day * 24 + index
+                                                property int profile: profileIndex(bandwidth.slots[slot])
+                                                width: 18; height: 18; radius: 3
+                                                color: profile >= 0 ? profileColors[profile % profileColors.length] : "#1d2430"
+                                                border.color: "#2a3443"
+                                                MouseArea { anchors.fill: parent; onClicked: cycleSlot(slot) }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
                         GroupBox {
                             title: "User Agent"
                             Layout.fillWidth: true
@@ -767,6 +1121,110 @@ ApplicationWindow {
                                 }
                             }
                         }
+
+                        GroupBox {
+                            title: "When a Download Completes"
+                            Layout.fillWidth: true
+                            ColumnLayout {
+                                spacing: 8
+                                Label {
+                                    text: "Categories and single downloads can set their own actions"
+                                    opacity: 0.7
+                                }
+                                RowLayout {
+                                    Layout.fillWidth: true
+                                    Label { text: "File"; Layout.preferredWidth: 160 }
+                                    ComboBox {
+                                        id: completionFileCombo
+                                        model: ["Leave in place", "Move to", "Copy to"]
+                                        currentIndex: completionAction("move") ? 1 : (completionAction("copy") ? 2 : 0)
+                                        onActivated: saveCompletionActions()
+                                    }
+                                    TextField {
+                                        id: completionDirField
+                                        Layout.fillWidth: true
+                                        enabled: completionFileCombo.currentIndex > 0
+                                        placeholderText: "Folder"
+                                        text: (completionAction("move") || completionAction("copy") || {}).dir || ""
+                                        onEditingFinished: saveCompletionActions()
+                                    }
+                                }
+                                RowLayout {
+                                    Layout.fillWidth: true
+                                    Label { text: "Extract Archives"; Layout.preferredWidth: 160 }
+                                    ComboBox {
+                                        id: completionExtractCombo
+                                        model: ["Off", "Next to the archive", "Into the category folder"]
+                                        currentIndex: !completionAction("extract") ? 0 : (completionAction("extract").target === "category" ? 2 : 1)
+                                        onActivated: saveCompletionActions()
+                                    }
+                                    CheckBox {
+                                        id: completionDeleteArchiveCheck
+                                        text: "Delete archive afterwards"
+                                        enabled: completionExtractCombo.currentIndex > 0
+                                        checked: (completionAction("extract") || {}).delete_archive === true
+                                        onToggled: saveCompletionActions()
+                                    }
+                                }
+                                RowLayout {
+                                    Layout.fillWidth: true
+                                    Label { text: "Run Command"; Layout.preferredWidth: 160 }
+                                    TextField {
+                                        id: completionCommandField
+                                        Layout.fillWidth: true
+                                        placeholderText: "Uses $GOSH_GID, $GOSH_PATH, $GOSH_NAME and $GOSH_URL"
+                                        text: (completionAction("command") || {}).command || ""
+                                        onEditingFinished: saveCompletionActions()
+                                    }
+                                }
+                                RowLayout {
+                                    Layout.fillWidth: true
+                                    Label { text: "Open File"; Layout.preferredWidth: 160 }
+                                    Switch {
+                                        id: completionOpenSwitch
+                                        checked: completionAction("open") !== null
+                                        onToggled: saveCompletionActions()
+                                    }
+                                }
+                            }
+                        }
+
+                        GroupBox {
+                            title: "Backup"
+                            Layout.fillWidth: true
+                            ColumnLayout {
+                                spacing: 8
+                                Label {
+                                    text: "Settings, history, trackers, categories and feeds as a JSON file"
+                                    opacity: 0.7
+                                }
+                                RowLayout {
+                                    Layout.fillWidth: true
+                                    CheckBox {
+                                        id: redactCheck
+                                        text: "Leave out passwords and cookies"
+                                        checked: true
+                                    }
+                                    Item { Layout.fillWidth: true }
+                                    Button {
+                                        text: "Export..."
+                                        onClicked: exportDialog.open()
+                                    }
+                                }
+                                RowLayout {
+                                    Layout.fillWidth: true
+                                    CheckBox {
+                                        id: replaceCheck
+                                        text: "Replace current data instead of merging"
+                                    }
+                                    Item { Layout.fillWidth: true }
+                                    Button {
+                                        text: "Import..."
+                                        onClicked: importDialog.open()
+                                    }
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -786,11 +1244,37 @@ ApplicationWindow {
 
     Dialog {
         id: addDialog
+        property string probedUrl: ""
+        property var probe: null
+        property string probeError: ""
         width: 640
         height: 600
         modal: true
         title: "Add Download"
         standardButtons: Dialog.Ok | Dialog.Cancel
+
+        function requestOptions() {
+            return {
+                user_agent: userAgentField.text.length > 0 ? userAgentField.text : undefined,
+                referer: refererField.text.length > 0 ? refererField.text : undefined,
+                header: headersField.text.length > 0 ? headersField.text.split("\n") : undefined,
+                cookies: cookiesField.text.length > 0 ? cookiesField.text : undefined,
+                proxy: proxyField.text.length > 0 ? proxyField.text : undefined
+            };
+        }
+
+        function probeSummary() {
+            if (probeError.length > 0) return probeError;
+            if (!probe) return probedUrl.length > 0 ? "Checking..." : "";
+            var parts = [probe.file_name || "(no name)"];
+            parts.push(probe.content_length !== null ? formatBytes(probe.content_length) : "unknown size");
+            if (probe.mime_type) parts.push(probe.mime_type);
+            parts.push(probe.resumable ? "resumable" : "cannot resume");
+            if (probe.redirects.length > 0) {
+                parts.push(probe.redirects.length + (probe.redirects.length === 1 ? " redirect" : " redirects"));
+            }
+            return parts.join(" · ");
+        }
         onAccepted: {
             var options = {
                 dir: downloadDirField.text.length > 0 ? downloadDirField.text : undefined,
@@ -800,15 +1284,18 @@ ApplicationWindow {
                 referer: refererField.text.length > 0 ? refererField.text : undefined,
                 header: headersField.text.length > 0 ? headersField.text.split("\n") : undefined,
                 cookies: cookiesField.text.length > 0 ? cookiesField.text : undefined,
+                proxy: proxyField.text.length > 0 ? proxyField.text : undefined,
                 checksum_type: checksumTypeCombo.currentText !== "None" ? checksumTypeCombo.currentText.toLowerCase() : undefined,
                 checksum_value: checksumValueField.text.length > 0 ? checksumValueField.text : undefined,
+                checksum_file: checksumFileField.text.length > 0 ? checksumFileField.text : undefined,
                 mirror_urls: mirrorsField.text.length > 0 ? mirrorsField.text.split("\n") : undefined,
                 priority: priorityCombo.currentText.toLowerCase(),
                 max_download_limit: speedLimitField.text.length > 0 ? speedLimitField.text : undefined,
                 max_upload_limit: uploadLimitField.text.length > 0 ? uploadLimitField.text : undefined,
                 sequential: sequentialSwitch.checked,
                 select_file: selectedFilesField.text.length > 0 ? selectedFilesField.text : undefined,
-                seed_ratio: seedRatioField.text.length > 0 ? seedRatioField.text : undefined
+                seed_ratio: seedRatioField.text.length > 0 ? seedRatioField.text : undefined,
+                conflict_policy: conflictCombo.currentIndex > 0 ? conflictCombo.currentText.toLowerCase() : undefined
             };
             if (scheduleSwitch.checked) {
                 options.scheduled_start = Math.floor(scheduleTime.dateTime.getTime() / 1000);
@@ -820,6 +1307,8 @@ ApplicationWindow {
                 controller.add_download(urlField.text, optionsJson)
             } else if (addTabs.currentIndex === 1) {
                 controller.add_magnet(magnetField.text, optionsJson)
+            } else if (/\.(metalink|meta4)$/i.test(torrentPathField.text)) {
+                controller.add_metalink(torrentPathField.text, optionsJson)
             } else {
                 controller.add_torrent(torrentPathField.text, optionsJson)
             }
@@ -844,7 +1333,38 @@ ApplicationWindow {
                     ColumnLayout {
                         anchors.fill: parent
                         spacing: 8
-                        TextField { id: urlField; placeholderText: "https://example.com/file.zip"; Layout.fillWidth: true }
+                        RowLayout {
+                            Layout.fillWidth: true
+                            TextArea {
+                                id: urlField
+                                placeholderText: "https://example.com/file.zip\n(paste several links, one per line)"
+                                Layout.fillWidth: true
+                                Layout.preferredHeight: 60
+                                onTextChanged: {
+                                    addDialog.probedUrl = "";
+                                    addDialog.probe = null;
+                                    addDialog.probeError = "";
+                                }
+                            }
+                            Button {
+                                text: "Check"
+                                // Only a single link can be checked
+                                enabled: /^\S+$/.test(urlField.text.trim())
+                                onClicked: {
+                                    addDialog.probedUrl = urlField.text.trim();
+                                    addDialog.probe = null;
+                                    addDialog.probeError = "";
+                                    controller.probe_url(addDialog.probedUrl, JSON.stringify(addDialog.requestOptions()));
+                                }
+                            }
+                        }
+                        Label {
+                            visible: text.length > 0
+                            text: addDialog.probeSummary()
+                            color: addDialog.probeError.length > 0 ? "#e07a7a" : "#9aa3b2"
+                            elide: Text.ElideMiddle
+                            Layout.fillWidth: true
+                        }
                     }
                 }
                 Item {
@@ -860,7 +1380,7 @@ ApplicationWindow {
                         spacing: 8
                         RowLayout {
                             Layout.fillWidth: true
-                            TextField { id: torrentPathField; Layout.fillWidth: true; placeholderText: "Select torrent file" }
+                            TextField { id: torrentPathField; Layout.fillWidth: true; placeholderText: "Select torrent or metalink file" }
                             Button { text: "Browse"; onClicked: torrentDialog.open() }
                         }
                     }
@@ -880,7 +1400,16 @@ ApplicationWindow {
                     RowLayout {
                         Layout.fillWidth: true
                         Label { text: "Filename"; Layout.preferredWidth: 120 }
-                        TextField { id: filenameField; Layout.fillWidth: true }
+                        TextField {
+                            id: filenameField
+                            Layout.fillWidth: true
+                            placeholderText: addDialog.probe && addDialog.probe.file_name ? addDialog.probe.file_name : ""
+                        }
+                    }
+                    RowLayout {
+                        Layout.fillWidth: true
+                        Label { text: "If File Exists"; Layout.preferredWidth: 120 }
+                        ComboBox { id: conflictCombo; model: ["Default", "Rename", "Overwrite", "Skip", "Resume"]; currentIndex: 0 }
                     }
                     RowLayout {
                         Layout.fillWidth: true
@@ -907,12 +1436,22 @@ ApplicationWindow {
                         Label { text: "Cookies"; Layout.preferredWidth: 120 }
                         TextField { id: cookiesField; Layout.fillWidth: true }
                     }
+                    RowLayout {
+                        Layout.fillWidth: true
+                        Label { text: "Proxy"; Layout.preferredWidth: 120 }
+                        TextField { id: proxyField; Layout.fillWidth: true; placeholderText: "From settings; only used to check the link" }
+                    }
                     RowLayout {
                         Layout.fillWidth: true
                         Label { text: "Checksum"; Layout.preferredWidth: 120 }
-                        ComboBox { id: checksumTypeCombo; model: ["None", "MD5", "SHA256"] }
+                        ComboBox { id: checksumTypeCombo; model: ["None", "MD5", "SHA1", "SHA256", "SHA512", "BLAKE3"] }
                         TextField { id: checksumValueField; Layout.fillWidth: true }
                     }
+                    RowLayout {
+                        Layout.fillWidth: true
+                        Label { text: "Checksum file"; Layout.preferredWidth: 120 }
+                        TextField { id: checksumFileField; Layout.fillWidth: true; placeholderText: "URL or path of SHA256SUMS, .sha256, ..." }
+                    }
                     RowLayout {
                         Layout.fillWidth: true
                         Label { text: "Mirrors (one per line)"; Layout.preferredWidth: 120 }
@@ -959,10 +1498,30 @@ ApplicationWindow {
         }
     }
 
+    FileDialog {
+        id: exportDialog
+        title: "Export Backup"
+        fileMode: FileDialog.SaveFile
+        defaultSuffix: "json"
+        nameFilters: ["Gosh-Fetch backups (*.json)"]
+        onAccepted: controller.export_bundle(exportDialog.file.toString(), redactCheck.checked)
+    }
+
+    FileDialog {
+        id: importDialog
+        title: "Import Backup"
+        nameFilters: ["Gosh-Fetch backups (*.json)"]
+        onAccepted: {
+            if (controller.import_bundle(importDialog.file.toString(), replaceCheck.checked)) {
+                loadSettings();
+            }
+        }
+    }
+
     FileDialog {
         id: torrentDialog
-        title: "Select Torrent File"
-        nameFilters: ["Torrent files (*.torrent)"]
+        title: "Select Torrent or Metalink File"
+        nameFilters: ["Torrent files (*.torrent)", "Metalink files (*.metalink *.meta4)"]
         onAccepted: {
             var path = torrentDialog.currentFile.toString().replace("file://", "");
             torrentPathField.text = path;
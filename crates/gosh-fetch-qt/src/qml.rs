@@ -2,9 +2,12 @@ use std::pin::Pin;
 use std::sync::{Mutex, OnceLock};
 
 use cxx_qt_lib::QString;
+use gosh_fetch_core::backup::{self, Bundle, ImportMode};
 use gosh_fetch_core::{
-    get_user_agent_presets, init_database, settings_to_engine_config, DownloadsDb, DownloadService,
-    EngineCommand, Settings, SettingsDb, TrackerUpdater, UiMessage,
+    get_user_agent_presets, init_database, ipc, record_ui_message, restore_incomplete_downloads,
+    CategoriesDb, Category, DaemonClient, DbusHandle, DownloadService, DownloadsDb,
+    DuplicateAction, EngineCommand, Feed, FeedsDb, Settings, SettingsDb, TrackerUpdater, UiMessage,
+    WatchFolder, WatchFoldersDb,
 };
 
 #[cxx_qt::bridge]
@@ -32,6 +35,15 @@ mod ffi {
         #[qinvokable]
         pub fn add_torrent(self: Pin<&mut AppController>, path: QString, options_json: QString);
 
+        #[qinvokable]
+        pub fn add_metalink(self: Pin<&mut AppController>, path: QString, options_json: QString);
+
+        #[qinvokable]
+        pub fn resend_add(self: Pin<&mut AppController>, command_json: QString, action: QString);
+
+        #[qinvokable]
+        pub fn probe_url(self: Pin<&mut AppController>, url: QString, options_json: QString);
+
         #[qinvokable]
         pub fn pause_download(self: Pin<&mut AppController>, gid: QString);
 
@@ -41,6 +53,18 @@ mod ffi {
         #[qinvokable]
         pub fn remove_download(self: Pin<&mut AppController>, gid: QString, delete_files: bool);
 
+        #[qinvokable]
+        pub fn reschedule_download(self: Pin<&mut AppController>, gid: QString, start: i64);
+
+        #[qinvokable]
+        pub fn cancel_scheduled(self: Pin<&mut AppController>, gid: QString);
+
+        #[qinvokable]
+        pub fn verify_checksum(self: Pin<&mut AppController>, gid: QString);
+
+        #[qinvokable]
+        pub fn get_action_log_json(self: Pin<&mut AppController>, gid: QString) -> QString;
+
         #[qinvokable]
         pub fn pause_all(self: Pin<&mut AppController>);
 
@@ -65,6 +89,42 @@ mod ffi {
         #[qinvokable]
         pub fn get_settings_json(self: Pin<&mut AppController>) -> QString;
 
+        #[qinvokable]
+        pub fn set_bandwidth_profile(self: Pin<&mut AppController>, name: QString);
+
+        #[qinvokable]
+        pub fn get_categories_json(self: Pin<&mut AppController>) -> QString;
+
+        #[qinvokable]
+        pub fn save_category(self: Pin<&mut AppController>, json: QString) -> bool;
+
+        #[qinvokable]
+        pub fn delete_category(self: Pin<&mut AppController>, id: i64);
+
+        #[qinvokable]
+        pub fn get_feeds_json(self: Pin<&mut AppController>) -> QString;
+
+        #[qinvokable]
+        pub fn save_feed(self: Pin<&mut AppController>, json: QString) -> bool;
+
+        #[qinvokable]
+        pub fn delete_feed(self: Pin<&mut AppController>, id: i64);
+
+        #[qinvokable]
+        pub fn get_watch_folders_json(self: Pin<&mut AppController>) -> QString;
+
+        #[qinvokable]
+        pub fn save_watch_folder(self: Pin<&mut AppController>, json: QString) -> bool;
+
+        #[qinvokable]
+        pub fn delete_watch_folder(self: Pin<&mut AppController>, id: i64);
+
+        #[qinvokable]
+        pub fn export_bundle(self: Pin<&mut AppController>, path: QString, redact: bool) -> bool;
+
+        #[qinvokable]
+        pub fn import_bundle(self: Pin<&mut AppController>, path: QString, replace: bool) -> bool;
+
         #[qinvokable]
         pub fn get_user_agent_presets_json(self: Pin<&mut AppController>) -> QString;
 
@@ -109,6 +169,26 @@ mod ffi {
 
         #[qsignal]
         pub fn toast(self: Pin<&mut AppController>, message: QString);
+
+        /// Percent of a download's archive unpacked, or -1 once done
+        #[qsignal]
+        pub fn extraction_progress(self: Pin<&mut AppController>, gid: QString, percent: i32);
+
+        /// An add was held back because the download already exists; the
+        /// command can be sent again with `resend_add`
+        #[qsignal]
+        pub fn duplicate_found(
+            self: Pin<&mut AppController>,
+            json: QString,
+            command_json: QString,
+        );
+
+        /// What a URL passed to `probe_url` serves, as a `ProbeResult`
+        #[qsignal]
+        pub fn url_probed(self: Pin<&mut AppController>, json: QString);
+
+        #[qsignal]
+        pub fn probe_failed(self: Pin<&mut AppController>, url: QString, message: QString);
     }
 }
 
@@ -117,6 +197,9 @@ struct AppState {
     settings: Settings,
     cmd_sender: async_channel::Sender<EngineCommand>,
     ui_receiver: async_channel::Receiver<UiMessage>,
+    dbus: Option<DbusHandle>,
+    /// Messages come from gosh-fetchd, which has already recorded them
+    attached: bool,
 }
 
 static APP_STATE: OnceLock<Mutex<AppState>> = OnceLock::new();
@@ -135,25 +218,44 @@ fn ensure_state() -> Result<(), String> {
     let (ui_sender, ui_receiver) = async_channel::bounded::<UiMessage>(200);
     let (cmd_sender, cmd_receiver) = async_channel::bounded::<EngineCommand>(200);
 
-    let rt = tokio::runtime::Runtime::new()
-        .map_err(|e| format!("Failed to create tokio runtime: {}", e))?;
-
-    match rt.block_on(DownloadService::new_async(&settings)) {
-        Ok(service) => {
-            service.spawn(ui_sender, cmd_receiver);
-        }
-        Err(e) => {
-            return Err(format!("Failed to create download service: {}", e));
+    // Attach to a running gosh-fetchd instead of starting a second engine
+    let socket = ipc::socket_path();
+    let mut dbus = None;
+    let attached = ipc::daemon_available(&socket);
+    if attached {
+        log::info!("Attaching to gosh-fetchd at {}", socket.display());
+        let client = DaemonClient::connect(&socket)
+            .map_err(|e| format!("Failed to connect to gosh-fetchd: {}", e))?;
+        client.spawn(ui_sender, cmd_receiver);
+    } else {
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create tokio runtime: {}", e))?;
+
+        match rt.block_on(DownloadService::new_async(&settings, db.clone())) {
+            Ok(service) => {
+                service.spawn(ui_sender, cmd_receiver);
+            }
+            Err(e) => {
+                return Err(format!("Failed to create download service: {}", e));
+            }
         }
-    }
 
-    restore_incomplete_downloads(&db, &cmd_sender);
+        restore_incomplete_downloads(&db, &cmd_sender);
+
+        // The daemon owns the bus name, polls feeds and watches folders when
+        // it is running
+        dbus = Some(gosh_fetch_core::dbus::spawn(cmd_sender.clone()));
+        gosh_fetch_core::feeds::spawn(db.clone(), cmd_sender.clone());
+        gosh_fetch_core::watch::spawn(db.clone(), cmd_sender.clone());
+    }
 
     let state = AppState {
         db,
         settings,
         cmd_sender,
         ui_receiver,
+        dbus,
+        attached,
     };
 
     APP_STATE
@@ -163,54 +265,6 @@ fn ensure_state() -> Result<(), String> {
     Ok(())
 }
 
-fn restore_incomplete_downloads(db: &gosh_fetch_core::Database, cmd_sender: &async_channel::Sender<EngineCommand>) {
-    match DownloadsDb::get_incomplete(db) {
-        Ok(incomplete) => {
-            if incomplete.is_empty() {
-                return;
-            }
-
-            log::info!("Restoring {} incomplete downloads", incomplete.len());
-
-            for download in incomplete {
-                match download.download_type {
-                    gosh_fetch_core::DownloadType::Http => {
-                        if let Some(url) = &download.url {
-                            let _ = cmd_sender.send_blocking(EngineCommand::AddDownload {
-                                url: url.clone(),
-                                options: None,
-                            });
-                        }
-                    }
-                    gosh_fetch_core::DownloadType::Magnet => {
-                        if let Some(uri) = &download.magnet_uri {
-                            let _ = cmd_sender.send_blocking(EngineCommand::AddMagnet {
-                                uri: uri.clone(),
-                                options: None,
-                            });
-                        }
-                    }
-                    gosh_fetch_core::DownloadType::Torrent => {
-                        log::debug!(
-                            "Skipping torrent restoration for {}: engine handles persistence",
-                            download.name
-                        );
-                    }
-                    gosh_fetch_core::DownloadType::Ftp => {
-                        log::warn!(
-                            "Skipping FTP download restoration for {}: not supported",
-                            download.name
-                        );
-                    }
-                }
-            }
-        }
-        Err(e) => {
-            log::error!("Failed to restore incomplete downloads: {}", e);
-        }
-    }
-}
-
 fn parse_options(options_json: &str) -> Option<gosh_fetch_core::DownloadOptions> {
     let trimmed = options_json.trim();
     if trimmed.is_empty() {
@@ -283,45 +337,33 @@ impl ffi::qobject::AppController {
         });
 
         for msg in messages {
+            self.with_state(|state| {
+                if !state.attached {
+                    record_ui_message(&state.db, &msg);
+                }
+                if let Some(dbus) = &state.dbus {
+                    dbus.emit(&msg);
+                }
+            });
+
             match msg {
                 UiMessage::EngineReady => {
                     self.toast(QString::from("Download engine ready"));
                 }
                 UiMessage::DownloadAdded(download) => {
-                    self.with_state(|state| {
-                        if let Err(e) = DownloadsDb::save(&state.db, &download) {
-                            log::error!("Failed to save download: {}", e);
-                        }
-                    });
                     if let Ok(json) = serde_json::to_string(&download) {
                         self.download_added(QString::from(json));
                     }
                 }
                 UiMessage::DownloadUpdated(gid, download) => {
-                    self.with_state(|state| {
-                        if let Err(e) = DownloadsDb::save(&state.db, &download) {
-                            log::error!("Failed to save download update: {}", e);
-                        }
-                    });
                     if let Ok(json) = serde_json::to_string(&download) {
                         self.download_updated(QString::from(gid), QString::from(json));
                     }
                 }
                 UiMessage::DownloadRemoved(gid) => {
-                    self.with_state(|state| {
-                        if let Err(e) = DownloadsDb::delete(&state.db, &gid) {
-                            log::error!("Failed to delete download record: {}", e);
-                        }
-                    });
                     self.download_removed(QString::from(gid));
                 }
                 UiMessage::DownloadCompleted(download) => {
-                    self.with_state(|state| {
-                        if let Err(e) = DownloadsDb::save(&state.db, &download) {
-                            log::error!("Failed to save completed download: {}", e);
-                        }
-                    });
-
                     if let Some(settings) = self.with_state(|state| state.settings.clone()) {
                         if settings.enable_notifications {
                             if let Err(e) = notify_rust::Notification::new()
@@ -343,6 +385,53 @@ impl ffi::qobject::AppController {
                 UiMessage::DownloadFailed(_, error) => {
                     self.error(QString::from(error));
                 }
+                UiMessage::ChecksumVerified(gid, result) => {
+                    let message = match result.matched {
+                        Some(true) => format!("Checksum OK ({})", result.algorithm),
+                        Some(false) => format!("Checksum mismatch ({})", result.algorithm),
+                        None => format!("{}: {}", result.algorithm, result.digest),
+                    };
+                    if result.matched == Some(false) {
+                        self.error(QString::from(message));
+                    } else {
+                        self.toast(QString::from(message));
+                    }
+
+                    // Show the stored result in the history list
+                    let download = self
+                        .with_state(|state| DownloadsDb::get_by_gid(&state.db, &gid))
+                        .and_then(|result| result.ok().flatten());
+                    if let Some(json) = download.and_then(|d| serde_json::to_string(&d).ok()) {
+                        self.download_completed(QString::from(json));
+                    }
+                }
+                UiMessage::ExtractionProgress(gid, read, size) => {
+                    let percent = (read * 100 / size.max(1)) as i32;
+                    self.extraction_progress(QString::from(gid), percent);
+                }
+                UiMessage::ExtractionFinished(gid, message) => {
+                    self.extraction_progress(QString::from(gid), -1);
+                    self.toast(QString::from(format!("Archive {}", message)));
+                }
+                UiMessage::ExtractionFailed(gid, error) => {
+                    self.extraction_progress(QString::from(gid), -1);
+                    self.error(QString::from(format!("Extraction failed: {}", error)));
+                }
+                UiMessage::DuplicateDownload(existing, cmd) => {
+                    if let (Ok(json), Ok(command_json)) =
+                        (serde_json::to_string(&existing), serde_json::to_string(&cmd))
+                    {
+                        self.duplicate_found(QString::from(json), QString::from(command_json));
+                    }
+                }
+                UiMessage::UrlProbed(result) => {
+                    if let Ok(json) = serde_json::to_string(&result) {
+                        self.url_probed(QString::from(json));
+                    }
+                }
+                UiMessage::ProbeFailed(url, error) => {
+                    self.probe_failed(QString::from(url), QString::from(error));
+                }
                 UiMessage::StatsUpdated(stats) => {
                     if let Ok(json) = serde_json::to_string(&stats) {
                         self.stats_updated(QString::from(json));
@@ -361,18 +450,28 @@ impl ffi::qobject::AppController {
     }
 
     pub fn add_download(self: Pin<&mut Self>, url: QString, options_json: QString) {
-        let url = url.to_string();
-        if url.trim().is_empty() {
+        // Several pasted links are added as one batch
+        let mut urls: Vec<String> = url
+            .to_string()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        if urls.is_empty() {
             self.error(QString::from("URL cannot be empty"));
             return;
         }
 
         let options = parse_options(&options_json.to_string());
-        let _ = self.with_state(|state| {
-            let _ = state.cmd_sender.send_blocking(EngineCommand::AddDownload {
-                url,
+        let cmd = if urls.len() == 1 {
+            EngineCommand::AddDownload {
+                url: urls.remove(0),
                 options,
-            });
+            }
+        } else {
+            EngineCommand::AddDownloads { urls, options }
+        };
+        let _ = self.with_state(|state| {
+            let _ = state.cmd_sender.send_blocking(cmd);
         });
     }
 
@@ -413,6 +512,63 @@ impl ffi::qobject::AppController {
         });
     }
 
+    pub fn add_metalink(self: Pin<&mut Self>, path: QString, options_json: QString) {
+        let path = normalize_path(&path.to_string());
+        if path.trim().is_empty() {
+            self.error(QString::from("Metalink path is empty"));
+            return;
+        }
+
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                self.error(QString::from(format!("Failed to read metalink: {}", e)));
+                return;
+            }
+        };
+
+        let options = parse_options(&options_json.to_string());
+        let _ = self.with_state(|state| {
+            let _ = state.cmd_sender.send_blocking(EngineCommand::AddMetalink {
+                data,
+                options,
+            });
+        });
+    }
+
+    pub fn resend_add(self: Pin<&mut Self>, command_json: QString, action: QString) {
+        let action = serde_json::Value::String(action.to_string());
+        let (cmd, action) = match (
+            serde_json::from_str::<EngineCommand>(&command_json.to_string()),
+            serde_json::from_value::<DuplicateAction>(action),
+        ) {
+            (Ok(cmd), Ok(action)) => (cmd, action),
+            _ => {
+                self.error(QString::from("Invalid download to add again"));
+                return;
+            }
+        };
+
+        let _ = self.with_state(|state| {
+            let _ = state.cmd_sender.send_blocking(cmd.with_duplicate_action(action));
+        });
+    }
+
+    pub fn probe_url(self: Pin<&mut Self>, url: QString, options_json: QString) {
+        let url = url.to_string().trim().to_string();
+        if url.is_empty() {
+            self.error(QString::from("URL cannot be empty"));
+            return;
+        }
+
+        let options = parse_options(&options_json.to_string());
+        let _ = self.with_state(|state| {
+            let _ = state
+                .cmd_sender
+                .send_blocking(EngineCommand::ProbeUrl { url, options });
+        });
+    }
+
     pub fn pause_download(self: Pin<&mut Self>, gid: QString) {
         let gid = gid.to_string();
         let _ = self.with_state(|state| {
@@ -437,6 +593,47 @@ impl ffi::qobject::AppController {
         });
     }
 
+    pub fn reschedule_download(self: Pin<&mut Self>, gid: QString, start: i64) {
+        let gid = gid.to_string();
+        let _ = self.with_state(|state| {
+            let _ = state
+                .cmd_sender
+                .send_blocking(EngineCommand::Reschedule { gid: gid.clone(), start });
+        });
+    }
+
+    pub fn cancel_scheduled(self: Pin<&mut Self>, gid: QString) {
+        let gid = gid.to_string();
+        let _ = self.with_state(|state| {
+            let _ = state
+                .cmd_sender
+                .send_blocking(EngineCommand::CancelScheduled(gid.clone()));
+        });
+    }
+
+    pub fn verify_checksum(self: Pin<&mut Self>, gid: QString) {
+        let gid = gid.to_string();
+        let _ = self.with_state(|state| {
+            let _ = state
+                .cmd_sender
+                .send_blocking(EngineCommand::VerifyChecksum(gid.clone()));
+        });
+    }
+
+    pub fn get_action_log_json(self: Pin<&mut Self>, gid: QString) -> QString {
+        let gid = gid.to_string();
+        let mut json = String::from("[]");
+        self.with_state(|state| match DownloadsDb::get_action_log(&state.db, &gid) {
+            Ok(entries) => {
+                if let Ok(value) = serde_json::to_string(&entries) {
+                    json = value;
+                }
+            }
+            Err(e) => log::error!("Failed to load action log: {}", e),
+        });
+        QString::from(json)
+    }
+
     pub fn pause_all(self: Pin<&mut Self>) {
         let _ = self.with_state(|state| {
             let _ = state.cmd_sender.send_blocking(EngineCommand::PauseAll);
@@ -524,13 +721,24 @@ impl ffi::qobject::AppController {
                 "proxy_pass" => state.settings.proxy_pass = Some(value.clone()).filter(|s| !s.is_empty()),
                 "min_segment_size" => state.settings.min_segment_size = value.parse().unwrap_or(1024),
                 "bt_preallocation" => state.settings.bt_preallocation = value.clone(),
+                "bandwidth_schedule" => match serde_json::from_str(&value) {
+                    Ok(schedule) => state.settings.bandwidth_schedule = schedule,
+                    Err(e) => log::error!("Invalid bandwidth schedule: {}", e),
+                },
+                "completion_actions" => match serde_json::from_str(&value) {
+                    Ok(actions) => state.settings.completion_actions = actions,
+                    Err(e) => log::error!("Invalid completion actions: {}", e),
+                },
+                "conflict_policy" => match value.parse() {
+                    Ok(policy) => state.settings.conflict_policy = policy,
+                    Err(e) => log::error!("Invalid conflict policy: {}", e),
+                },
                 _ => {}
             }
 
-            let config = settings_to_engine_config(&state.settings);
             let _ = state
                 .cmd_sender
-                .send_blocking(EngineCommand::UpdateConfig(config));
+                .send_blocking(EngineCommand::UpdateSettings(state.settings.clone()));
         });
     }
 
@@ -544,6 +752,230 @@ impl ffi::qobject::AppController {
         QString::from(json)
     }
 
+    pub fn set_bandwidth_profile(self: Pin<&mut Self>, name: QString) {
+        // An empty name goes back to the schedule
+        let name = Some(name.to_string()).filter(|n| !n.is_empty());
+        let _ = self.with_state(|state| {
+            let _ = state
+                .cmd_sender
+                .send_blocking(EngineCommand::SetBandwidthProfile(name.clone()));
+        });
+    }
+
+    pub fn get_categories_json(self: Pin<&mut Self>) -> QString {
+        let mut json = String::from("[]");
+        self.with_state(|state| match CategoriesDb::list(&state.db) {
+            Ok(categories) => {
+                if let Ok(value) = serde_json::to_string(&categories) {
+                    json = value;
+                }
+            }
+            Err(e) => log::error!("Failed to load categories: {}", e),
+        });
+        QString::from(json)
+    }
+
+    pub fn save_category(mut self: Pin<&mut Self>, json: QString) -> bool {
+        let category: Category = match serde_json::from_str(&json.to_string()) {
+            Ok(category) => category,
+            Err(e) => {
+                self.as_mut().error(QString::from(format!("Invalid category: {}", e)));
+                return false;
+            }
+        };
+
+        let mut result = Ok(0);
+        self.as_mut().with_state(|state| {
+            result = CategoriesDb::save(&state.db, &category);
+            if result.is_ok() {
+                let _ = state.cmd_sender.send_blocking(EngineCommand::ReloadCategories);
+            }
+        });
+
+        match result {
+            Ok(_) => true,
+            Err(e) => {
+                self.error(QString::from(e.to_string()));
+                false
+            }
+        }
+    }
+
+    pub fn delete_category(self: Pin<&mut Self>, id: i64) {
+        self.with_state(|state| {
+            if let Err(e) = CategoriesDb::delete(&state.db, id) {
+                log::error!("Failed to delete category: {}", e);
+                return;
+            }
+            let _ = state.cmd_sender.send_blocking(EngineCommand::ReloadCategories);
+        });
+    }
+
+    pub fn get_feeds_json(self: Pin<&mut Self>) -> QString {
+        let mut json = String::from("[]");
+        self.with_state(|state| match FeedsDb::list(&state.db) {
+            Ok(feeds) => {
+                if let Ok(value) = serde_json::to_string(&feeds) {
+                    json = value;
+                }
+            }
+            Err(e) => log::error!("Failed to load feeds: {}", e),
+        });
+        QString::from(json)
+    }
+
+    pub fn save_feed(mut self: Pin<&mut Self>, json: QString) -> bool {
+        let feed: Feed = match serde_json::from_str(&json.to_string()) {
+            Ok(feed) => feed,
+            Err(e) => {
+                self.as_mut().error(QString::from(format!("Invalid feed: {}", e)));
+                return false;
+            }
+        };
+
+        let mut result = Ok(0);
+        self.as_mut().with_state(|state| {
+            result = FeedsDb::save(&state.db, &feed);
+        });
+
+        match result {
+            Ok(_) => true,
+            Err(e) => {
+                self.error(QString::from(e.to_string()));
+                false
+            }
+        }
+    }
+
+    pub fn delete_feed(self: Pin<&mut Self>, id: i64) {
+        self.with_state(|state| {
+            if let Err(e) = FeedsDb::delete(&state.db, id) {
+                log::error!("Failed to delete feed: {}", e);
+            }
+        });
+    }
+
+    pub fn get_watch_folders_json(self: Pin<&mut Self>) -> QString {
+        let mut json = String::from("[]");
+        self.with_state(|state| match WatchFoldersDb::list(&state.db) {
+            Ok(folders) => {
+                if let Ok(value) = serde_json::to_string(&folders) {
+                    json = value;
+                }
+            }
+            Err(e) => log::error!("Failed to load watch folders: {}", e),
+        });
+        QString::from(json)
+    }
+
+    pub fn save_watch_folder(mut self: Pin<&mut Self>, json: QString) -> bool {
+        let folder: WatchFolder = match serde_json::from_str(&json.to_string()) {
+            Ok(folder) => folder,
+            Err(e) => {
+                self.as_mut().error(QString::from(format!("Invalid watch folder: {}", e)));
+                return false;
+            }
+        };
+        let folder = WatchFolder {
+            path: normalize_path(&folder.path),
+            ..folder
+        };
+
+        let mut result = Ok(0);
+        self.as_mut().with_state(|state| {
+            result = WatchFoldersDb::save(&state.db, &folder);
+        });
+
+        match result {
+            Ok(_) => true,
+            Err(e) => {
+                self.error(QString::from(e.to_string()));
+                false
+            }
+        }
+    }
+
+    pub fn delete_watch_folder(self: Pin<&mut Self>, id: i64) {
+        self.with_state(|state| {
+            if let Err(e) = WatchFoldersDb::delete(&state.db, id) {
+                log::error!("Failed to delete watch folder: {}", e);
+            }
+        });
+    }
+
+    pub fn export_bundle(mut self: Pin<&mut Self>, path: QString, redact: bool) -> bool {
+        let path = normalize_path(&path.to_string());
+
+        let mut result = Ok(());
+        self.as_mut().with_state(|state| {
+            result = backup::export(&state.db, redact)
+                .and_then(|bundle| bundle.to_json())
+                .and_then(|json| Ok(std::fs::write(&path, json)?));
+        });
+
+        match result {
+            Ok(()) => {
+                self.toast(QString::from(format!("Exported to {}", path)));
+                true
+            }
+            Err(e) => {
+                self.error(QString::from(format!("Export failed: {}", e)));
+                false
+            }
+        }
+    }
+
+    pub fn import_bundle(mut self: Pin<&mut Self>, path: QString, replace: bool) -> bool {
+        let path = normalize_path(&path.to_string());
+        let bundle = match std::fs::read_to_string(&path) {
+            Ok(json) => Bundle::from_json(&json),
+            Err(e) => Err(e.into()),
+        };
+        let bundle = match bundle {
+            Ok(bundle) => bundle,
+            Err(e) => {
+                self.as_mut().error(QString::from(format!("Import failed: {}", e)));
+                return false;
+            }
+        };
+        let mode = if replace {
+            ImportMode::Replace
+        } else {
+            ImportMode::Merge
+        };
+
+        let mut result = Ok(Default::default());
+        self.as_mut().with_state(|state| {
+            result = backup::import(&state.db, &bundle, mode);
+            if result.is_err() {
+                return;
+            }
+            match SettingsDb::load(&state.db) {
+                Ok(settings) => state.settings = settings,
+                Err(e) => log::error!("Failed to reload settings: {}", e),
+            }
+            let _ = state
+                .cmd_sender
+                .send_blocking(EngineCommand::UpdateSettings(state.settings.clone()));
+            let _ = state.cmd_sender.send_blocking(EngineCommand::ReloadCategories);
+        });
+
+        match result {
+            Ok(summary) => {
+                self.as_mut().load_completed();
+                self.toast(QString::from(format!(
+                    "Imported {} settings, {} downloads, {} categories and {} feeds",
+                    summary.settings, summary.downloads, summary.categories, summary.feeds
+                )));
+                true
+            }
+            Err(e) => {
+                self.error(QString::from(format!("Import failed: {}", e)));
+                false
+            }
+        }
+    }
+
     pub fn get_user_agent_presets_json(self: Pin<&mut Self>) -> QString {
         let presets = get_user_agent_presets();
         let names: Vec<&str> = presets.iter().map(|(name, _)| *name).collect();
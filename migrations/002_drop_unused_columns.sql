@@ -0,0 +1,6 @@
+-- The theme setting was never part of Settings
+DELETE FROM settings WHERE key = 'theme';
+
+-- Tracker health was never tracked
+ALTER TABLE trackers DROP COLUMN last_checked;
+ALTER TABLE trackers DROP COLUMN is_working;
@@ -0,0 +1,7 @@
+-- Options each download was added with, replayed on restore
+CREATE TABLE IF NOT EXISTS download_options (
+    gid TEXT PRIMARY KEY,
+    options TEXT NOT NULL,
+    -- Cookies and credential headers, kept apart and cleared on completion
+    secrets TEXT
+);
@@ -0,0 +1,6 @@
+-- Original .torrent files, so torrents survive a lost engine session
+CREATE TABLE IF NOT EXISTS torrent_blobs (
+    gid TEXT PRIMARY KEY,
+    data BLOB NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
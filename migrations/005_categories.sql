@@ -0,0 +1,12 @@
+-- Download categories with their own save location, defaults and rules
+CREATE TABLE IF NOT EXISTS categories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT UNIQUE NOT NULL,
+    save_dir TEXT NOT NULL,
+    options TEXT NOT NULL DEFAULT '{}',
+    rules TEXT NOT NULL DEFAULT '{}'
+);
+
+ALTER TABLE downloads ADD COLUMN category TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_downloads_category ON downloads(category);
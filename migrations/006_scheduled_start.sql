@@ -0,0 +1,5 @@
+-- Start time of downloads held back by the scheduler
+ALTER TABLE downloads ADD COLUMN scheduled_start INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_downloads_scheduled ON downloads(scheduled_start)
+    WHERE status = 'scheduled';
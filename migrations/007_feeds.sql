@@ -0,0 +1,24 @@
+-- RSS/Atom feed subscriptions and the items already downloaded from them
+CREATE TABLE IF NOT EXISTS feeds (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT UNIQUE NOT NULL,
+    title TEXT NOT NULL DEFAULT '',
+    enabled INTEGER NOT NULL DEFAULT 1,
+    include_pattern TEXT,
+    exclude_pattern TEXT,
+    category TEXT,
+    poll_interval INTEGER NOT NULL DEFAULT 3600,
+    last_checked INTEGER,
+    last_error TEXT
+);
+
+CREATE TABLE IF NOT EXISTS feed_items (
+    feed_id INTEGER NOT NULL,
+    guid TEXT NOT NULL,
+    url TEXT NOT NULL,
+    title TEXT NOT NULL DEFAULT '',
+    added_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (feed_id, guid)
+);
+
+CREATE INDEX IF NOT EXISTS idx_feed_items_url ON feed_items(feed_id, url);
@@ -0,0 +1,2 @@
+-- Outcome of the last checksum verification, as JSON
+ALTER TABLE downloads ADD COLUMN checksum_result TEXT;
@@ -0,0 +1,11 @@
+-- Results of the actions run when a download completes
+CREATE TABLE IF NOT EXISTS action_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    gid TEXT NOT NULL,
+    action TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    message TEXT NOT NULL DEFAULT '',
+    ran_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_action_log_gid ON action_log(gid);
@@ -0,0 +1,9 @@
+-- Directories watched for dropped .torrent, .metalink and URL list files
+CREATE TABLE IF NOT EXISTS watch_folders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT UNIQUE NOT NULL,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    dir TEXT,
+    category TEXT,
+    move_aside INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,2 @@
+-- What the filename conflict policy did when a download's file already existed
+ALTER TABLE downloads ADD COLUMN conflict_outcome TEXT;